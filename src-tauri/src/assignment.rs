@@ -0,0 +1,948 @@
+use serde::{Deserialize, Serialize};
+
+/// Maximum number of refinement sweeps over the grid in global mode.
+const REFINE_MAX_PASSES: usize = 8;
+/// Cells holding a candidate tile that are considered as swap partners per
+/// pass. Each pass starts further along the holders, so large groups are
+/// covered over several passes.
+const SWAP_PARTNER_LIMIT: usize = 32;
+/// Minimum objective improvement for a move to be applied.
+const IMPROVEMENT_EPSILON: f64 = 1e-9;
+/// Nearest tiles tried for a cell whose candidates all break a rule, as a
+/// multiple of its candidate count, before the rule is relaxed.
+const WIDEN_FACTOR: usize = 4;
+
+/// Strategy used to assign tiles to grid cells.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AssignmentMode {
+    /// Row-major greedy matching; early cells get first pick.
+    #[default]
+    Greedy,
+    /// Whole-grid optimization via iterative move and swap refinement.
+    Global,
+}
+
+/// A placement constraint that could not be fully honored.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum UnmetConstraint {
+    /// Some cells had to use a tile beyond the per-tile usage cap.
+    UsageCapExceeded { cap: usize, cells: usize },
+    /// Some library tiles could not be placed at least once.
+    TilesUnused { unused: usize, cells: usize },
+    /// Some cells had to repeat a tile within the exclusion radius.
+    RepeatDistanceRelaxed { cells: usize },
+}
+
+/// Result of a tile assignment.
+#[derive(Debug, Clone)]
+pub(crate) struct AssignmentOutcome {
+    /// Tile index per cell, in cell order.
+    pub tiles: Vec<usize>,
+    pub unmet: Vec<UnmetConstraint>,
+}
+
+/// Cells in which a tile may not repeat.
+enum Exclusion {
+    /// Row-major grid width and the Chebyshev radius around each cell.
+    Grid { columns: usize, radius: usize },
+    /// Explicit neighbor lists, for layouts whose cells differ in size.
+    Neighbors(Vec<Vec<usize>>),
+}
+
+/// Which placement rules a candidate has to satisfy.
+#[derive(Clone, Copy, PartialEq, Eq)]
+struct Rules {
+    exclusion: bool,
+    cap: bool,
+}
+
+impl Rules {
+    const NONE: Rules = Rules {
+        exclusion: false,
+        cap: false,
+    };
+}
+
+/// Mutable assignment with the indexes needed for constant-time rule checks.
+struct AssignmentState {
+    tiles: Vec<usize>,
+    /// Mirror of `tiles` for exclusion checks.
+    placed: Vec<Option<usize>>,
+    costs: Vec<f64>,
+    cells_by_tile: Vec<Vec<usize>>,
+}
+
+impl AssignmentState {
+    fn move_cell(&mut self, cell: usize, tile: usize, cost: f64) {
+        let current = self.tiles[cell];
+        self.cells_by_tile[current].retain(|&c| c != cell);
+        self.cells_by_tile[tile].push(cell);
+        self.tiles[cell] = tile;
+        self.placed[cell] = Some(tile);
+        self.costs[cell] = cost;
+    }
+
+    fn swap_cells(&mut self, a: usize, b: usize, cost_a: f64, cost_b: f64) {
+        let (tile_a, tile_b) = (self.tiles[a], self.tiles[b]);
+        replace_cell(&mut self.cells_by_tile[tile_b], b, a);
+        replace_cell(&mut self.cells_by_tile[tile_a], a, b);
+        self.tiles[a] = tile_b;
+        self.tiles[b] = tile_a;
+        self.placed[a] = Some(tile_b);
+        self.placed[b] = Some(tile_a);
+        self.costs[a] = cost_a;
+        self.costs[b] = cost_b;
+    }
+}
+
+/// Tile assignment over precomputed per-cell candidate lists.
+///
+/// `cost(cell, tile)` returns the match cost without usage penalty. Each use of
+/// a tile beyond the first costs `penalty` more than the previous one, so the
+/// greedy and global modes minimize the same objective.
+pub(crate) struct AssignmentSolver<'a, F: Fn(usize, usize) -> f64> {
+    candidates: &'a [Vec<(usize, f64)>],
+    tile_count: usize,
+    penalty: f64,
+    cost: F,
+    exclusion: Option<Exclusion>,
+    /// Hard maximum number of cells per tile.
+    usage_cap: Option<usize>,
+    /// Place every tile at least once when there are enough cells.
+    use_every_tile: bool,
+    /// Cells each tile may be moved into when covering unused tiles, besides
+    /// the cells that list it as a candidate.
+    cover_cells: Vec<Vec<usize>>,
+    /// The `k` tiles nearest to a cell, nearest first.
+    nearest: Option<Box<NearestTiles<'a>>>,
+}
+
+/// Looks up the `k` tiles nearest to a cell, as `nearest(cell, k)`.
+type NearestTiles<'a> = dyn Fn(usize, usize) -> Vec<usize> + 'a;
+
+impl<'a, F: Fn(usize, usize) -> f64> AssignmentSolver<'a, F> {
+    #[must_use]
+    pub fn new(
+        candidates: &'a [Vec<(usize, f64)>],
+        tile_count: usize,
+        penalty: f64,
+        cost: F,
+    ) -> Self {
+        Self {
+            candidates,
+            tile_count,
+            penalty,
+            cost,
+            exclusion: None,
+            usage_cap: None,
+            use_every_tile: false,
+            cover_cells: Vec::new(),
+            nearest: None,
+        }
+    }
+
+    /// Forbids a tile from reappearing within `radius` cells of itself.
+    /// Cells are laid out row-major with `columns` cells per row.
+    #[must_use]
+    pub fn with_exclusion(mut self, columns: usize, radius: usize) -> Self {
+        if radius > 0 && columns > 0 {
+            self.exclusion = Some(Exclusion::Grid { columns, radius });
+        }
+        self
+    }
+
+    /// Forbids a tile from reappearing in any of `neighbors[cell]`.
+    #[must_use]
+    pub fn with_neighbor_exclusion(mut self, neighbors: Vec<Vec<usize>>) -> Self {
+        if neighbors.iter().any(|near| !near.is_empty()) {
+            self.exclusion = Some(Exclusion::Neighbors(neighbors));
+        }
+        self
+    }
+
+    /// Limits how many cells may use the same tile. 0 means unlimited.
+    #[must_use]
+    pub fn with_usage_cap(mut self, cap: usize) -> Self {
+        self.usage_cap = (cap > 0).then_some(cap);
+        self
+    }
+
+    /// Requires every tile to be used at least once when the grid allows it.
+    #[must_use]
+    pub fn with_every_tile_used(mut self, enabled: bool) -> Self {
+        self.use_every_tile = enabled;
+        self
+    }
+
+    /// Adds cells to try per tile when placing unused tiles, such as the
+    /// cells closest to each tile. They are tried after the candidate cells,
+    /// so only tiles that fit neither make coverage scan the whole grid.
+    #[must_use]
+    pub fn with_cover_cells(mut self, cells: Vec<Vec<usize>>) -> Self {
+        self.cover_cells = cells;
+        self
+    }
+
+    /// Adds a lookup of the tiles nearest to a cell, tried when none of its
+    /// candidates satisfies the rules. Without it a rule is relaxed as soon
+    /// as a cell's candidates all break it.
+    #[must_use]
+    pub fn with_nearest_tiles(mut self, nearest: impl Fn(usize, usize) -> Vec<usize> + 'a) -> Self {
+        self.nearest = Some(Box::new(nearest));
+        self
+    }
+
+    /// Assigns a tile index to every cell using the given mode.
+    #[must_use]
+    pub fn solve(&self, mode: AssignmentMode) -> AssignmentOutcome {
+        let mut unmet = Vec::new();
+        let constrained = self.exclusion.is_some() || self.usage_cap.is_some();
+
+        // Greedy honors the hard rules as it goes, so it is also the valid
+        // starting point for global refinement when rules are active
+        let (start, relaxed) = match mode {
+            AssignmentMode::Global if !constrained => (self.nearest(), Relaxed::default()),
+            _ => self.greedy(),
+        };
+        if relaxed.over_cap > 0 {
+            unmet.push(UnmetConstraint::UsageCapExceeded {
+                cap: self.usage_cap.unwrap_or(0),
+                cells: relaxed.over_cap,
+            });
+        }
+        if relaxed.exclusion > 0 {
+            unmet.push(UnmetConstraint::RepeatDistanceRelaxed {
+                cells: relaxed.exclusion,
+            });
+        }
+
+        let mut state = self.build_state(start);
+
+        if self.use_every_tile {
+            let unused = if self.candidates.len() >= self.tile_count {
+                self.cover_unused(&mut state)
+            } else {
+                state.cells_by_tile.iter().filter(|c| c.is_empty()).count()
+            };
+            if unused > 0 {
+                unmet.push(UnmetConstraint::TilesUnused {
+                    unused,
+                    cells: self.candidates.len(),
+                });
+            }
+        }
+
+        if mode == AssignmentMode::Global {
+            self.refine(&mut state);
+        }
+
+        AssignmentOutcome {
+            tiles: state.tiles,
+            unmet,
+        }
+    }
+
+    /// Picks the lowest-cost candidate for every cell, ignoring usage.
+    fn nearest(&self) -> Vec<usize> {
+        self.candidates
+            .iter()
+            .map(|candidates| {
+                candidates
+                    .iter()
+                    .fold((0, f64::MAX), |best, &(idx, cost)| {
+                        if cost < best.1 {
+                            (idx, cost)
+                        } else {
+                            best
+                        }
+                    })
+                    .0
+            })
+            .collect()
+    }
+
+    fn build_state(&self, tiles: Vec<usize>) -> AssignmentState {
+        let costs = tiles
+            .iter()
+            .enumerate()
+            .map(|(cell, &tile)| (self.cost)(cell, tile))
+            .collect();
+        let mut cells_by_tile = vec![Vec::new(); self.tile_count];
+        for (cell, &tile) in tiles.iter().enumerate() {
+            cells_by_tile[tile].push(cell);
+        }
+        AssignmentState {
+            placed: tiles.iter().copied().map(Some).collect(),
+            tiles,
+            costs,
+            cells_by_tile,
+        }
+    }
+
+    /// Assigns cells in order, penalizing tiles by their usage so far.
+    fn greedy(&self) -> (Vec<usize>, Relaxed) {
+        let mut usage_counts = vec![0usize; self.tile_count];
+        let mut assigned: Vec<Option<usize>> = vec![None; self.candidates.len()];
+        let mut relaxed = Relaxed::default();
+
+        let levels = self.relaxation_levels();
+
+        for (cell, candidates) in self.candidates.iter().enumerate() {
+            let mut choice = None;
+            for &(rules, widen) in &levels {
+                let allowed = |idx: usize| self.allowed(&assigned, &usage_counts, cell, idx, rules);
+                // Costs of wider tiles are only looked up once they pass the rules
+                let best = match widen {
+                    true => self.lowest_score(
+                        &usage_counts,
+                        self.wider_tiles(cell, candidates, rules, allowed)
+                            .into_iter()
+                            .map(|idx| (idx, (self.cost)(cell, idx))),
+                    ),
+                    false => self.lowest_score(
+                        &usage_counts,
+                        candidates.iter().copied().filter(|&(idx, _)| allowed(idx)),
+                    ),
+                };
+                if let Some(idx) = best {
+                    choice = Some((idx, rules));
+                    break;
+                }
+            }
+
+            let (best_idx, rules) = choice.unwrap_or((0, Rules::NONE));
+            if !rules.exclusion && self.conflicts(&assigned, cell, best_idx, None) {
+                relaxed.exclusion += 1;
+            }
+            if !rules.cap
+                && self
+                    .usage_cap
+                    .is_some_and(|cap| usage_counts[best_idx] >= cap)
+            {
+                relaxed.over_cap += 1;
+            }
+
+            usage_counts[best_idx] += 1;
+            assigned[cell] = Some(best_idx);
+        }
+
+        let tiles = assigned.into_iter().map(|idx| idx.unwrap_or(0)).collect();
+        (tiles, relaxed)
+    }
+
+    /// The tile with the lowest cost plus usage penalty among `options`, the
+    /// first one on ties.
+    fn lowest_score(
+        &self,
+        usage_counts: &[usize],
+        options: impl Iterator<Item = (usize, f64)>,
+    ) -> Option<usize> {
+        options
+            .map(|(idx, cost)| (idx, cost + usage_counts[idx] as f64 * self.penalty))
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(idx, _)| idx)
+    }
+
+    /// Allowed tiles beyond a cell's candidates to try before a rule is
+    /// relaxed, from the nearest `WIDEN_FACTOR` times as many tiles as it has
+    /// candidates. Once only the usage cap is active the lookup keeps doubling
+    /// until it finds a tile under the cap or covers the library; at most
+    /// cells / cap tiles can be at it, so that stays far from a full scan.
+    fn wider_tiles(
+        &self,
+        cell: usize,
+        candidates: &[(usize, f64)],
+        rules: Rules,
+        allowed: impl Fn(usize) -> bool,
+    ) -> Vec<usize> {
+        let Some(ref nearest) = self.nearest else {
+            return Vec::new();
+        };
+        let grow = rules.cap && !rules.exclusion;
+        let mut k = WIDEN_FACTOR * candidates.len().max(1);
+        loop {
+            let tiles: Vec<usize> = nearest(cell, k)
+                .into_iter()
+                .filter(|&idx| !candidates.iter().any(|&(candidate, _)| candidate == idx))
+                .filter(|&idx| allowed(idx))
+                .collect();
+            if !tiles.is_empty() || !grow || k >= self.tile_count {
+                return tiles;
+            }
+            k *= 2;
+        }
+    }
+
+    /// Rule sets a cell is tried with, each first on its candidates and then,
+    /// while a rule is active, on the nearest tiles beyond them. The exclusion
+    /// radius is dropped first and the usage cap only after it.
+    fn relaxation_levels(&self) -> Vec<(Rules, bool)> {
+        let mut rules = Rules {
+            exclusion: self.exclusion.is_some(),
+            cap: self.usage_cap.is_some(),
+        };
+        let mut levels = vec![(rules, false)];
+        while rules != Rules::NONE {
+            levels.push((rules, true));
+            if rules.exclusion {
+                rules.exclusion = false;
+            } else {
+                rules.cap = false;
+            }
+            levels.push((rules, false));
+        }
+        levels
+    }
+
+    fn allowed(
+        &self,
+        assigned: &[Option<usize>],
+        usage_counts: &[usize],
+        cell: usize,
+        tile: usize,
+        rules: Rules,
+    ) -> bool {
+        let under_cap = !rules.cap || self.usage_cap.is_none_or(|cap| usage_counts[tile] < cap);
+        under_cap && !(rules.exclusion && self.conflicts(assigned, cell, tile, None))
+    }
+
+    /// Returns true if placing `tile` in `cell` would repeat it within the
+    /// exclusion radius. `ignore` skips a cell that is about to change.
+    fn conflicts(
+        &self,
+        assigned: &[Option<usize>],
+        cell: usize,
+        tile: usize,
+        ignore: Option<usize>,
+    ) -> bool {
+        let repeats = |neighbor: usize| {
+            neighbor != cell
+                && Some(neighbor) != ignore
+                && assigned.get(neighbor).copied().flatten() == Some(tile)
+        };
+        let (columns, radius) = match self.exclusion {
+            None => return false,
+            Some(Exclusion::Neighbors(ref neighbors)) => {
+                return neighbors[cell].iter().any(|&neighbor| repeats(neighbor));
+            }
+            Some(Exclusion::Grid { columns, radius }) => (columns, radius),
+        };
+        let rows = assigned.len().div_ceil(columns);
+        let (row, col) = (cell / columns, cell % columns);
+
+        let row_range = row.saturating_sub(radius)..=(row + radius).min(rows - 1);
+        row_range.into_iter().any(|r| {
+            let col_range = col.saturating_sub(radius)..=(col + radius).min(columns - 1);
+            col_range.into_iter().any(|c| repeats(r * columns + c))
+        })
+    }
+
+    /// Places each unused tile in the cell where it costs least, taking cells
+    /// from tiles that are used more than once. The cells that list the tile
+    /// as candidate and its cover cells are tried first, so the work grows
+    /// with the number of tiles rather than tiles times cells; only tiles
+    /// that fit none of them fall back to scanning the whole grid. Returns
+    /// the tiles left unused.
+    fn cover_unused(&self, state: &mut AssignmentState) -> usize {
+        // Cells that list a tile as candidate are tried before its cover cells
+        let mut candidate_cells = vec![Vec::new(); self.tile_count];
+        for (cell, candidates) in self.candidates.iter().enumerate() {
+            for &(tile, _) in candidates {
+                candidate_cells[tile].push(cell);
+            }
+        }
+        let no_cells = Vec::new();
+
+        let mut unused = 0;
+        for (tile, cells) in candidate_cells.iter().enumerate() {
+            if !state.cells_by_tile[tile].is_empty() {
+                continue;
+            }
+
+            let best_cell = |cells: &mut dyn Iterator<Item = usize>| {
+                let mut best: Option<(usize, f64, f64)> = None;
+                for cell in cells {
+                    if state.cells_by_tile[state.tiles[cell]].len() < 2
+                        || self.conflicts(&state.placed, cell, tile, None)
+                    {
+                        continue;
+                    }
+                    let cost = (self.cost)(cell, tile);
+                    let delta = cost - state.costs[cell];
+                    if best.is_none_or(|(_, _, d)| delta < d) {
+                        best = Some((cell, cost, delta));
+                    }
+                }
+                best
+            };
+
+            let cover = self.cover_cells.get(tile).unwrap_or(&no_cells);
+            match best_cell(&mut cells.iter().copied())
+                .or_else(|| best_cell(&mut cover.iter().copied()))
+                .or_else(|| best_cell(&mut (0..state.tiles.len())))
+            {
+                Some((cell, cost, _)) => state.move_cell(cell, tile, cost),
+                None => unused += 1,
+            }
+        }
+        unused
+    }
+
+    /// Repeatedly applies the single-cell move or pairwise swap that most
+    /// lowers the total objective, never breaking an active rule. Only
+    /// candidate costs are compared, so refinement never calls `cost`: a swap
+    /// is considered when each cell's new tile is among its candidates.
+    fn refine(&self, state: &mut AssignmentState) {
+        // Candidates ordered by tile, to look up a partner's cost for a swap
+        let by_tile: Vec<Vec<(usize, f64)>> = self
+            .candidates
+            .iter()
+            .map(|candidates| {
+                let mut sorted = candidates.clone();
+                sorted.sort_unstable_by_key(|&(tile, _)| tile);
+                sorted
+            })
+            .collect();
+        let candidate_cost = |cell: usize, tile: usize| {
+            let sorted = &by_tile[cell];
+            sorted
+                .binary_search_by_key(&tile, |&(idx, _)| idx)
+                .ok()
+                .map(|i| sorted[i].1)
+        };
+
+        for pass in 0..REFINE_MAX_PASSES {
+            let mut improved = false;
+
+            for cell in 0..state.tiles.len() {
+                let current = state.tiles[cell];
+                let current_cost = state.costs[cell];
+                let current_users = state.cells_by_tile[current].len();
+                // Marginal penalty released by removing this cell from its tile
+                let released = (current_users - 1) as f64 * self.penalty;
+                let may_leave = !(self.use_every_tile && current_users == 1);
+
+                let mut best_move: Option<(usize, f64, f64)> = None;
+                for &(tile, cost) in &self.candidates[cell] {
+                    if tile == current || !may_leave {
+                        continue;
+                    }
+                    let users = state.cells_by_tile[tile].len();
+                    let delta = cost - current_cost + users as f64 * self.penalty - released;
+                    if delta < -IMPROVEMENT_EPSILON
+                        && best_move.is_none_or(|(_, _, d)| delta < d)
+                        && self.usage_cap.is_none_or(|cap| users < cap)
+                        && !self.conflicts(&state.placed, cell, tile, None)
+                    {
+                        best_move = Some((tile, cost, delta));
+                    }
+                }
+
+                // Swaps keep usage unchanged, so only color/structure costs
+                // matter. A swap may make this cell worse if its partner gains more.
+                let mut best_swap: Option<(usize, f64, f64, f64)> = None;
+                for &(tile, cost) in &self.candidates[cell] {
+                    if tile == current {
+                        continue;
+                    }
+                    let holders = &state.cells_by_tile[tile];
+                    let start = (pass * SWAP_PARTNER_LIMIT) % holders.len().max(1);
+                    let partners = holders[start..].iter().chain(&holders[..start]);
+                    for &other in partners.take(SWAP_PARTNER_LIMIT) {
+                        let Some(other_cost) = candidate_cost(other, current) else {
+                            continue;
+                        };
+                        let delta = cost + other_cost - current_cost - state.costs[other];
+                        if delta < -IMPROVEMENT_EPSILON
+                            && best_swap.is_none_or(|(_, _, _, d)| delta < d)
+                            && !self.conflicts(&state.placed, cell, tile, Some(other))
+                            && !self.conflicts(&state.placed, other, current, Some(cell))
+                        {
+                            best_swap = Some((other, cost, other_cost, delta));
+                        }
+                    }
+                }
+
+                let move_delta = best_move.map_or(0.0, |(_, _, d)| d);
+                let swap_delta = best_swap.map_or(0.0, |(_, _, _, d)| d);
+
+                if let Some((other, cost, other_cost, _)) =
+                    best_swap.filter(|_| swap_delta < move_delta)
+                {
+                    state.swap_cells(cell, other, cost, other_cost);
+                    improved = true;
+                } else if let Some((tile, cost, _)) = best_move {
+                    state.move_cell(cell, tile, cost);
+                    improved = true;
+                }
+            }
+
+            if !improved {
+                break;
+            }
+        }
+    }
+}
+
+/// Number of cells where greedy assignment had to relax a rule.
+#[derive(Default)]
+struct Relaxed {
+    exclusion: usize,
+    over_cap: usize,
+}
+
+fn replace_cell(cells: &mut [usize], from: usize, to: usize) {
+    if let Some(slot) = cells.iter_mut().find(|c| **c == from) {
+        *slot = to;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // costs[cell][tile], with every tile a candidate for every cell
+    fn candidates_from(costs: &[Vec<f64>]) -> Vec<Vec<(usize, f64)>> {
+        costs
+            .iter()
+            .map(|row| row.iter().copied().enumerate().collect())
+            .collect()
+    }
+
+    #[test]
+    fn greedy_gives_early_cells_first_pick() {
+        let costs = vec![vec![10.0, 12.0], vec![10.0, 100.0]];
+        let candidates = candidates_from(&costs);
+        let solver = AssignmentSolver::new(&candidates, 2, 1000.0, |c, t| costs[c][t]);
+
+        assert_eq!(solver.solve(AssignmentMode::Greedy).tiles, vec![0, 1]);
+    }
+
+    #[test]
+    fn global_assigns_tiles_where_they_matter_most() {
+        let costs = vec![vec![10.0, 12.0], vec![10.0, 100.0]];
+        let candidates = candidates_from(&costs);
+        let solver = AssignmentSolver::new(&candidates, 2, 1000.0, |c, t| costs[c][t]);
+
+        assert_eq!(solver.solve(AssignmentMode::Global).tiles, vec![1, 0]);
+    }
+
+    #[test]
+    fn global_swaps_tiles_between_cells() {
+        // Moves alone settle on 107; swapping the first and last cell reaches 103
+        let costs = vec![
+            vec![0.0, 5.0, 100.0],
+            vec![1.0, 50.0, 100.0],
+            vec![2.0, 3.0, 100.0],
+        ];
+        let candidates = candidates_from(&costs);
+        let solver = AssignmentSolver::new(&candidates, 3, 1e6, |c, t| costs[c][t]);
+
+        assert_eq!(solver.solve(AssignmentMode::Global).tiles, vec![0, 2, 1]);
+    }
+
+    #[test]
+    fn global_swaps_even_when_one_cell_gets_worse() {
+        // Greedy under the cap gives cell 0 its perfect tile and leaves cell 1
+        // with 100; the only improvement costs cell 0 a little
+        let costs = vec![vec![0.0, 1.0], vec![2.0, 100.0]];
+        let candidates = candidates_from(&costs);
+        let solver =
+            AssignmentSolver::new(&candidates, 2, 0.0, |c, t| costs[c][t]).with_usage_cap(1);
+
+        assert_eq!(solver.solve(AssignmentMode::Greedy).tiles, vec![0, 1]);
+        assert_eq!(solver.solve(AssignmentMode::Global).tiles, vec![1, 0]);
+    }
+
+    #[test]
+    fn global_refinement_only_prices_candidates() {
+        // A 100x100 grid whose cells share overlapping candidate lists
+        let (cells, tile_count, per_cell) = (100 * 100, 500, 20);
+        let cost_of = |c: usize, t: usize| ((c * 31 + t * 17) % 97) as f64;
+        let candidates: Vec<Vec<(usize, f64)>> = (0..cells)
+            .map(|c| {
+                (0..per_cell)
+                    .map(|j| {
+                        let t = (c / 4 + j * 13) % tile_count;
+                        (t, cost_of(c, t))
+                    })
+                    .collect()
+            })
+            .collect();
+        let calls = std::cell::Cell::new(0usize);
+        let cost = |c: usize, t: usize| {
+            calls.set(calls.get() + 1);
+            cost_of(c, t)
+        };
+        let solver = AssignmentSolver::new(&candidates, tile_count, 5.0, cost);
+
+        let tiles = solver.solve(AssignmentMode::Global).tiles;
+        assert_eq!(tiles.len(), cells);
+        // One lookup per cell to build the state; moves and swaps reuse candidate costs
+        assert!(calls.get() <= cells, "{} cost calls", calls.get());
+    }
+
+    fn has_adjacent_repeat(assignment: &[usize], columns: usize) -> bool {
+        (0..assignment.len()).any(|a| {
+            (a + 1..assignment.len()).any(|b| {
+                let row_gap = (a / columns).abs_diff(b / columns);
+                let col_gap = (a % columns).abs_diff(b % columns);
+                row_gap <= 1 && col_gap <= 1 && assignment[a] == assignment[b]
+            })
+        })
+    }
+
+    #[test]
+    fn exclusion_prevents_adjacent_repeats() {
+        // Tile 0 is the best match everywhere and reuse is free
+        let costs: Vec<Vec<f64>> = (0..16).map(|_| vec![0.0, 5.0, 6.0, 7.0, 8.0]).collect();
+        let candidates = candidates_from(&costs);
+
+        for mode in [AssignmentMode::Greedy, AssignmentMode::Global] {
+            let solver =
+                AssignmentSolver::new(&candidates, 5, 0.0, |c, t| costs[c][t]).with_exclusion(4, 1);
+            let assignment = solver.solve(mode).tiles;
+            assert!(!has_adjacent_repeat(&assignment, 4), "{:?}", assignment);
+        }
+    }
+
+    #[test]
+    fn exclusion_falls_back_to_tiles_outside_candidates() {
+        let costs = [[0.0, 50.0], [0.0, 50.0]];
+        // Only tile 0 is a candidate, so the second cell must widen the search
+        let candidates = vec![vec![(0, 0.0)], vec![(0, 0.0)]];
+        let solver = AssignmentSolver::new(&candidates, 2, 0.0, |c, t| costs[c][t])
+            .with_exclusion(2, 1)
+            .with_nearest_tiles(|_, k| (0..k.min(2)).collect());
+
+        assert_eq!(solver.solve(AssignmentMode::Greedy).tiles, vec![0, 1]);
+    }
+
+    #[test]
+    fn exclusion_widens_to_a_bounded_number_of_nearest_tiles() {
+        // Every cell prefers tiles 0 and 1 of a large library, and the radius
+        // forbids both in every third cell
+        let tile_count = 10_000;
+        let candidates: Vec<Vec<(usize, f64)>> = (0..6).map(|_| vec![(0, 0.0), (1, 1.0)]).collect();
+        let calls = std::cell::RefCell::new(vec![0; 6]);
+        let cost = |c: usize, t: usize| {
+            calls.borrow_mut()[c] += 1;
+            t as f64
+        };
+        let solver = AssignmentSolver::new(&candidates, tile_count, 0.0, cost)
+            .with_exclusion(6, 2)
+            .with_nearest_tiles(|_, k| (0..k.min(tile_count)).collect());
+
+        let outcome = solver.solve(AssignmentMode::Greedy);
+        assert_eq!(outcome.tiles, vec![0, 1, 2, 0, 1, 2]);
+        assert!(outcome.unmet.is_empty());
+        // The widened tiles and one lookup per cell to build the state
+        let bound = WIDEN_FACTOR * 2 + 1;
+        assert!(calls.borrow().iter().all(|&n| n <= bound), "{:?}", calls);
+
+        // The radius is only relaxed once the nearest tiles are exhausted
+        let solver = AssignmentSolver::new(&candidates, tile_count, 0.0, |_, t| t as f64)
+            .with_exclusion(6, 2)
+            .with_nearest_tiles(|_, _| vec![0, 1]);
+        assert_eq!(
+            solver.solve(AssignmentMode::Greedy).unmet,
+            vec![UnmetConstraint::RepeatDistanceRelaxed { cells: 3 }]
+        );
+    }
+
+    #[test]
+    fn neighbor_exclusion_only_separates_listed_cells() {
+        let costs: Vec<Vec<f64>> = (0..3).map(|_| vec![0.0, 5.0]).collect();
+        let candidates = candidates_from(&costs);
+        // Cells 0 and 1 touch, cell 2 is far from both
+        let neighbors = vec![vec![1], vec![0], vec![]];
+        let solver = AssignmentSolver::new(&candidates, 2, 0.0, |c, t| costs[c][t])
+            .with_neighbor_exclusion(neighbors);
+
+        let outcome = solver.solve(AssignmentMode::Greedy);
+        assert_eq!(outcome.tiles, vec![0, 1, 0]);
+        assert!(outcome.unmet.is_empty());
+    }
+
+    #[test]
+    fn usage_cap_is_never_exceeded_when_capacity_allows() {
+        let costs: Vec<Vec<f64>> = (0..6).map(|_| vec![0.0, 5.0, 9.0]).collect();
+        let candidates = candidates_from(&costs);
+
+        for mode in [AssignmentMode::Greedy, AssignmentMode::Global] {
+            let solver =
+                AssignmentSolver::new(&candidates, 3, 0.0, |c, t| costs[c][t]).with_usage_cap(2);
+            let outcome = solver.solve(mode);
+            for tile in 0..3 {
+                assert_eq!(outcome.tiles.iter().filter(|&&t| t == tile).count(), 2);
+            }
+            assert!(outcome.unmet.is_empty());
+        }
+    }
+
+    #[test]
+    fn usage_cap_widens_the_nearest_tiles_past_capped_ones() {
+        // Tiles fill up three cells at a time, far beyond the first lookup
+        let tile_count = 10_000;
+        let candidates: Vec<Vec<(usize, f64)>> =
+            (0..30).map(|_| vec![(0, 0.0), (1, 1.0)]).collect();
+        let calls = std::cell::RefCell::new(vec![0; 30]);
+        let cost = |c: usize, t: usize| {
+            calls.borrow_mut()[c] += 1;
+            t as f64
+        };
+        let solver = AssignmentSolver::new(&candidates, tile_count, 0.0, cost)
+            .with_usage_cap(3)
+            .with_nearest_tiles(|_, k| (0..k.min(tile_count)).collect());
+
+        let outcome = solver.solve(AssignmentMode::Greedy);
+        assert_eq!(outcome.tiles, (0..30).map(|c| c / 3).collect::<Vec<_>>());
+        assert!(outcome.unmet.is_empty());
+        // Capped tiles are skipped without a cost lookup, so a cell only
+        // prices the tiles under the cap in its last, doubled lookup
+        let bound = 2 * WIDEN_FACTOR * 2 + 1;
+        assert!(calls.borrow().iter().all(|&n| n <= bound), "{:?}", calls);
+    }
+
+    #[test]
+    fn usage_cap_reports_insufficient_capacity() {
+        let costs: Vec<Vec<f64>> = (0..5).map(|_| vec![0.0, 5.0]).collect();
+        let candidates = candidates_from(&costs);
+        let solver =
+            AssignmentSolver::new(&candidates, 2, 0.0, |c, t| costs[c][t]).with_usage_cap(2);
+
+        assert_eq!(
+            solver.solve(AssignmentMode::Greedy).unmet,
+            vec![UnmetConstraint::UsageCapExceeded { cap: 2, cells: 1 }]
+        );
+    }
+
+    #[test]
+    fn every_tile_is_used_when_grid_is_large_enough() {
+        let costs: Vec<Vec<f64>> = (0..4).map(|_| vec![0.0, 50.0, 60.0]).collect();
+        // Tile 2 is never a candidate, so coverage must fall back to its cover cells
+        let candidates: Vec<Vec<(usize, f64)>> =
+            (0..4).map(|_| vec![(0, 0.0), (1, 50.0)]).collect();
+        let cover_cells = vec![vec![], vec![], vec![2, 3]];
+
+        for mode in [AssignmentMode::Greedy, AssignmentMode::Global] {
+            let solver = AssignmentSolver::new(&candidates, 3, 0.0, |c, t| costs[c][t])
+                .with_every_tile_used(true)
+                .with_cover_cells(cover_cells.clone());
+            let outcome = solver.solve(mode);
+            for tile in 0..3 {
+                assert!(outcome.tiles.contains(&tile), "{:?}", outcome.tiles);
+            }
+            assert!(outcome.unmet.is_empty());
+        }
+    }
+
+    #[test]
+    fn coverage_only_tries_candidate_and_cover_cells_when_they_fit() {
+        let costs: Vec<Vec<f64>> = (0..4).map(|_| vec![0.0, 60.0]).collect();
+        let candidates: Vec<Vec<(usize, f64)>> = (0..4).map(|_| vec![(0, 0.0)]).collect();
+        let calls = std::cell::Cell::new(0);
+        let solver = AssignmentSolver::new(&candidates, 2, 0.0, |c, t| {
+            calls.set(calls.get() + 1);
+            costs[c][t]
+        })
+        .with_every_tile_used(true)
+        .with_cover_cells(vec![vec![], vec![3]]);
+
+        let outcome = solver.solve(AssignmentMode::Greedy);
+        assert_eq!(outcome.tiles, vec![0, 0, 0, 1]);
+        assert!(outcome.unmet.is_empty());
+        // One cost per cell to build the state and one for the cover cell
+        assert_eq!(calls.get(), 5);
+    }
+
+    #[test]
+    fn coverage_scans_the_grid_when_nearby_cells_are_taken() {
+        // Cells 0-3 each hold a tile used only there; cells 4 and 5 share tile 0
+        let own_tile = |c: usize| if c < 4 { c } else { 0 };
+        let candidates: Vec<Vec<(usize, f64)>> = (0..6).map(|c| vec![(own_tile(c), 0.0)]).collect();
+        // Tile 4 is nobody's candidate, and its nearest cells are single-use
+        let cost = |c: usize, t: usize| match (c, t) {
+            (5, 4) => 10.0,
+            (_, 4) => 50.0,
+            (c, t) if t == own_tile(c) => 0.0,
+            _ => 100.0,
+        };
+        let cover_cells = vec![vec![], vec![], vec![], vec![], vec![1, 2, 3]];
+
+        for mode in [AssignmentMode::Greedy, AssignmentMode::Global] {
+            let solver = AssignmentSolver::new(&candidates, 5, 0.0, cost)
+                .with_every_tile_used(true)
+                .with_cover_cells(cover_cells.clone());
+            let outcome = solver.solve(mode);
+            assert_eq!(outcome.tiles, vec![0, 1, 2, 3, 0, 4]);
+            assert!(outcome.unmet.is_empty());
+        }
+    }
+
+    #[test]
+    fn relaxation_levels_follow_the_active_rules() {
+        let candidates: Vec<Vec<(usize, f64)>> = Vec::new();
+        let levels = |solver: AssignmentSolver<'_, fn(usize, usize) -> f64>| {
+            solver
+                .relaxation_levels()
+                .into_iter()
+                .map(|(rules, widen)| (rules.exclusion, rules.cap, widen))
+                .collect::<Vec<_>>()
+        };
+        let solver =
+            || AssignmentSolver::new(&candidates, 2, 0.0, (|_, _| 0.0) as fn(usize, usize) -> f64);
+
+        assert_eq!(levels(solver()), [(false, false, false)]);
+        assert_eq!(
+            levels(solver().with_usage_cap(1)),
+            [
+                (false, true, false),
+                (false, true, true),
+                (false, false, false)
+            ]
+        );
+        assert_eq!(
+            levels(solver().with_exclusion(2, 1).with_usage_cap(1)),
+            [
+                (true, true, false),
+                (true, true, true),
+                (false, true, false),
+                (false, true, true),
+                (false, false, false)
+            ]
+        );
+    }
+
+    #[test]
+    fn every_tile_reports_when_grid_is_too_small() {
+        let costs = [[0.0, 5.0, 9.0]];
+        let candidates = vec![vec![(0, 0.0), (1, 5.0), (2, 9.0)]];
+        let solver = AssignmentSolver::new(&candidates, 3, 0.0, |c, t| costs[c][t])
+            .with_every_tile_used(true);
+
+        assert_eq!(
+            solver.solve(AssignmentMode::Greedy).unmet,
+            vec![UnmetConstraint::TilesUnused {
+                unused: 2,
+                cells: 1
+            }]
+        );
+    }
+
+    #[test]
+    fn global_is_deterministic() {
+        let costs: Vec<Vec<f64>> = (0..12)
+            .map(|c| (0..5).map(|t| ((c * 7 + t * 3) % 11) as f64).collect())
+            .collect();
+        let candidates = candidates_from(&costs);
+        let solver = AssignmentSolver::new(&candidates, 5, 5.0, |c, t| costs[c][t]);
+
+        assert_eq!(
+            solver.solve(AssignmentMode::Global).tiles,
+            solver.solve(AssignmentMode::Global).tiles
+        );
+    }
+}
@@ -0,0 +1,298 @@
+use serde::{Deserialize, Serialize};
+use std::sync::OnceLock;
+
+// D65 reference white used for XYZ <-> CIELAB conversion
+const WHITE_X: f64 = 0.95047;
+const WHITE_Y: f64 = 1.0;
+const WHITE_Z: f64 = 1.08883;
+const LAB_EPSILON: f64 = 216.0 / 24389.0;
+const LAB_KAPPA: f64 = 24389.0 / 27.0;
+
+/// Color space used for tile colors, target cell averages and the KD-tree.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorSpace {
+    /// Gamma-encoded sRGB in 0..=255, averaged without linearization.
+    #[default]
+    Srgb,
+    /// Linear-light RGB scaled to 0..=255.
+    LinearRgb,
+    /// CIELAB (D65), L in 0..=100.
+    Lab,
+    /// OKLab, L in 0..=1.
+    Oklab,
+}
+
+impl ColorSpace {
+    /// Converts a linear-light RGB triple (0..=1) into this color space.
+    #[must_use]
+    pub fn from_linear(self, rgb: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb => rgb.map(linear_to_srgb),
+            ColorSpace::LinearRgb => rgb.map(|c| c * 255.0),
+            ColorSpace::Lab => linear_to_lab(rgb),
+            ColorSpace::Oklab => linear_to_oklab(rgb),
+        }
+    }
+
+    /// Converts a color in this space back to linear-light RGB (0..=1).
+    #[must_use]
+    pub fn to_linear(self, color: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb => color.map(srgb_to_linear),
+            ColorSpace::LinearRgb => color.map(|c| c / 255.0),
+            ColorSpace::Lab => lab_to_linear(color),
+            ColorSpace::Oklab => oklab_to_linear(color),
+        }
+    }
+
+    /// Converts a color in this space to CIELAB.
+    #[must_use]
+    pub fn to_lab(self, color: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Lab => color,
+            _ => linear_to_lab(self.to_linear(color)),
+        }
+    }
+
+    /// Converts a color in this space to gamma-encoded sRGB (0..=255).
+    #[must_use]
+    pub fn to_srgb(self, color: [f64; 3]) -> [f64; 3] {
+        match self {
+            ColorSpace::Srgb => color,
+            _ => self.to_linear(color).map(linear_to_srgb),
+        }
+    }
+
+    /// Factor converting squared distances in this space to the 0..=255 scale
+    /// the usage penalty was tuned against.
+    #[inline]
+    #[must_use]
+    pub fn distance_scale(self) -> f64 {
+        match self {
+            ColorSpace::Srgb | ColorSpace::LinearRgb => 1.0,
+            ColorSpace::Lab => LAB_DISTANCE_SCALE,
+            ColorSpace::Oklab => 255.0 * 255.0,
+        }
+    }
+}
+
+/// Squared-distance factor from CIELAB units (L in 0..=100) to the 0..=255 scale.
+pub const LAB_DISTANCE_SCALE: f64 = 2.55 * 2.55;
+
+/// Converts an 8-bit sRGB channel to linear light using a lookup table.
+#[inline]
+#[must_use]
+pub fn srgb_u8_to_linear(value: u8) -> f64 {
+    static LUT: OnceLock<[f64; 256]> = OnceLock::new();
+    LUT.get_or_init(|| std::array::from_fn(|i| srgb_to_linear(i as f64)))[value as usize]
+}
+
+/// Converts a gamma-encoded sRGB channel (0..=255) to linear light (0..=1).
+#[must_use]
+pub fn srgb_to_linear(value: f64) -> f64 {
+    let c = value / 255.0;
+    if c <= 0.04045 {
+        c / 12.92
+    } else {
+        ((c + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// Converts a linear-light channel (0..=1) to gamma-encoded sRGB (0..=255).
+#[must_use]
+pub fn linear_to_srgb(value: f64) -> f64 {
+    let c = value.clamp(0.0, 1.0);
+    let encoded = if c <= 0.003_130_8 {
+        c * 12.92
+    } else {
+        1.055 * c.powf(1.0 / 2.4) - 0.055
+    };
+    encoded * 255.0
+}
+
+fn linear_to_xyz([r, g, b]: [f64; 3]) -> [f64; 3] {
+    [
+        0.412_456_4 * r + 0.357_576_1 * g + 0.180_437_5 * b,
+        0.212_672_9 * r + 0.715_152_2 * g + 0.072_175_0 * b,
+        0.019_333_9 * r + 0.119_192_0 * g + 0.950_304_1 * b,
+    ]
+}
+
+fn xyz_to_linear([x, y, z]: [f64; 3]) -> [f64; 3] {
+    [
+        3.240_454_2 * x - 1.537_138_5 * y - 0.498_531_4 * z,
+        -0.969_266_0 * x + 1.876_010_8 * y + 0.041_556_0 * z,
+        0.055_643_4 * x - 0.204_025_9 * y + 1.057_225_2 * z,
+    ]
+}
+
+/// Converts linear-light RGB (0..=1) to CIELAB (D65).
+#[must_use]
+pub fn linear_to_lab(rgb: [f64; 3]) -> [f64; 3] {
+    let [x, y, z] = linear_to_xyz(rgb);
+    let f = |t: f64| {
+        if t > LAB_EPSILON {
+            t.cbrt()
+        } else {
+            (LAB_KAPPA * t + 16.0) / 116.0
+        }
+    };
+    let fx = f(x / WHITE_X);
+    let fy = f(y / WHITE_Y);
+    let fz = f(z / WHITE_Z);
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Converts CIELAB (D65) to linear-light RGB (0..=1).
+#[must_use]
+pub fn lab_to_linear([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let fy = (l + 16.0) / 116.0;
+    let fx = fy + a / 500.0;
+    let fz = fy - b / 200.0;
+    let finv = |f: f64| {
+        let cubed = f * f * f;
+        if cubed > LAB_EPSILON {
+            cubed
+        } else {
+            (116.0 * f - 16.0) / LAB_KAPPA
+        }
+    };
+    xyz_to_linear([finv(fx) * WHITE_X, finv(fy) * WHITE_Y, finv(fz) * WHITE_Z])
+}
+
+/// Converts linear-light RGB (0..=1) to OKLab.
+#[must_use]
+pub fn linear_to_oklab([r, g, b]: [f64; 3]) -> [f64; 3] {
+    let l = (0.412_221_470_8 * r + 0.536_332_536_3 * g + 0.051_445_992_9 * b).cbrt();
+    let m = (0.211_903_498_2 * r + 0.680_699_545_1 * g + 0.107_396_956_6 * b).cbrt();
+    let s = (0.088_302_461_9 * r + 0.281_718_837_6 * g + 0.629_978_700_5 * b).cbrt();
+    [
+        0.210_454_255_3 * l + 0.793_617_785_0 * m - 0.004_072_046_8 * s,
+        1.977_998_495_1 * l - 2.428_592_205_0 * m + 0.450_593_709_9 * s,
+        0.025_904_037_1 * l + 0.782_771_766_2 * m - 0.808_675_766_0 * s,
+    ]
+}
+
+/// Converts OKLab to linear-light RGB (0..=1).
+#[must_use]
+pub fn oklab_to_linear([l, a, b]: [f64; 3]) -> [f64; 3] {
+    let l_ = l + 0.396_337_777_4 * a + 0.215_803_757_3 * b;
+    let m_ = l - 0.105_561_345_8 * a - 0.063_854_172_8 * b;
+    let s_ = l - 0.089_484_177_5 * a - 1.291_485_548_0 * b;
+    let (l, m, s) = (l_ * l_ * l_, m_ * m_ * m_, s_ * s_ * s_);
+    [
+        4.076_741_662_1 * l - 3.307_711_591_3 * m + 0.230_969_929_2 * s,
+        -1.268_438_004_6 * l + 2.609_757_401_1 * m - 0.341_319_396_5 * s,
+        -0.004_196_086_3 * l - 0.703_418_614_7 * m + 1.707_614_701_0 * s,
+    ]
+}
+
+/// CIEDE2000 color difference between two CIELAB colors.
+#[must_use]
+pub fn ciede2000(lab1: [f64; 3], lab2: [f64; 3]) -> f64 {
+    let [l1, a1, b1] = lab1;
+    let [l2, a2, b2] = lab2;
+
+    let c1 = a1.hypot(b1);
+    let c2 = a2.hypot(b2);
+    let c_bar7 = ((c1 + c2) / 2.0).powi(7);
+    let g = 0.5 * (1.0 - (c_bar7 / (c_bar7 + 25f64.powi(7))).sqrt());
+
+    let a1p = (1.0 + g) * a1;
+    let a2p = (1.0 + g) * a2;
+    let c1p = a1p.hypot(b1);
+    let c2p = a2p.hypot(b2);
+
+    let hue = |b: f64, a: f64| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let h1p = hue(b1, a1p);
+    let h2p = hue(b2, a2p);
+
+    let delta_lp = l2 - l1;
+    let delta_cp = c2p - c1p;
+    let delta_hp = if c1p * c2p == 0.0 {
+        0.0
+    } else if (h2p - h1p).abs() <= 180.0 {
+        h2p - h1p
+    } else if h2p <= h1p {
+        h2p - h1p + 360.0
+    } else {
+        h2p - h1p - 360.0
+    };
+    let delta_big_hp = 2.0 * (c1p * c2p).sqrt() * (delta_hp / 2.0).to_radians().sin();
+
+    let l_bar_p = (l1 + l2) / 2.0;
+    let c_bar_p = (c1p + c2p) / 2.0;
+    let h_bar_p = if c1p * c2p == 0.0 {
+        h1p + h2p
+    } else if (h1p - h2p).abs() <= 180.0 {
+        (h1p + h2p) / 2.0
+    } else if h1p + h2p < 360.0 {
+        (h1p + h2p + 360.0) / 2.0
+    } else {
+        (h1p + h2p - 360.0) / 2.0
+    };
+
+    let t = 1.0 - 0.17 * (h_bar_p - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_bar_p).to_radians().cos()
+        + 0.32 * (3.0 * h_bar_p + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_bar_p - 63.0).to_radians().cos();
+    let delta_theta = 30.0 * (-((h_bar_p - 275.0) / 25.0).powi(2)).exp();
+    let c_bar_p7 = c_bar_p.powi(7);
+    let r_c = 2.0 * (c_bar_p7 / (c_bar_p7 + 25f64.powi(7))).sqrt();
+    let l_term = (l_bar_p - 50.0).powi(2);
+    let s_l = 1.0 + 0.015 * l_term / (20.0 + l_term).sqrt();
+    let s_c = 1.0 + 0.045 * c_bar_p;
+    let s_h = 1.0 + 0.015 * c_bar_p * t;
+    let r_t = -(2.0 * delta_theta).to_radians().sin() * r_c;
+
+    let dl = delta_lp / s_l;
+    let dc = delta_cp / s_c;
+    let dh = delta_big_hp / s_h;
+    (dl * dl + dc * dc + dh * dh + r_t * dc * dh).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: [f64; 3], b: [f64; 3], tolerance: f64) {
+        for (x, y) in a.iter().zip(b.iter()) {
+            assert!((x - y).abs() < tolerance, "{:?} != {:?}", a, b);
+        }
+    }
+
+    #[test]
+    fn color_spaces_round_trip_through_linear() {
+        let linear = [0.2, 0.5, 0.8];
+        for space in [
+            ColorSpace::Srgb,
+            ColorSpace::LinearRgb,
+            ColorSpace::Lab,
+            ColorSpace::Oklab,
+        ] {
+            assert_close(space.to_linear(space.from_linear(linear)), linear, 1e-6);
+        }
+    }
+
+    #[test]
+    fn srgb_white_maps_to_lab_white() {
+        let lab = ColorSpace::Srgb.to_lab([255.0, 255.0, 255.0]);
+        assert_close(lab, [100.0, 0.0, 0.0], 1e-3);
+    }
+
+    #[test]
+    fn ciede2000_matches_reference_pairs() {
+        // Reference values from Sharma, Wu & Dalal (2005)
+        let d1 = ciede2000([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485]);
+        let d2 = ciede2000([50.0, 2.5, 0.0], [73.0, 25.0, -18.0]);
+        assert!((d1 - 2.0425).abs() < 1e-4);
+        assert!((d2 - 27.1492).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,225 @@
+use crate::color::{linear_to_srgb, srgb_u8_to_linear};
+use image::imageops::{self, FilterType};
+use image::{GenericImageView, Rgba, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// How the original image is combined with the tiles when overlaid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlendMode {
+    #[default]
+    Normal,
+    Multiply,
+    SoftLight,
+}
+
+impl BlendMode {
+    /// Blends one channel of `top` onto `base`, both in 0..=1.
+    #[inline]
+    #[must_use]
+    fn apply(self, base: f64, top: f64) -> f64 {
+        match self {
+            BlendMode::Normal => top,
+            BlendMode::Multiply => base * top,
+            // W3C compositing soft-light
+            BlendMode::SoftLight => {
+                if top <= 0.5 {
+                    base - (1.0 - 2.0 * top) * base * (1.0 - base)
+                } else {
+                    let d = if base <= 0.25 {
+                        ((16.0 * base - 12.0) * base + 4.0) * base
+                    } else {
+                        base.sqrt()
+                    };
+                    base + (2.0 * top - 1.0) * (d - base)
+                }
+            }
+        }
+    }
+}
+
+/// Unweighted mean color of an image in linear light.
+#[must_use]
+pub(crate) fn mean_linear_rgb(img: &impl GenericImageView<Pixel = Rgba<u8>>) -> [f64; 3] {
+    let mut sums = [0.0; 3];
+    for (_, _, pixel) in img.pixels() {
+        for (sum, &value) in sums.iter_mut().zip(pixel.0.iter()) {
+            *sum += srgb_u8_to_linear(value);
+        }
+    }
+
+    let count = (img.width() as f64 * img.height() as f64).max(1.0);
+    sums.map(|s| s / count)
+}
+
+/// Shifts a tile's colors toward a target mean in linear light.
+/// `strength` is 0..=1, where 1 moves the tile mean onto the target mean.
+pub(crate) fn correct_toward(tile: &mut RgbaImage, target_mean: [f64; 3], strength: f64) {
+    let tile_mean = mean_linear_rgb(tile);
+
+    // One lookup table per channel keeps the per-pixel work to indexing
+    let luts: [[u8; 256]; 3] = std::array::from_fn(|channel| {
+        let offset = (target_mean[channel] - tile_mean[channel]) * strength;
+        std::array::from_fn(|value| {
+            linear_to_srgb(srgb_u8_to_linear(value as u8) + offset).round() as u8
+        })
+    });
+
+    for pixel in tile.pixels_mut() {
+        for (value, lut) in pixel.0.iter_mut().zip(luts.iter()) {
+            *value = lut[*value as usize];
+        }
+    }
+}
+
+/// Blends `overlay` over `canvas` with the given mode and `opacity` (0..=1).
+/// Both images must have the same dimensions; canvas alpha is preserved.
+pub(crate) fn blend_overlay(
+    canvas: &mut RgbaImage,
+    overlay: &RgbaImage,
+    mode: BlendMode,
+    opacity: f64,
+) {
+    for (base, top) in canvas.pixels_mut().zip(overlay.pixels()) {
+        let alpha = opacity * top.0[3] as f64 / 255.0;
+        for (b, &t) in base.0.iter_mut().zip(top.0.iter()).take(3) {
+            let (bf, tf) = (*b as f64 / 255.0, t as f64 / 255.0);
+            let blended = bf + (mode.apply(bf, tf) - bf) * alpha;
+            *b = (blended * 255.0).round().clamp(0.0, 255.0) as u8;
+        }
+    }
+}
+
+/// Returns the rows starting at `top` and the first `width` columns of
+/// `src` resized to `size` with Lanczos3. The source is resampled in blocks
+/// of `block.0` rows that each become `block.1` output rows, every block
+/// together with the blocks above and below it so the seams match a
+/// whole-image resize. A block is always resized the same way whichever
+/// rows are requested, so strips rendered separately match one full render.
+#[must_use]
+pub(crate) fn resize_rows(
+    src: &RgbaImage,
+    size: (u32, u32),
+    block: (u32, u32),
+    top: u32,
+    width: u32,
+    height: u32,
+) -> RgbaImage {
+    let mut out = RgbaImage::new(width, height);
+    let (src_block, out_block) = (block.0.max(1), block.1.max(1));
+    let blocks = src.height().div_ceil(src_block);
+    let bottom = top + height;
+
+    for b in top / out_block..bottom.div_ceil(out_block).min(blocks) {
+        let (from, to) = (b.saturating_sub(1), (b + 2).min(blocks));
+        let src_top = from * src_block;
+        let context = imageops::crop_imm(
+            src,
+            0,
+            src_top,
+            src.width(),
+            (to * src_block).min(src.height()) - src_top,
+        );
+        let resized = imageops::resize(
+            &*context,
+            size.0,
+            (to - from) * out_block,
+            FilterType::Lanczos3,
+        );
+
+        let rows = (b * out_block).max(top)..((b + 1) * out_block).min(bottom);
+        let block_rows = imageops::crop_imm(
+            &resized,
+            0,
+            rows.start - from * out_block,
+            width,
+            rows.end - rows.start,
+        );
+        imageops::replace(&mut out, &*block_rows, 0, i64::from(rows.start - top));
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn solid(rgb: [u8; 3]) -> RgbaImage {
+        ImageBuffer::from_pixel(4, 4, Rgba([rgb[0], rgb[1], rgb[2], 255]))
+    }
+
+    #[test]
+    fn full_strength_matches_target_mean() {
+        let mut tile = solid([40, 120, 200]);
+        let target = mean_linear_rgb(&solid([180, 60, 90]));
+
+        correct_toward(&mut tile, target, 1.0);
+
+        assert_eq!(tile.get_pixel(0, 0).0, [180, 60, 90, 255]);
+    }
+
+    #[test]
+    fn zero_strength_leaves_tile_unchanged() {
+        let mut tile = solid([40, 120, 200]);
+        let target = mean_linear_rgb(&solid([180, 60, 90]));
+
+        correct_toward(&mut tile, target, 0.0);
+
+        assert_eq!(tile, solid([40, 120, 200]));
+    }
+
+    #[test]
+    fn blend_overlay_mixes_by_opacity() {
+        let mut canvas = solid([0, 100, 200]);
+        blend_overlay(&mut canvas, &solid([200, 100, 0]), BlendMode::Normal, 0.5);
+        assert_eq!(canvas.get_pixel(0, 0).0, [100, 100, 100, 255]);
+
+        let mut canvas = solid([255, 128, 0]);
+        blend_overlay(
+            &mut canvas,
+            &solid([128, 255, 255]),
+            BlendMode::Multiply,
+            1.0,
+        );
+        assert_eq!(canvas.get_pixel(0, 0).0, [128, 128, 0, 255]);
+    }
+
+    #[test]
+    fn resize_rows_is_seamless_and_independent_of_strips() {
+        // Five blocks of 4 source rows, each scaled to 6 output rows
+        let src: RgbaImage = ImageBuffer::from_fn(5, 20, |x, y| {
+            Rgba([
+                (x * 50) as u8,
+                (y * 12) as u8,
+                ((x * y) % 3 * 90) as u8,
+                255,
+            ])
+        });
+        let rows = resize_rows(&src, (15, 30), (4, 6), 0, 15, 30);
+        let whole = imageops::resize(&src, 15, 30, FilterType::Lanczos3);
+        for (a, b) in rows.pixels().zip(whole.pixels()) {
+            for (x, y) in a.0.iter().zip(b.0.iter()) {
+                assert!(x.abs_diff(*y) <= 1, "{:?} != {:?}", a, b);
+            }
+        }
+
+        for top in 0..30 {
+            let strip = resize_rows(&src, (15, 30), (4, 6), top, 13, 1);
+            for x in 0..13 {
+                assert_eq!(strip.get_pixel(x, 0), rows.get_pixel(x, top));
+            }
+        }
+        assert_eq!(
+            resize_rows(&src, (15, 30), (4, 6), 5, 15, 17),
+            imageops::crop_imm(&rows, 0, 5, 15, 17).to_image()
+        );
+    }
+
+    #[test]
+    fn soft_light_with_mid_gray_is_identity() {
+        for base in [0.0, 0.2, 0.5, 0.9, 1.0] {
+            assert!((BlendMode::SoftLight.apply(base, 0.5) - base).abs() < 1e-12);
+        }
+    }
+}
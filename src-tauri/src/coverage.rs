@@ -0,0 +1,170 @@
+use serde::Serialize;
+
+/// Bins along each sRGB channel; 8 gives 512 bins of 32 levels each.
+pub const BINS_PER_CHANNEL: usize = 8;
+const BIN_COUNT: usize = BINS_PER_CHANNEL * BINS_PER_CHANNEL * BINS_PER_CHANNEL;
+const BIN_WIDTH: f64 = 256.0 / BINS_PER_CHANNEL as f64;
+/// Target colors rarer than this share of cells are not reported as gaps.
+const MIN_REPORTED_SHARE: f64 = 0.005;
+/// A bin is under-covered when the library holds less than this fraction of
+/// the target's share of it.
+const UNDER_COVERED_RATIO: f64 = 0.5;
+
+/// 3D histogram of gamma-encoded sRGB colors (0..=255 per channel).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ColorHistogram {
+    counts: Vec<u32>,
+    total: u32,
+}
+
+impl ColorHistogram {
+    pub fn from_colors(colors: impl IntoIterator<Item = [f64; 3]>) -> Self {
+        let mut counts = vec![0; BIN_COUNT];
+        let mut total = 0;
+        for color in colors {
+            counts[Self::bin_of(color)] += 1;
+            total += 1;
+        }
+        Self { counts, total }
+    }
+
+    /// Bin holding `color`. Out-of-gamut values are clamped to the cube.
+    #[must_use]
+    pub fn bin_of(color: [f64; 3]) -> usize {
+        let index = |c: f64| ((c.max(0.0) / BIN_WIDTH) as usize).min(BINS_PER_CHANNEL - 1);
+        let [r, g, b] = color.map(index);
+        (r * BINS_PER_CHANNEL + g) * BINS_PER_CHANNEL + b
+    }
+
+    #[must_use]
+    pub fn count(&self, bin: usize) -> u32 {
+        self.counts[bin]
+    }
+
+    #[must_use]
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Fraction of all colors that fall into `bin`.
+    #[must_use]
+    pub fn share(&self, bin: usize) -> f64 {
+        match self.total {
+            0 => 0.0,
+            total => self.counts[bin] as f64 / total as f64,
+        }
+    }
+}
+
+/// sRGB color at the center of a bin.
+fn bin_center(bin: usize) -> [u8; 3] {
+    let center = |index: usize| (index as f64 * BIN_WIDTH + BIN_WIDTH / 2.0) as u8;
+    [
+        center(bin / (BINS_PER_CHANNEL * BINS_PER_CHANNEL)),
+        center(bin / BINS_PER_CHANNEL % BINS_PER_CHANNEL),
+        center(bin % BINS_PER_CHANNEL),
+    ]
+}
+
+/// One color region of the histogram.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ColorBin {
+    /// sRGB color at the center of the region.
+    pub color: [u8; 3],
+    /// Fraction of target cells in this region.
+    pub target_share: f64,
+    /// Fraction of library tiles in this region.
+    pub library_share: f64,
+    pub tile_count: u32,
+}
+
+/// How well a tile library's colors cover a target image.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CoverageReport {
+    pub bins_per_channel: usize,
+    pub cell_count: u32,
+    pub tile_count: u32,
+    /// Overlap of the two color distributions (histogram intersection):
+    /// 1 when the library has the target's colors in the same proportions,
+    /// 0 when they share no color region.
+    pub coverage_score: f64,
+    /// Every region used by the target or the library, in bin order.
+    pub bins: Vec<ColorBin>,
+    /// Regions the target needs noticeably more of than the library has,
+    /// largest shortfall first.
+    pub under_covered: Vec<ColorBin>,
+}
+
+/// Compares the color distribution of target cells with that of library tiles.
+#[must_use]
+pub fn compare_histograms(target: &ColorHistogram, library: &ColorHistogram) -> CoverageReport {
+    let bins: Vec<ColorBin> = (0..BIN_COUNT)
+        .filter(|&bin| target.count(bin) > 0 || library.count(bin) > 0)
+        .map(|bin| ColorBin {
+            color: bin_center(bin),
+            target_share: target.share(bin),
+            library_share: library.share(bin),
+            tile_count: library.count(bin),
+        })
+        .collect();
+    let coverage_score = bins
+        .iter()
+        .map(|bin| bin.target_share.min(bin.library_share))
+        .sum();
+
+    let mut under_covered: Vec<ColorBin> = bins
+        .iter()
+        .filter(|bin| {
+            bin.target_share >= MIN_REPORTED_SHARE
+                && bin.library_share < bin.target_share * UNDER_COVERED_RATIO
+        })
+        .cloned()
+        .collect();
+    under_covered.sort_by(|a, b| {
+        (b.target_share - b.library_share).total_cmp(&(a.target_share - a.library_share))
+    });
+
+    CoverageReport {
+        bins_per_channel: BINS_PER_CHANNEL,
+        cell_count: target.total(),
+        tile_count: library.total(),
+        coverage_score,
+        bins,
+        under_covered,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bin_of_clamps_and_orders_channels() {
+        assert_eq!(ColorHistogram::bin_of([0.0, 0.0, 0.0]), 0);
+        assert_eq!(ColorHistogram::bin_of([0.0, 0.0, 40.0]), 1);
+        assert_eq!(ColorHistogram::bin_of([0.0, 40.0, 0.0]), BINS_PER_CHANNEL);
+        assert_eq!(ColorHistogram::bin_of([300.0, 255.0, 255.0]), BIN_COUNT - 1);
+        assert_eq!(ColorHistogram::bin_of([-5.0, 0.0, 0.0]), 0);
+        assert_eq!(bin_center(BIN_COUNT - 1), [240, 240, 240]);
+    }
+
+    #[test]
+    fn missing_dark_blues_are_reported_as_under_covered() {
+        let dark_blue = [10.0, 20.0, 90.0];
+        let orange = [240.0, 150.0, 20.0];
+        // Half the target is dark blue, the library is almost all orange
+        let target = ColorHistogram::from_colors([dark_blue, dark_blue, orange, orange]);
+        let library = ColorHistogram::from_colors(
+            std::iter::repeat_n(orange, 9).chain(std::iter::once(dark_blue)),
+        );
+
+        let report = compare_histograms(&target, &library);
+
+        assert_eq!((report.cell_count, report.tile_count), (4, 10));
+        assert_eq!(report.bins.len(), 2);
+        assert!((report.coverage_score - 0.6).abs() < 1e-9);
+        assert_eq!(report.under_covered.len(), 1);
+        assert_eq!(report.under_covered[0].color, [16, 16, 80]);
+        assert_eq!(report.under_covered[0].tile_count, 1);
+    }
+}
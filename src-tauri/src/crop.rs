@@ -0,0 +1,319 @@
+use crate::layout::CellSize;
+use image::{imageops::FilterType, DynamicImage, GenericImageView, RgbImage};
+use serde::{Deserialize, Serialize};
+
+/// Longest side of the thumbnail crops are chosen on.
+const ANALYSIS_SIZE: u32 = 128;
+/// Luma histogram bins for the entropy term; 32 levels of 8 each.
+const HISTOGRAM_BINS: usize = 32;
+/// Share of the thumbnail that must look like skin before it steers the crop.
+const MIN_SKIN_SHARE: f64 = 0.01;
+/// Weight of the skin share in a window against its saliency (0..=2).
+const SKIN_WEIGHT: f64 = 4.0;
+/// Scores closer than this count as equal, and the more central window wins.
+const SCORE_EPSILON: f64 = 1e-9;
+/// Windows scoring within this of each other are all equally plain, so the
+/// photo keeps its center crop. Covers the rounding noise of the thumbnail.
+const MIN_SCORE_SPREAD: f64 = 0.05;
+
+/// How photos are cropped to the cell aspect before resizing.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CropStrategy {
+    /// Keep the middle of the photo.
+    #[default]
+    Center,
+    /// Keep the most detailed part: strong edges and a wide spread of tones.
+    Saliency,
+    /// Keep skin-toned regions such as faces, falling back to saliency when
+    /// a photo shows too little skin.
+    Faces,
+}
+
+/// Region of a source image, after EXIF orientation, that a tile shows.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CropRect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CropStrategy {
+    /// Picks the largest window with the aspect of `cell` inside `img`.
+    /// Returns None for center crops, for images that already have the
+    /// cell's aspect and for images where no window stands out, which are
+    /// all left to `resize_to_fill`.
+    #[must_use]
+    pub fn choose(self, img: &DynamicImage, cell: CellSize) -> Option<CropRect> {
+        if self == CropStrategy::Center {
+            return None;
+        }
+        let (width, height) = img.dimensions();
+        // The window spans the image along one axis and slides along the other
+        let wide =
+            u64::from(width) * u64::from(cell.height) > u64::from(height) * u64::from(cell.width);
+        let (length, window) = match wide {
+            true => (
+                width,
+                scale(height, cell.width, cell.height).clamp(1, width),
+            ),
+            false => (
+                height,
+                scale(width, cell.height, cell.width).clamp(1, height),
+            ),
+        };
+        if window == length {
+            return None;
+        }
+
+        let thumbnail = img.thumbnail(ANALYSIS_SIZE, ANALYSIS_SIZE).to_rgb8();
+        let lines = LineProfiles::new(&thumbnail, wide);
+        let thumb_length = lines.edges.len() as u32;
+        let thumb_window = scale(window, thumb_length, length).clamp(1, thumb_length);
+        let start = lines.best_window(thumb_window as usize, self)? as u32;
+
+        let offset = scale(start, length, thumb_length).min(length - window);
+        Some(match wide {
+            true => CropRect {
+                x: offset,
+                y: 0,
+                width: window,
+                height,
+            },
+            false => CropRect {
+                x: 0,
+                y: offset,
+                width,
+                height: window,
+            },
+        })
+    }
+}
+
+/// `value * numerator / denominator`, rounded to the nearest integer.
+fn scale(value: u32, numerator: u32, denominator: u32) -> u32 {
+    let scaled = u64::from(value) * u64::from(numerator);
+    ((scaled + u64::from(denominator) / 2) / u64::from(denominator)) as u32
+}
+
+/// Crops `img` to `crop`, or its center when there is none, and resizes it to `size`.
+#[must_use]
+pub(crate) fn fill_cell(
+    img: &DynamicImage,
+    crop: Option<CropRect>,
+    size: CellSize,
+) -> DynamicImage {
+    match crop {
+        Some(crop) => img
+            .crop_imm(crop.x, crop.y, crop.width, crop.height)
+            .resize_to_fill(size.width, size.height, FilterType::Lanczos3),
+        None => img.resize_to_fill(size.width, size.height, FilterType::Lanczos3),
+    }
+}
+
+/// Whether a pixel falls in the usual YCbCr range of skin tones.
+#[inline]
+fn is_skin([r, g, b]: [u8; 3]) -> bool {
+    let [r, g, b] = [r, g, b].map(f64::from);
+    let cb = 128.0 - 0.168_736 * r - 0.331_264 * g + 0.5 * b;
+    let cr = 128.0 + 0.5 * r - 0.418_688 * g - 0.081_312 * b;
+    (77.0..=127.0).contains(&cb) && (133.0..=173.0).contains(&cr)
+}
+
+/// Per-line features of a thumbnail along the axis the crop window slides
+/// on: columns of wide images, rows of tall ones.
+struct LineProfiles {
+    /// Sum of absolute luma differences to the right and lower neighbors.
+    edges: Vec<f64>,
+    skin: Vec<u32>,
+    histograms: Vec<[u32; HISTOGRAM_BINS]>,
+    /// Pixels per line.
+    line_pixels: u32,
+}
+
+impl LineProfiles {
+    fn new(thumbnail: &RgbImage, columns: bool) -> Self {
+        let (width, height) = thumbnail.dimensions();
+        let lines = if columns { width } else { height } as usize;
+        let mut profiles = Self {
+            edges: vec![0.0; lines],
+            skin: vec![0; lines],
+            histograms: vec![[0; HISTOGRAM_BINS]; lines],
+            line_pixels: if columns { height } else { width },
+        };
+
+        let luma = |x: u32, y: u32| {
+            let [r, g, b] = thumbnail.get_pixel(x, y).0.map(f64::from);
+            0.299 * r + 0.587 * g + 0.114 * b
+        };
+        for (x, y, pixel) in thumbnail.enumerate_pixels() {
+            let line = if columns { x } else { y } as usize;
+            let here = luma(x, y);
+            let right = if x + 1 < width { luma(x + 1, y) } else { here };
+            let below = if y + 1 < height { luma(x, y + 1) } else { here };
+            profiles.edges[line] += (right - here).abs() + (below - here).abs();
+            profiles.skin[line] += u32::from(is_skin(pixel.0));
+            let bin = (here as usize * HISTOGRAM_BINS / 256).min(HISTOGRAM_BINS - 1);
+            profiles.histograms[line][bin] += 1;
+        }
+        profiles
+    }
+
+    /// First line of the `window` consecutive lines that score highest for
+    /// `strategy`, or None when no position stands out. Sums slide
+    /// along the lines, so every position costs the same regardless of the
+    /// window length.
+    fn best_window(&self, window: usize, strategy: CropStrategy) -> Option<usize> {
+        let total_skin: u32 = self.skin.iter().sum();
+        let pixels = f64::from(self.line_pixels) * self.edges.len() as f64;
+        let use_skin =
+            strategy == CropStrategy::Faces && f64::from(total_skin) >= MIN_SKIN_SHARE * pixels;
+        let window_pixels = f64::from(self.line_pixels) * window as f64;
+
+        let mut edges: f64 = self.edges[..window].iter().sum();
+        let mut skin: u32 = self.skin[..window].iter().sum();
+        let mut histogram = [0u32; HISTOGRAM_BINS];
+        for line in &self.histograms[..window] {
+            histogram.iter_mut().zip(line).for_each(|(a, b)| *a += b);
+        }
+
+        let center = (self.edges.len() - window) as f64 / 2.0;
+        let mut best = (0, f64::NEG_INFINITY);
+        let mut worst = f64::INFINITY;
+        for start in 0..=self.edges.len() - window {
+            if start > 0 {
+                let (leaving, entering) = (start - 1, start + window - 1);
+                edges += self.edges[entering] - self.edges[leaving];
+                skin = skin + self.skin[entering] - self.skin[leaving];
+                for (bin, count) in histogram.iter_mut().enumerate() {
+                    *count =
+                        *count + self.histograms[entering][bin] - self.histograms[leaving][bin];
+                }
+            }
+
+            // Mean edge strength (at most 2 * 255) and entropy, each scaled to 0..=1
+            let entropy: f64 = histogram
+                .iter()
+                .filter(|&&count| count > 0)
+                .map(|&count| {
+                    let p = f64::from(count) / window_pixels;
+                    -p * p.log2()
+                })
+                .sum();
+            let mut score =
+                edges / window_pixels / 510.0 + entropy / (HISTOGRAM_BINS as f64).log2();
+            if use_skin {
+                score += SKIN_WEIGHT * f64::from(skin) / f64::from(total_skin);
+            }
+
+            worst = worst.min(score);
+            let (best_start, best_score) = best;
+            let closer = (start as f64 - center).abs() < (best_start as f64 - center).abs();
+            if score > best_score + SCORE_EPSILON || (score > best_score - SCORE_EPSILON && closer)
+            {
+                best = (start, score);
+            }
+        }
+        (best.1 - worst >= MIN_SCORE_SPREAD).then_some(best.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{Rgb, RgbImage};
+
+    #[test]
+    fn saliency_keeps_the_detailed_end_of_a_wide_photo() {
+        // Flat gray with a checkerboard in the right third
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(300, 100, |x, y| {
+            if x >= 200 && (x / 4 + y / 4) % 2 == 0 {
+                Rgb([250, 250, 250])
+            } else {
+                Rgb([90, 90, 90])
+            }
+        }));
+
+        let crop = CropStrategy::Saliency
+            .choose(&img, CellSize::square(32))
+            .unwrap();
+        assert_eq!((crop.y, crop.width, crop.height), (0, 100, 100));
+        assert!(crop.x >= 190, "{:?}", crop);
+        assert_eq!(
+            CropStrategy::Center.choose(&img, CellSize::square(32)),
+            None
+        );
+        // A 3:1 cell already fits the whole photo
+        assert_eq!(
+            CropStrategy::Saliency.choose(&img, CellSize::new(48, 16)),
+            None
+        );
+    }
+
+    #[test]
+    fn faces_keep_skin_tones_in_a_tall_photo() {
+        // A skin-toned oval near the top of a blue-gray portrait, and detail at the bottom
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(60, 180, |x, y| {
+            let (dx, dy) = (x as f64 - 30.0, y as f64 - 30.0);
+            if dx * dx / 225.0 + dy * dy / 400.0 <= 1.0 {
+                Rgb([224, 172, 140])
+            } else if y > 150 && (x + y) % 2 == 0 {
+                Rgb([255, 255, 255])
+            } else {
+                Rgb([70, 80, 110])
+            }
+        }));
+        assert!(is_skin([224, 172, 140]));
+        assert!(!is_skin([70, 80, 110]));
+
+        let faces = CropStrategy::Faces
+            .choose(&img, CellSize::square(16))
+            .unwrap();
+        assert_eq!((faces.x, faces.width, faces.height), (0, 60, 60));
+        assert!(faces.y <= 10, "{:?}", faces);
+        let salient = CropStrategy::Saliency
+            .choose(&img, CellSize::square(16))
+            .unwrap();
+        assert_eq!(salient.y, 120);
+    }
+
+    #[test]
+    fn photos_without_detail_or_skin_keep_the_center_crop() {
+        // Flat photos score the same everywhere, up to thumbnail rounding
+        let wide = DynamicImage::ImageRgb8(RgbImage::from_pixel(300, 100, Rgb([90, 90, 90])));
+        let tall = DynamicImage::ImageRgb8(RgbImage::from_pixel(60, 180, Rgb([40, 60, 160])));
+        let green = DynamicImage::ImageRgb8(RgbImage::from_pixel(90, 30, Rgb([0, 160, 0])));
+
+        for img in [&wide, &tall, &green] {
+            for strategy in [CropStrategy::Saliency, CropStrategy::Faces] {
+                assert_eq!(strategy.choose(img, CellSize::square(16)), None);
+            }
+        }
+    }
+
+    #[test]
+    fn fill_cell_resizes_the_crop_to_the_cell() {
+        let img = DynamicImage::ImageRgb8(RgbImage::from_fn(40, 20, |x, _| {
+            if x < 20 {
+                Rgb([255, 0, 0])
+            } else {
+                Rgb([0, 0, 255])
+            }
+        }));
+        let crop = CropRect {
+            x: 20,
+            y: 0,
+            width: 20,
+            height: 20,
+        };
+
+        let filled = fill_cell(&img, Some(crop), CellSize::square(8)).to_rgb8();
+        assert_eq!(filled.dimensions(), (8, 8));
+        assert!(filled.pixels().all(|p| p.0 == [0, 0, 255]));
+        assert_eq!(
+            fill_cell(&img, None, CellSize::new(8, 6)).dimensions(),
+            (8, 6)
+        );
+    }
+}
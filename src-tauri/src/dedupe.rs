@@ -0,0 +1,239 @@
+use crate::errors::{AppError, AppResult};
+use image::{imageops, imageops::FilterType, DynamicImage, GrayImage};
+use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
+
+/// Side of the grayscale thumbnail the DCT hash is computed from.
+const DCT_SIZE: u32 = 32;
+/// Side of the block of bits each hash is made of.
+const HASH_SIDE: u32 = 8;
+/// Largest accepted Hamming distance; half the bits differing means unrelated images.
+pub const MAX_DUPLICATE_DISTANCE: u32 = 32;
+
+/// Perceptual hash used to find near-duplicate tiles. All of them compare
+/// grayscale structure only, so they survive resizing, recompression and
+/// small edits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HashKind {
+    /// aHash: pixels brighter than the mean. Fastest, but sensitive to exposure changes.
+    Average,
+    /// dHash: brightness gradient between horizontal neighbors.
+    #[default]
+    Difference,
+    /// pHash: low DCT frequencies above their median. Most robust to edits and re-exports.
+    Perceptual,
+}
+
+/// The 64-bit hashes of one image. All kinds are kept, so changing the kind
+/// does not require decoding the library again.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct PerceptualHashes {
+    pub average: u64,
+    pub difference: u64,
+    pub perceptual: u64,
+}
+
+impl PerceptualHashes {
+    #[must_use]
+    pub fn compute(img: &DynamicImage) -> Self {
+        // One cheap downscale of the full image, then filtered resizes of the small copy
+        let gray = img.thumbnail_exact(DCT_SIZE * 2, DCT_SIZE * 2).to_luma8();
+        let resize = |width, height| imageops::resize(&gray, width, height, FilterType::Triangle);
+
+        Self {
+            average: average_hash(&resize(HASH_SIDE, HASH_SIDE)),
+            difference: difference_hash(&resize(HASH_SIDE + 1, HASH_SIDE)),
+            perceptual: dct_hash(&resize(DCT_SIZE, DCT_SIZE)),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn get(&self, kind: HashKind) -> u64 {
+        match kind {
+            HashKind::Average => self.average,
+            HashKind::Difference => self.difference,
+            HashKind::Perceptual => self.perceptual,
+        }
+    }
+}
+
+/// Packs bits into a hash, first bit most significant.
+fn pack_bits(bits: impl Iterator<Item = bool>) -> u64 {
+    bits.fold(0, |hash, bit| (hash << 1) | u64::from(bit))
+}
+
+fn average_hash(img: &GrayImage) -> u64 {
+    let mean =
+        img.pixels().map(|p| f64::from(p[0])).sum::<f64>() / f64::from(HASH_SIDE * HASH_SIDE);
+    pack_bits(img.pixels().map(|p| f64::from(p[0]) > mean))
+}
+
+fn difference_hash(img: &GrayImage) -> u64 {
+    pack_bits((0..HASH_SIDE).flat_map(|y| {
+        (0..HASH_SIDE).map(move |x| img.get_pixel(x, y)[0] > img.get_pixel(x + 1, y)[0])
+    }))
+}
+
+fn dct_hash(img: &GrayImage) -> u64 {
+    let n = DCT_SIZE as usize;
+    let side = HASH_SIDE as usize;
+    // cos((2x + 1) u pi / 2n) for the kept frequencies u
+    let basis: Vec<Vec<f64>> = (0..side)
+        .map(|u| {
+            (0..n)
+                .map(|x| {
+                    ((2 * x + 1) as f64 * u as f64 * std::f64::consts::PI / (2 * n) as f64).cos()
+                })
+                .collect()
+        })
+        .collect();
+
+    // Separable 2D DCT-II, restricted to the lowest frequencies
+    let rows: Vec<Vec<f64>> = (0..n)
+        .map(|y| {
+            (0..side)
+                .map(|u| {
+                    (0..n)
+                        .map(|x| f64::from(img.get_pixel(x as u32, y as u32)[0]) * basis[u][x])
+                        .sum()
+                })
+                .collect()
+        })
+        .collect();
+    let coefficients: Vec<f64> = (0..side)
+        .flat_map(|v| {
+            let rows = &rows;
+            let basis = &basis;
+            (0..side).map(move |u| (0..n).map(|y| rows[y][u] * basis[v][y]).sum())
+        })
+        .collect();
+
+    // The DC term only carries overall brightness, so it is left out of the median
+    let mut ac = coefficients[1..].to_vec();
+    ac.sort_by(f64::total_cmp);
+    let median = ac[ac.len() / 2];
+    pack_bits(coefficients.iter().map(|&c| c > median))
+}
+
+/// Number of differing bits between two hashes.
+#[inline]
+#[must_use]
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Collapses images whose hashes differ by at most `max_distance` bits.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DuplicateFilter {
+    pub hash: HashKind,
+    pub max_distance: u32,
+}
+
+impl DuplicateFilter {
+    pub fn validate(&self) -> AppResult<()> {
+        if self.max_distance > MAX_DUPLICATE_DISTANCE {
+            return Err(AppError::Config(format!(
+                "Invalid duplicate_threshold {}. Expected value in range 0..={}",
+                self.max_distance, MAX_DUPLICATE_DISTANCE
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Near-duplicate files that were collapsed into one tile.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct DuplicateGroup {
+    /// The file kept as a tile.
+    pub representative: PathBuf,
+    /// Files left out of the library in its favor.
+    pub duplicates: Vec<PathBuf>,
+}
+
+/// Returns the representative of every entry: the first earlier entry whose
+/// hash is within `max_distance` of it, or the entry itself. Members are
+/// always within the threshold of their representative, so groups cannot
+/// chain into unrelated images. Entries without a hash stand alone.
+#[must_use]
+pub fn group_near_duplicates(hashes: &[Option<u64>], max_distance: u32) -> Vec<usize> {
+    let mut representatives: Vec<(usize, u64)> = Vec::new();
+    hashes
+        .iter()
+        .enumerate()
+        .map(|(i, hash)| {
+            let Some(hash) = *hash else {
+                return i;
+            };
+            match representatives
+                .iter()
+                .find(|&&(_, rep)| hamming_distance(rep, hash) <= max_distance)
+            {
+                Some(&(rep, _)) => rep,
+                None => {
+                    representatives.push((i, hash));
+                    i
+                }
+            }
+        })
+        .collect()
+}
+
+/// Deterministic blocky noise, distinct for each seed.
+#[cfg(test)]
+pub(crate) fn noise(seed: u32) -> GrayImage {
+    GrayImage::from_fn(64, 64, |x, y| {
+        let mut h = ((x / 8) * 8 + y / 8) ^ seed.wrapping_mul(0x9e37_79b9);
+        h = (h ^ (h >> 16)).wrapping_mul(0x85eb_ca6b);
+        h = (h ^ (h >> 13)).wrapping_mul(0xc2b2_ae35);
+        image::Luma([(h >> 24) as u8])
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Luma;
+
+    #[test]
+    fn hashes_tolerate_small_edits_but_separate_different_images() {
+        let original = noise(1);
+        let mut edited = original.clone();
+        for pixel in edited.pixels_mut() {
+            pixel[0] = pixel[0].saturating_add(6);
+        }
+        edited.put_pixel(3, 3, Luma([255]));
+        let other = noise(2);
+
+        let original = PerceptualHashes::compute(&DynamicImage::ImageLuma8(original));
+        let edited = PerceptualHashes::compute(&DynamicImage::ImageLuma8(edited));
+        let other = PerceptualHashes::compute(&DynamicImage::ImageLuma8(other));
+
+        for kind in [
+            HashKind::Average,
+            HashKind::Difference,
+            HashKind::Perceptual,
+        ] {
+            assert!(
+                hamming_distance(original.get(kind), edited.get(kind)) <= 4,
+                "{:?}",
+                kind
+            );
+            assert!(
+                hamming_distance(original.get(kind), other.get(kind)) > 12,
+                "{:?}",
+                kind
+            );
+        }
+    }
+
+    #[test]
+    fn groups_stay_within_distance_of_their_representative() {
+        // 0b111 is within 2 of 0b001 but 3 away from 0, so it starts its own group
+        let hashes = [Some(0), Some(0b001), Some(0b111), None, Some(0b011)];
+
+        assert_eq!(group_near_duplicates(&hashes, 2), vec![0, 0, 2, 3, 0]);
+        assert_eq!(group_near_duplicates(&hashes, 0), vec![0, 1, 2, 3, 4]);
+    }
+}
@@ -0,0 +1,496 @@
+use crate::errors::{AppError, AppResult};
+use crate::export::{StripSink, DEFAULT_JPEG_QUALITY};
+use image::{codecs::jpeg::JpegEncoder, ExtendedColorType, ImageEncoder, RgbaImage};
+use rayon::prelude::*;
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+/// Edge length of pyramid tiles, excluding overlap. 254 + 2 * 1 keeps tiles at 256px.
+pub const DEFAULT_DZI_TILE_SIZE: u32 = 254;
+/// Pixels each pyramid tile shares with its neighbors.
+pub const DEFAULT_DZI_OVERLAP: u32 = 1;
+
+const DZI_NAMESPACE: &str = "http://schemas.microsoft.com/deepzoom/2008";
+
+/// Layout and encoding of a Deep Zoom pyramid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DeepZoomOptions {
+    pub tile_size: u32,
+    pub overlap: u32,
+    /// JPEG quality (1-100) of the pyramid tiles.
+    pub jpeg_quality: u8,
+}
+
+impl Default for DeepZoomOptions {
+    fn default() -> Self {
+        Self {
+            tile_size: DEFAULT_DZI_TILE_SIZE,
+            overlap: DEFAULT_DZI_OVERLAP,
+            jpeg_quality: DEFAULT_JPEG_QUALITY,
+        }
+    }
+}
+
+impl DeepZoomOptions {
+    pub fn validate(&self) -> AppResult<()> {
+        if !(16..=4096).contains(&self.tile_size) {
+            return Err(AppError::Config(format!(
+                "Invalid tile_size {}. Expected value in range 16..=4096",
+                self.tile_size
+            )));
+        }
+
+        if self.overlap > self.tile_size / 2 {
+            return Err(AppError::Config(format!(
+                "Invalid overlap {}. Expected value in range 0..={}",
+                self.overlap,
+                self.tile_size / 2
+            )));
+        }
+
+        if !(1..=100).contains(&self.jpeg_quality) {
+            return Err(AppError::Config(format!(
+                "Invalid jpeg_quality {}. Expected value in range 1..=100",
+                self.jpeg_quality
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+/// Number of pyramid levels for an image; level 0 is 1x1 and the last level is full size.
+#[must_use]
+pub fn level_count(width: u32, height: u32) -> u32 {
+    let mut size = width.max(height).max(1);
+    let mut levels = 1;
+    while size > 1 {
+        size = size.div_ceil(2);
+        levels += 1;
+    }
+    levels
+}
+
+/// Contents of the `.dzi` descriptor for an image of the given size.
+#[must_use]
+pub fn dzi_descriptor(width: u32, height: u32, options: &DeepZoomOptions) -> String {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <Image xmlns=\"{}\" Format=\"jpg\" Overlap=\"{}\" TileSize=\"{}\">\n\
+         \x20   <Size Width=\"{}\" Height=\"{}\"/>\n\
+         </Image>\n",
+        DZI_NAMESPACE, options.overlap, options.tile_size, width, height
+    )
+}
+
+/// Directory holding the pyramid tiles of a `.dzi` file: `<stem>_files` next to it.
+#[must_use]
+pub fn tiles_directory(dzi_path: &Path) -> PathBuf {
+    let stem = dzi_path
+        .file_stem()
+        .map(|s| s.to_string_lossy().into_owned())
+        .unwrap_or_default();
+    dzi_path.with_file_name(format!("{}_files", stem))
+}
+
+fn io_error(path: &Path, err: impl std::fmt::Display) -> AppError {
+    AppError::Io(format!("Failed to write {}: {}", path.display(), err))
+}
+
+/// One pyramid level, receiving its rows top to bottom. Rows are kept only
+/// until every tile and downsampled row that needs them has been written.
+struct PyramidLevel {
+    level: u32,
+    width: u32,
+    height: u32,
+    dir: PathBuf,
+    rows: VecDeque<Vec<u8>>,
+    /// Index of the first buffered row.
+    top: u32,
+    received: u32,
+    next_tile_row: u32,
+    /// Next row pair to average into the level below.
+    next_downsample: u32,
+}
+
+impl PyramidLevel {
+    fn row(&self, y: u32) -> &[u8] {
+        &self.rows[(y - self.top) as usize]
+    }
+
+    /// Buffers `rows`, writes every tile row that is now complete and returns
+    /// the rows of the next coarser level that became available.
+    fn accept(
+        &mut self,
+        rows: Vec<Vec<u8>>,
+        options: &DeepZoomOptions,
+        downsample: bool,
+    ) -> AppResult<(Vec<Vec<u8>>, u64)> {
+        self.received += rows.len() as u32;
+        if self.received > self.height {
+            return Err(AppError::Image(format!(
+                "Failed to write pyramid: level {} received more than {} rows",
+                self.level, self.height
+            )));
+        }
+        self.rows.extend(rows);
+
+        let mut written = 0;
+        while self.next_tile_row * options.tile_size < self.height {
+            let bottom =
+                ((self.next_tile_row + 1) * options.tile_size + options.overlap).min(self.height);
+            if self.received < bottom {
+                break;
+            }
+            written += self.write_tile_row(self.next_tile_row, options)?;
+            self.next_tile_row += 1;
+        }
+
+        let mut coarser = Vec::new();
+        if downsample {
+            // An odd last row is averaged on its own once the level is complete
+            while self.next_downsample + 1 < self.received
+                || (self.received == self.height && self.next_downsample < self.height)
+            {
+                let y = self.next_downsample;
+                let below = (y + 1 < self.height).then(|| self.row(y + 1));
+                coarser.push(halve_rows(self.row(y), below, self.width));
+                self.next_downsample += 2;
+            }
+        } else {
+            self.next_downsample = self.received;
+        }
+
+        let tile_top = (self.next_tile_row * options.tile_size).saturating_sub(options.overlap);
+        let keep_from = tile_top.min(self.next_downsample).min(self.received);
+        while self.top < keep_from {
+            self.rows.pop_front();
+            self.top += 1;
+        }
+
+        Ok((coarser, written))
+    }
+
+    fn write_tile_row(&self, row: u32, options: &DeepZoomOptions) -> AppResult<u64> {
+        let top = (row * options.tile_size).saturating_sub(options.overlap);
+        let bottom = ((row + 1) * options.tile_size + options.overlap).min(self.height);
+        let columns = self.width.div_ceil(options.tile_size);
+
+        (0..columns)
+            .into_par_iter()
+            .map(|col| {
+                let left = (col * options.tile_size).saturating_sub(options.overlap);
+                let right = ((col + 1) * options.tile_size + options.overlap).min(self.width);
+                // JPEG has no alpha channel
+                let mut rgb = Vec::with_capacity(((right - left) * (bottom - top) * 3) as usize);
+                for y in top..bottom {
+                    let line = &self.row(y)[left as usize * 4..right as usize * 4];
+                    for px in line.chunks_exact(4) {
+                        rgb.extend_from_slice(&px[..3]);
+                    }
+                }
+
+                let path = self.dir.join(format!("{}_{}.jpg", col, row));
+                let file = File::create(&path).map_err(|e| io_error(&path, e))?;
+                let mut writer = BufWriter::new(file);
+                JpegEncoder::new_with_quality(&mut writer, options.jpeg_quality)
+                    .write_image(&rgb, right - left, bottom - top, ExtendedColorType::Rgb8)
+                    .map_err(|e| AppError::Image(format!("Failed to encode image: {}", e)))?;
+                writer.flush().map_err(|e| io_error(&path, e))
+            })
+            .collect::<AppResult<Vec<()>>>()?;
+
+        Ok(columns as u64)
+    }
+}
+
+/// Averages a pair of RGBA rows (or a single last row) into one row of half the width.
+fn halve_rows(upper: &[u8], lower: Option<&[u8]>, width: u32) -> Vec<u8> {
+    let width = width as usize;
+    let mut out = Vec::with_capacity(width.div_ceil(2) * 4);
+    for x in (0..width).step_by(2) {
+        let columns = if x + 1 < width { 2 } else { 1 };
+        for channel in 0..4 {
+            let mut sum = 0u32;
+            let mut count = 0u32;
+            for row in std::iter::once(upper).chain(lower) {
+                for dx in 0..columns {
+                    sum += row[(x + dx) * 4 + channel] as u32;
+                    count += 1;
+                }
+            }
+            out.push(((sum + count / 2) / count) as u8);
+        }
+    }
+    out
+}
+
+/// Builds a Deep Zoom pyramid from full-resolution strips. Each coarser level
+/// is averaged from the one above as rows arrive, so no level is ever held in
+/// memory whole.
+struct PyramidWriter {
+    options: DeepZoomOptions,
+    /// Finest level first.
+    levels: Vec<PyramidLevel>,
+    tiles_written: u64,
+}
+
+impl PyramidWriter {
+    fn create(dir: &Path, width: u32, height: u32, options: DeepZoomOptions) -> AppResult<Self> {
+        let count = level_count(width, height);
+        let mut levels = Vec::with_capacity(count as usize);
+        let (mut level_width, mut level_height) = (width, height);
+        for level in (0..count).rev() {
+            let level_dir = dir.join(level.to_string());
+            std::fs::create_dir_all(&level_dir).map_err(|e| io_error(&level_dir, e))?;
+            levels.push(PyramidLevel {
+                level,
+                width: level_width,
+                height: level_height,
+                dir: level_dir,
+                rows: VecDeque::new(),
+                top: 0,
+                received: 0,
+                next_tile_row: 0,
+                next_downsample: 0,
+            });
+            level_width = level_width.div_ceil(2);
+            level_height = level_height.div_ceil(2);
+        }
+
+        Ok(Self {
+            options,
+            levels,
+            tiles_written: 0,
+        })
+    }
+
+    fn push(&mut self, strip: &RgbaImage) -> AppResult<()> {
+        let width = self.levels[0].width;
+        if strip.width() != width {
+            return Err(AppError::Image(format!(
+                "Failed to write pyramid: strip is {} pixels wide, expected {}",
+                strip.width(),
+                width
+            )));
+        }
+
+        let mut rows: Vec<Vec<u8>> = strip
+            .as_raw()
+            .chunks_exact(width as usize * 4)
+            .map(<[u8]>::to_vec)
+            .collect();
+        let last = self.levels.len() - 1;
+        for (i, level) in self.levels.iter_mut().enumerate() {
+            let (coarser, written) = level.accept(rows, &self.options, i < last)?;
+            self.tiles_written += written;
+            rows = coarser;
+        }
+        Ok(())
+    }
+
+    /// Checks that every level is complete and returns the number of tiles written.
+    fn finish(self) -> AppResult<u64> {
+        for level in &self.levels {
+            if level.received != level.height {
+                return Err(AppError::Image(format!(
+                    "Failed to write pyramid: expected {} rows at level {}",
+                    level.height, level.level
+                )));
+            }
+        }
+        Ok(self.tiles_written)
+    }
+}
+
+/// Summary of a written pyramid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PyramidSummary {
+    pub levels: u32,
+    pub tile_count: u64,
+}
+
+/// Writes a Deep Zoom pyramid for an image whose full-resolution rows are
+/// produced in strips: tiles go to `<stem>_files/<level>/<column>_<row>.jpg`
+/// and the descriptor to `dzi_path` once every tile exists. A tiles folder
+/// left by an earlier export to the same path is removed first, so none of
+/// its levels or tiles outlive it.
+/// See `export::encode_strips` for the contract of `produce`.
+pub fn write_deep_zoom<P>(
+    dzi_path: &Path,
+    width: u32,
+    height: u32,
+    options: &DeepZoomOptions,
+    produce: P,
+) -> AppResult<PyramidSummary>
+where
+    P: FnOnce(&mut StripSink) -> AppResult<()>,
+{
+    options.validate()?;
+    if width == 0 || height == 0 {
+        return Err(AppError::Config(
+            "Cannot build a pyramid of an empty image".into(),
+        ));
+    }
+
+    let tiles_dir = tiles_directory(dzi_path);
+    match std::fs::remove_dir_all(&tiles_dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(io_error(&tiles_dir, e)),
+        _ => {}
+    }
+
+    let mut writer = PyramidWriter::create(&tiles_dir, width, height, *options)?;
+    produce(&mut |strip: &RgbaImage| writer.push(strip))?;
+    let levels = writer.levels.len() as u32;
+    let tile_count = writer.finish()?;
+
+    std::fs::write(dzi_path, dzi_descriptor(width, height, options))
+        .map_err(|e| io_error(dzi_path, e))?;
+
+    Ok(PyramidSummary { levels, tile_count })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir;
+    use image::{ImageBuffer, ImageReader, Rgba};
+
+    #[test]
+    fn level_count_halves_down_to_one_pixel() {
+        assert_eq!(level_count(1, 1), 1);
+        assert_eq!(level_count(2, 1), 2);
+        assert_eq!(level_count(600, 300), 11);
+        assert_eq!(level_count(1024, 1024), 11);
+        assert_eq!(level_count(1025, 10), 12);
+    }
+
+    #[test]
+    fn tiles_directory_sits_next_to_descriptor() {
+        assert_eq!(
+            tiles_directory(Path::new("/out/mosaic.dzi")),
+            PathBuf::from("/out/mosaic_files")
+        );
+    }
+
+    #[test]
+    fn write_deep_zoom_builds_every_level_with_overlap() {
+        let dir = temp_dir("dzi");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dzi_path = dir.join("mosaic.dzi");
+
+        let img: RgbaImage = ImageBuffer::from_fn(75, 41, |x, y| {
+            Rgba([(x * 3) as u8, (y * 5) as u8, 120, 255])
+        });
+        let options = DeepZoomOptions {
+            tile_size: 16,
+            overlap: 1,
+            jpeg_quality: 95,
+        };
+        let summary = write_deep_zoom(&dzi_path, 75, 41, &options, |sink| {
+            for top in (0..41).step_by(7) {
+                let rows = 7.min(41 - top);
+                sink(&image::imageops::crop_imm(&img, 0, top, 75, rows).to_image())?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(summary.levels, 8);
+        let descriptor = std::fs::read_to_string(&dzi_path).unwrap();
+        assert!(descriptor.contains("TileSize=\"16\""));
+        assert!(descriptor.contains("<Size Width=\"75\" Height=\"41\"/>"));
+
+        let files = dir.join("mosaic_files");
+        let open = |level: u32, name: &str| {
+            ImageReader::open(files.join(level.to_string()).join(name))
+                .unwrap()
+                .decode()
+                .unwrap()
+        };
+        // Interior tiles carry one pixel of overlap on each side
+        let interior = open(7, "1_1.jpg");
+        assert_eq!((interior.width(), interior.height()), (18, 18));
+        let corner = open(7, "4_2.jpg");
+        assert_eq!((corner.width(), corner.height()), (75 - 63, 41 - 31));
+        // Coarser levels round their size up
+        let half = open(6, "2_1.jpg");
+        assert_eq!((half.width(), half.height()), (38 - 31, 21 - 15));
+        let single = open(0, "0_0.jpg");
+        assert_eq!((single.width(), single.height()), (1, 1));
+
+        let mut total = 0;
+        let (mut width, mut height) = (75u32, 41u32);
+        for level in (0..8).rev() {
+            let count = std::fs::read_dir(files.join(level.to_string()))
+                .unwrap()
+                .count() as u64;
+            assert_eq!(count, (width.div_ceil(16) * height.div_ceil(16)) as u64);
+            total += count;
+            width = width.div_ceil(2);
+            height = height.div_ceil(2);
+        }
+        assert_eq!(summary.tile_count, total);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn write_deep_zoom_replaces_a_previous_pyramid() {
+        let dir = temp_dir("dzi-rewrite");
+        std::fs::create_dir_all(&dir).unwrap();
+        let dzi_path = dir.join("mosaic.dzi");
+        let export = |width: u32, height: u32, tile_size: u32| {
+            let img: RgbaImage = ImageBuffer::from_pixel(width, height, Rgba([90, 60, 30, 255]));
+            let options = DeepZoomOptions {
+                tile_size,
+                overlap: 0,
+                jpeg_quality: 80,
+            };
+            write_deep_zoom(&dzi_path, width, height, &options, |sink| sink(&img)).unwrap()
+        };
+
+        export(75, 41, 16);
+        let summary = export(20, 10, 32);
+
+        let files = dir.join("mosaic_files");
+        let mut levels: Vec<u32> = std::fs::read_dir(&files)
+            .unwrap()
+            .map(|entry| {
+                entry
+                    .unwrap()
+                    .file_name()
+                    .to_str()
+                    .unwrap()
+                    .parse()
+                    .unwrap()
+            })
+            .collect();
+        levels.sort_unstable();
+        assert_eq!(levels, (0..summary.levels).collect::<Vec<_>>());
+        let finest = std::fs::read_dir(files.join((summary.levels - 1).to_string()))
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect::<Vec<_>>();
+        assert_eq!(finest, ["0_0.jpg"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn halve_rows_averages_blocks_and_keeps_odd_edges() {
+        let upper = [10, 20, 30, 255, 30, 40, 50, 255, 90, 90, 90, 255];
+        let lower = [30, 20, 10, 255, 50, 40, 30, 255, 10, 10, 10, 255];
+
+        assert_eq!(
+            halve_rows(&upper, Some(&lower), 3),
+            vec![30, 30, 30, 255, 50, 50, 50, 255]
+        );
+        assert_eq!(
+            halve_rows(&upper, None, 3),
+            vec![20, 30, 40, 255, 90, 90, 90, 255]
+        );
+    }
+}
@@ -0,0 +1,202 @@
+use crate::color::{srgb_u8_to_linear, ColorSpace};
+use crate::GaussianMask;
+use image::{DynamicImage, GenericImageView};
+use kiddo::{ImmutableKdTree, SquaredEuclidean};
+use serde::{Deserialize, Serialize};
+use std::num::NonZeroUsize;
+
+/// Sub-grid layout used to describe a tile or target cell.
+/// Each region contributes one color, so a 3x3 grid yields a 27-dimensional vector.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DescriptorGrid {
+    #[default]
+    #[serde(rename = "1x1")]
+    Single,
+    #[serde(rename = "2x2")]
+    Grid2x2,
+    #[serde(rename = "3x3")]
+    Grid3x3,
+}
+
+impl DescriptorGrid {
+    /// Number of regions along each axis.
+    #[inline]
+    #[must_use]
+    pub fn side(self) -> u32 {
+        match self {
+            DescriptorGrid::Single => 1,
+            DescriptorGrid::Grid2x2 => 2,
+            DescriptorGrid::Grid3x3 => 3,
+        }
+    }
+
+    /// Total number of regions in the grid.
+    #[inline]
+    #[must_use]
+    pub fn regions(self) -> usize {
+        (self.side() * self.side()) as usize
+    }
+
+    /// Length of the descriptor vector (three channels per region).
+    #[inline]
+    #[must_use]
+    pub fn dimensions(self) -> usize {
+        self.regions() * 3
+    }
+}
+
+/// Computes the region-averaged color descriptor of an image.
+/// Regions use the Gaussian mask weights that fall inside them, normalized per region.
+#[must_use]
+pub(crate) fn compute_descriptor(
+    img: &DynamicImage,
+    mask: &GaussianMask,
+    space: ColorSpace,
+    grid: DescriptorGrid,
+) -> Vec<f64> {
+    let (width, height) = img.dimensions();
+    let side = grid.side();
+    let weights = mask.weights();
+
+    // Accumulate per region: channel sums followed by total weight
+    let mut sums = vec![[0.0f64; 4]; grid.regions()];
+    for (i, (x, y, pixel)) in img.pixels().enumerate() {
+        let region_x = (x * side / width).min(side - 1);
+        let region_y = (y * side / height).min(side - 1);
+        let acc = &mut sums[(region_y * side + region_x) as usize];
+        let weight = weights[i];
+        for (channel, &value) in acc.iter_mut().zip(pixel.0.iter().take(3)) {
+            *channel += match space {
+                ColorSpace::Srgb => value as f64,
+                _ => srgb_u8_to_linear(value),
+            } * weight;
+        }
+        acc[3] += weight;
+    }
+
+    sums.iter()
+        .flat_map(|acc| {
+            let total = acc[3].max(f64::MIN_POSITIVE);
+            let avg = [acc[0] / total, acc[1] / total, acc[2] / total];
+            match space {
+                ColorSpace::Srgb => avg,
+                _ => space.from_linear(avg),
+            }
+        })
+        .collect()
+}
+
+/// KD-tree over tile descriptors. The tree dimension is fixed at compile time,
+/// so each supported grid gets its own variant.
+pub(crate) enum DescriptorIndex {
+    Single(Box<ImmutableKdTree<f64, 3>>),
+    Grid2x2(Box<ImmutableKdTree<f64, 12>>),
+    Grid3x3(Box<ImmutableKdTree<f64, 27>>),
+}
+
+fn to_points<const K: usize>(descriptors: &[Vec<f64>]) -> Vec<[f64; K]> {
+    descriptors
+        .iter()
+        .map(|d| {
+            let mut point = [0.0; K];
+            point.copy_from_slice(d);
+            point
+        })
+        .collect()
+}
+
+fn query_tree<const K: usize>(
+    tree: &ImmutableKdTree<f64, K>,
+    query: &[f64],
+    k: NonZeroUsize,
+) -> Vec<(usize, f64)> {
+    let mut point = [0.0; K];
+    point.copy_from_slice(query);
+    tree.nearest_n::<SquaredEuclidean>(&point, k)
+        .into_iter()
+        .map(|n| (n.item as usize, n.distance))
+        .collect()
+}
+
+impl DescriptorIndex {
+    /// Builds an index for descriptors of the given grid layout.
+    #[must_use]
+    pub fn build(grid: DescriptorGrid, descriptors: &[Vec<f64>]) -> Self {
+        match grid {
+            DescriptorGrid::Single => Self::Single(Box::new(ImmutableKdTree::new_from_slice(
+                &to_points(descriptors),
+            ))),
+            DescriptorGrid::Grid2x2 => Self::Grid2x2(Box::new(ImmutableKdTree::new_from_slice(
+                &to_points(descriptors),
+            ))),
+            DescriptorGrid::Grid3x3 => Self::Grid3x3(Box::new(ImmutableKdTree::new_from_slice(
+                &to_points(descriptors),
+            ))),
+        }
+    }
+
+    /// Returns the `k` nearest items as `(index, squared distance)` pairs, nearest first.
+    #[must_use]
+    pub fn nearest_n(&self, query: &[f64], k: NonZeroUsize) -> Vec<(usize, f64)> {
+        match self {
+            Self::Single(tree) => query_tree(tree, query, k),
+            Self::Grid2x2(tree) => query_tree(tree, query, k),
+            Self::Grid3x3(tree) => query_tree(tree, query, k),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Rgba};
+
+    fn split_image(size: u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(size, size, |_x, y| {
+            if y < size / 2 {
+                Rgba([0, 0, 0, 255])
+            } else {
+                Rgba([255, 255, 255, 255])
+            }
+        }))
+    }
+
+    #[test]
+    fn compute_descriptor_preserves_region_structure() {
+        let mask = GaussianMask::new(8, 0.0);
+        let descriptor = compute_descriptor(
+            &split_image(8),
+            &mask,
+            ColorSpace::Srgb,
+            DescriptorGrid::Grid2x2,
+        );
+
+        assert_eq!(descriptor.len(), DescriptorGrid::Grid2x2.dimensions());
+        assert_eq!(&descriptor[0..3], &[0.0, 0.0, 0.0]);
+        assert_eq!(&descriptor[9..12], &[255.0, 255.0, 255.0]);
+    }
+
+    #[test]
+    fn single_region_descriptor_is_the_average_color() {
+        let mask = GaussianMask::new(8, 0.0);
+        let descriptor = compute_descriptor(
+            &split_image(8),
+            &mask,
+            ColorSpace::Srgb,
+            DescriptorGrid::Single,
+        );
+
+        assert_eq!(descriptor, vec![127.5, 127.5, 127.5]);
+    }
+
+    #[test]
+    fn descriptor_index_returns_nearest_first() {
+        let descriptors = vec![vec![0.0; 12], vec![100.0; 12], vec![200.0; 12]];
+        let index = DescriptorIndex::build(DescriptorGrid::Grid2x2, &descriptors);
+
+        let nearest = index.nearest_n(&[190.0; 12], NonZeroUsize::new(2).unwrap());
+
+        assert_eq!(nearest[0].0, 2);
+        assert_eq!(nearest[1].0, 1);
+    }
+}
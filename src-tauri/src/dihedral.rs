@@ -0,0 +1,168 @@
+use image::{imageops, RgbaImage};
+use serde::{Deserialize, Serialize};
+
+/// One of the eight rotations and mirrorings of a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Dihedral {
+    #[default]
+    Identity,
+    /// Quarter turns clockwise.
+    Rotate90,
+    Rotate180,
+    Rotate270,
+    /// Mirrored left to right.
+    FlipHorizontal,
+    /// Mirrored top to bottom.
+    FlipVertical,
+    /// Mirrored along the diagonal from the top-left corner.
+    Transpose,
+    /// Mirrored along the diagonal from the top-right corner.
+    Transverse,
+}
+
+impl Dihedral {
+    /// Whether the transform keeps width and height, so rectangular cells can use it.
+    #[inline]
+    #[must_use]
+    pub fn keeps_shape(self) -> bool {
+        matches!(
+            self,
+            Dihedral::Identity
+                | Dihedral::Rotate180
+                | Dihedral::FlipHorizontal
+                | Dihedral::FlipVertical
+        )
+    }
+
+    /// Name of the transform as serialized.
+    #[must_use]
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Dihedral::Identity => "identity",
+            Dihedral::Rotate90 => "rotate90",
+            Dihedral::Rotate180 => "rotate180",
+            Dihedral::Rotate270 => "rotate270",
+            Dihedral::FlipHorizontal => "flip_horizontal",
+            Dihedral::FlipVertical => "flip_vertical",
+            Dihedral::Transpose => "transpose",
+            Dihedral::Transverse => "transverse",
+        }
+    }
+
+    /// Returns the transformed image; the identity hands back `img` unchanged.
+    #[must_use]
+    pub fn apply(self, img: RgbaImage) -> RgbaImage {
+        match self {
+            Dihedral::Identity => img,
+            Dihedral::Rotate90 => imageops::rotate90(&img),
+            Dihedral::Rotate180 => imageops::rotate180(&img),
+            Dihedral::Rotate270 => imageops::rotate270(&img),
+            Dihedral::FlipHorizontal => imageops::flip_horizontal(&img),
+            Dihedral::FlipVertical => imageops::flip_vertical(&img),
+            Dihedral::Transpose => imageops::flip_horizontal(&imageops::rotate90(&img)),
+            Dihedral::Transverse => imageops::flip_vertical(&imageops::rotate90(&img)),
+        }
+    }
+}
+
+/// Rotated and mirrored copies of each tile that are matched besides the original.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TileTransforms {
+    /// Tiles are only used as photographed.
+    #[default]
+    Original,
+    /// Adds the left-to-right mirror image.
+    Mirror,
+    /// Adds the three other quarter turns.
+    Rotate,
+    /// Adds all seven other rotations and mirror images.
+    All,
+}
+
+impl TileTransforms {
+    /// Transforms matched besides the identity. Rectangular cells only get
+    /// those that keep their shape.
+    #[must_use]
+    pub fn variants(self, square: bool) -> Vec<Dihedral> {
+        let transforms: &[Dihedral] = match self {
+            TileTransforms::Original => &[],
+            TileTransforms::Mirror => &[Dihedral::FlipHorizontal],
+            TileTransforms::Rotate => {
+                &[Dihedral::Rotate90, Dihedral::Rotate180, Dihedral::Rotate270]
+            }
+            TileTransforms::All => &[
+                Dihedral::Rotate90,
+                Dihedral::Rotate180,
+                Dihedral::Rotate270,
+                Dihedral::FlipHorizontal,
+                Dihedral::FlipVertical,
+                Dihedral::Transpose,
+                Dihedral::Transverse,
+            ],
+        };
+        transforms
+            .iter()
+            .copied()
+            .filter(|transform| square || transform.keeps_shape())
+            .collect()
+    }
+}
+
+/// Matching features of a tile drawn with a transform other than the identity.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TileVariant {
+    pub transform: Dihedral,
+    pub descriptor: Vec<f64>,
+    pub structure: Vec<f64>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+    use std::collections::HashSet;
+
+    #[test]
+    fn transforms_are_the_eight_symmetries_of_a_square() {
+        // Every pixel of a 3x3 image is distinct, so every symmetry gives a different image
+        let img = RgbaImage::from_fn(3, 3, |x, y| Rgba([x as u8, y as u8, 0, 255]));
+        let all: Vec<Dihedral> = std::iter::once(Dihedral::Identity)
+            .chain(TileTransforms::All.variants(true))
+            .collect();
+        let images: HashSet<Vec<u8>> = all
+            .iter()
+            .map(|transform| transform.apply(img.clone()).into_raw())
+            .collect();
+        assert_eq!(images.len(), 8);
+
+        let transposed = Dihedral::Transpose.apply(img.clone());
+        assert_eq!(transposed.get_pixel(2, 0).0, [0, 2, 0, 255]);
+        let transversed = Dihedral::Transverse.apply(img);
+        assert_eq!(transversed.get_pixel(0, 0).0, [2, 2, 0, 255]);
+    }
+
+    #[test]
+    fn rectangular_cells_only_get_transforms_that_keep_their_shape() {
+        assert_eq!(
+            TileTransforms::All.variants(false),
+            [
+                Dihedral::Rotate180,
+                Dihedral::FlipHorizontal,
+                Dihedral::FlipVertical
+            ]
+        );
+        assert_eq!(
+            TileTransforms::Rotate.variants(false),
+            [Dihedral::Rotate180]
+        );
+        assert!(TileTransforms::Original.variants(true).is_empty());
+
+        let wide = RgbaImage::new(4, 3);
+        for transform in TileTransforms::All.variants(false) {
+            assert_eq!(transform.apply(wide.clone()).dimensions(), (4, 3));
+        }
+        assert_eq!(Dihedral::Rotate90.apply(wide).dimensions(), (3, 4));
+    }
+}
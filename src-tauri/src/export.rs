@@ -0,0 +1,358 @@
+use crate::errors::{AppError, AppResult};
+use image::{
+    codecs::{jpeg::JpegEncoder, webp::WebPEncoder},
+    DynamicImage, ExtendedColorType, ImageEncoder, RgbaImage,
+};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Seek, Write};
+use std::path::Path;
+use tiff::encoder::{colortype, TiffEncoder, TiffKind};
+
+/// Default JPEG quality when none is given.
+pub const DEFAULT_JPEG_QUALITY: u8 = 90;
+
+/// Size of the PNG IDAT chunks written by the streaming encoder.
+const PNG_CHUNK_BYTES: usize = 1 << 20;
+/// Raw RGBA size above which TIFF output switches to 64-bit BigTIFF offsets.
+const BIGTIFF_THRESHOLD_BYTES: u64 = 0xF000_0000;
+
+/// Receives consecutive horizontal strips of an image, top to bottom.
+pub type StripSink<'a> = dyn FnMut(&RgbaImage) -> AppResult<()> + 'a;
+
+/// File format for mosaics written to disk.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    #[default]
+    Png,
+    Jpeg,
+    /// Lossless WebP.
+    Webp,
+    Tiff,
+}
+
+impl OutputFormat {
+    /// Whether the format can be written strip by strip without the whole image in memory.
+    #[must_use]
+    pub fn supports_streaming(self) -> bool {
+        matches!(self, OutputFormat::Png | OutputFormat::Tiff)
+    }
+}
+
+/// Encoding options for a saved mosaic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ExportOptions {
+    pub format: OutputFormat,
+    /// JPEG quality (1-100). Ignored by the other formats.
+    pub jpeg_quality: u8,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            format: OutputFormat::Png,
+            jpeg_quality: DEFAULT_JPEG_QUALITY,
+        }
+    }
+}
+
+impl ExportOptions {
+    pub fn validate(&self) -> AppResult<()> {
+        if !(1..=100).contains(&self.jpeg_quality) {
+            return Err(AppError::Config(format!(
+                "Invalid jpeg_quality {}. Expected value in range 1..=100",
+                self.jpeg_quality
+            )));
+        }
+
+        Ok(())
+    }
+}
+
+fn encode_error(err: impl std::fmt::Display) -> AppError {
+    AppError::Image(format!("Failed to encode image: {}", err))
+}
+
+/// Encodes an image into `writer` using the requested format.
+/// PNG and TIFF go through the strip encoder, so the result is byte-identical
+/// to streaming the same pixels with `encode_strips`.
+pub fn encode_image<W: Write + Seek>(
+    img: &RgbaImage,
+    writer: W,
+    options: &ExportOptions,
+) -> AppResult<()> {
+    let (width, height) = img.dimensions();
+    let result = match options.format {
+        OutputFormat::Png | OutputFormat::Tiff => {
+            return encode_strips(writer, width, height, options, |sink| sink(img));
+        }
+        OutputFormat::Jpeg => {
+            // JPEG has no alpha channel
+            let rgb = DynamicImage::ImageRgba8(img.clone()).into_rgb8();
+            JpegEncoder::new_with_quality(writer, options.jpeg_quality).write_image(
+                &rgb,
+                width,
+                height,
+                ExtendedColorType::Rgb8,
+            )
+        }
+        OutputFormat::Webp => WebPEncoder::new_lossless(writer).write_image(
+            img,
+            width,
+            height,
+            ExtendedColorType::Rgba8,
+        ),
+    };
+
+    result.map_err(encode_error)
+}
+
+/// Encodes an image whose rows are produced in strips, keeping only one
+/// strip in memory. `produce` must pass every row exactly once, top to bottom,
+/// in strips of any height. Only formats that support streaming are accepted.
+pub fn encode_strips<W, P>(
+    writer: W,
+    width: u32,
+    height: u32,
+    options: &ExportOptions,
+    produce: P,
+) -> AppResult<()>
+where
+    W: Write + Seek,
+    P: FnOnce(&mut StripSink) -> AppResult<()>,
+{
+    match options.format {
+        OutputFormat::Png => encode_png_strips(writer, width, height, produce),
+        OutputFormat::Tiff if width as u64 * height as u64 * 4 > BIGTIFF_THRESHOLD_BYTES => {
+            let encoder = TiffEncoder::new_big(writer).map_err(encode_error)?;
+            encode_tiff_strips(encoder, width, height, produce)
+        }
+        OutputFormat::Tiff => {
+            let encoder = TiffEncoder::new(writer).map_err(encode_error)?;
+            encode_tiff_strips(encoder, width, height, produce)
+        }
+        format => Err(AppError::Config(format!(
+            "{:?} output cannot be streamed. Use PNG or TIFF",
+            format
+        ))),
+    }
+}
+
+fn encode_png_strips<W, P>(writer: W, width: u32, height: u32, produce: P) -> AppResult<()>
+where
+    W: Write,
+    P: FnOnce(&mut StripSink) -> AppResult<()>,
+{
+    let mut encoder = png::Encoder::new(writer, width, height);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_compression(png::Compression::Balanced);
+    encoder.set_filter(png::Filter::Adaptive);
+
+    let mut png_writer = encoder.write_header().map_err(encode_error)?;
+    let mut stream = png_writer
+        .stream_writer_with_size(PNG_CHUNK_BYTES)
+        .map_err(encode_error)?;
+    // Rows are filtered and compressed as they arrive, so strip boundaries
+    // never show up in the output
+    produce(&mut |strip: &RgbaImage| {
+        check_strip_width(strip, width)?;
+        stream.write_all(strip.as_raw()).map_err(encode_error)
+    })?;
+    stream.finish().map_err(encode_error)?;
+    png_writer.finish().map_err(encode_error)
+}
+
+fn encode_tiff_strips<W, K, P>(
+    mut encoder: TiffEncoder<W, K>,
+    width: u32,
+    height: u32,
+    produce: P,
+) -> AppResult<()>
+where
+    W: Write + Seek,
+    K: TiffKind,
+    P: FnOnce(&mut StripSink) -> AppResult<()>,
+{
+    let mut image = encoder
+        .new_image::<colortype::RGBA8>(width, height)
+        .map_err(encode_error)?;
+    // TIFF strips have a fixed row count, independent of the incoming strips
+    let mut pending: Vec<u8> = Vec::new();
+    produce(&mut |strip: &RgbaImage| {
+        check_strip_width(strip, width)?;
+        let mut data = strip.as_raw().as_slice();
+        loop {
+            let needed = image.next_strip_sample_count() as usize;
+            if needed == 0 || pending.len() + data.len() < needed {
+                break;
+            }
+            if pending.is_empty() {
+                image.write_strip(&data[..needed]).map_err(encode_error)?;
+                data = &data[needed..];
+            } else {
+                let take = needed - pending.len();
+                pending.extend_from_slice(&data[..take]);
+                image.write_strip(&pending).map_err(encode_error)?;
+                pending.clear();
+                data = &data[take..];
+            }
+        }
+        pending.extend_from_slice(data);
+        Ok(())
+    })?;
+
+    if image.next_strip_sample_count() != 0 || !pending.is_empty() {
+        return Err(AppError::Image(format!(
+            "Failed to encode image: expected {} rows of pixel data",
+            height
+        )));
+    }
+    image.finish().map_err(encode_error)
+}
+
+fn check_strip_width(strip: &RgbaImage, width: u32) -> AppResult<()> {
+    if strip.width() != width {
+        return Err(AppError::Image(format!(
+            "Failed to encode image: strip is {} pixels wide, expected {}",
+            strip.width(),
+            width
+        )));
+    }
+    Ok(())
+}
+
+/// Writes an image to `path` and returns the size of the written file in bytes.
+/// A partially written file is removed if encoding fails.
+pub fn write_image(img: &RgbaImage, path: &Path, options: &ExportOptions) -> AppResult<u64> {
+    write_file(path, options, |writer| encode_image(img, writer, options))
+}
+
+/// Streams strips produced by `produce` into a file at `path`.
+/// See `encode_strips` for the contract of `produce`.
+pub fn write_image_strips<P>(
+    path: &Path,
+    width: u32,
+    height: u32,
+    options: &ExportOptions,
+    produce: P,
+) -> AppResult<u64>
+where
+    P: FnOnce(&mut StripSink) -> AppResult<()>,
+{
+    write_file(path, options, |writer| {
+        encode_strips(writer, width, height, options, produce)
+    })
+}
+
+fn write_file(
+    path: &Path,
+    options: &ExportOptions,
+    encode: impl FnOnce(&mut BufWriter<File>) -> AppResult<()>,
+) -> AppResult<u64> {
+    options.validate()?;
+
+    let file = File::create(path)
+        .map_err(|e| AppError::Io(format!("Failed to create {}: {}", path.display(), e)))?;
+    let mut writer = BufWriter::new(file);
+
+    let written = encode(&mut writer).and_then(|()| {
+        writer
+            .flush()
+            .map_err(|e| AppError::Io(format!("Failed to write {}: {}", path.display(), e)))
+    });
+    if let Err(e) = written {
+        drop(writer);
+        let _ = std::fs::remove_file(path);
+        return Err(e);
+    }
+
+    Ok(std::fs::metadata(path)?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir;
+    use image::{ImageBuffer, ImageReader, Rgba};
+
+    #[test]
+    fn write_image_round_trips_every_format() {
+        let img: RgbaImage = ImageBuffer::from_fn(16, 8, |x, _y| Rgba([x as u8 * 16, 0, 0, 255]));
+        let base = temp_dir("export");
+
+        for (format, ext) in [
+            (OutputFormat::Png, "png"),
+            (OutputFormat::Jpeg, "jpg"),
+            (OutputFormat::Webp, "webp"),
+            (OutputFormat::Tiff, "tiff"),
+        ] {
+            let path = base.with_extension(ext);
+            let options = ExportOptions {
+                format,
+                ..ExportOptions::default()
+            };
+
+            let size = write_image(&img, &path, &options).unwrap();
+            let decoded = ImageReader::open(&path).unwrap().decode().unwrap();
+
+            assert_eq!(size, std::fs::metadata(&path).unwrap().len());
+            assert_eq!((decoded.width(), decoded.height()), (16, 8));
+            std::fs::remove_file(path).unwrap();
+        }
+    }
+
+    #[test]
+    fn strip_encoding_matches_whole_image_encoding() {
+        let img: RgbaImage = ImageBuffer::from_fn(300, 37, |x, y| {
+            Rgba([(x * 7) as u8, (y * 13) as u8, 90, 255])
+        });
+
+        for format in [OutputFormat::Png, OutputFormat::Tiff] {
+            let options = ExportOptions {
+                format,
+                ..ExportOptions::default()
+            };
+            let mut whole = std::io::Cursor::new(Vec::new());
+            encode_image(&img, &mut whole, &options).unwrap();
+
+            let mut strips = std::io::Cursor::new(Vec::new());
+            encode_strips(&mut strips, 300, 37, &options, |sink| {
+                for top in (0..37).step_by(5) {
+                    let rows = 5.min(37 - top);
+                    sink(&image::imageops::crop_imm(&img, 0, top, 300, rows).to_image())?;
+                }
+                Ok(())
+            })
+            .unwrap();
+
+            assert_eq!(whole.into_inner(), strips.into_inner(), "{:?}", format);
+        }
+    }
+
+    #[test]
+    fn strip_encoding_rejects_missing_rows() {
+        let options = ExportOptions::default();
+        let strip: RgbaImage = ImageBuffer::new(4, 2);
+
+        let result = encode_strips(std::io::Cursor::new(Vec::new()), 4, 4, &options, |sink| {
+            sink(&strip)
+        });
+
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn export_options_reject_zero_jpeg_quality() {
+        let options = ExportOptions {
+            format: OutputFormat::Jpeg,
+            jpeg_quality: 0,
+        };
+
+        assert!(matches!(
+            options.validate(),
+            Err(AppError::Config(message)) if message.contains("jpeg_quality")
+        ));
+    }
+}
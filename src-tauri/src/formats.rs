@@ -0,0 +1,254 @@
+use crate::errors::{AppError, AppResult};
+use crate::load_report::{SkipReason, SkippedFile};
+use crate::sources::TileSource;
+use image::ImageFormat;
+use rayon::prelude::*;
+use std::collections::HashSet;
+use std::fs::File;
+use std::io::Read;
+use std::path::{Path, PathBuf};
+
+/// Leading bytes read to recognize a file; enough for every signature `image` knows.
+const SNIFF_BYTES: usize = 32;
+
+/// Extensions of common photo formats `image` does not recognize at all:
+/// phone HEIC/HEIF and camera RAW. They are reported rather than ignored.
+const UNDECODABLE_PHOTO_EXTENSIONS: &[&str] = &[
+    "heic", "heif", "hif", "cr2", "cr3", "crw", "nef", "nrw", "arw", "srf", "sr2", "dng", "raf",
+    "orf", "rw2", "pef", "srw", "x3f", "3fr", "iiq", "raw", "rwl",
+];
+
+/// Whether `path` names a photo format this build has no decoder for.
+fn is_undecodable_photo(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| {
+            UNDECODABLE_PHOTO_EXTENSIONS
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(ext))
+        })
+}
+
+/// Whether this build can decode `format`.
+#[must_use]
+pub fn can_decode(format: ImageFormat) -> bool {
+    match format {
+        // image's own `avif` feature only encodes; decoding needs dav1d
+        ImageFormat::Avif => cfg!(feature = "avif"),
+        format => format.reading_enabled(),
+    }
+}
+
+/// Every format this build can decode.
+pub fn decodable_formats() -> impl Iterator<Item = ImageFormat> {
+    ImageFormat::all().filter(|&format| can_decode(format))
+}
+
+/// File extensions of every decodable format, for file pickers.
+#[must_use]
+pub fn decodable_extensions() -> Vec<&'static str> {
+    decodable_formats()
+        .flat_map(|format| format.extensions_str().iter().copied())
+        .collect()
+}
+
+/// Recognizes an image from its leading bytes, so mislabeled or extensionless
+/// files are found too. Formats without a signature (TGA) fall back to the extension.
+pub fn detect_format(path: &Path) -> std::io::Result<Option<ImageFormat>> {
+    let mut header = Vec::with_capacity(SNIFF_BYTES);
+    File::open(path)?
+        .take(SNIFF_BYTES as u64)
+        .read_to_end(&mut header)?;
+
+    Ok(image::guess_format(&header)
+        .ok()
+        .or_else(|| ImageFormat::from_path(path).ok()))
+}
+
+/// Image files found in the tile sources, and the entries that could not be used.
+#[derive(Debug, Default)]
+pub struct ImageScan {
+    /// Files in one of the requested formats, in directory order.
+    pub files: Vec<PathBuf>,
+    /// Unreadable entries and images this build cannot decode, including phone
+    /// and camera RAW photos. Files that are not images, or images outside the
+    /// requested formats, are not listed.
+    pub skipped: Vec<SkippedFile>,
+}
+
+/// Finds files in `sources` holding an image in one of `formats`. A file
+/// reached through several overlapping sources is listed once, under the
+/// path it was first found at, even when the sources spell the folder
+/// differently or reach it through a symlink.
+pub fn find_image_files(sources: &[TileSource], formats: &FormatSet) -> AppResult<ImageScan> {
+    let mut scan = ImageScan::default();
+
+    // Collect entries first to avoid holding WalkDir in the parallel bridge
+    let mut seen = HashSet::new();
+    let mut entries: Vec<PathBuf> = Vec::new();
+    for source in sources {
+        let (files, skipped) = source.list_files()?;
+        entries.extend(
+            files
+                .into_iter()
+                .filter(|path| seen.insert(path.canonicalize().unwrap_or_else(|_| path.clone()))),
+        );
+        scan.skipped.extend(skipped);
+    }
+
+    let checked: Vec<Result<Option<PathBuf>, SkippedFile>> = entries
+        .into_par_iter()
+        .map(|path| match detect_format(&path) {
+            Err(e) => Err(SkippedFile::from_io(path, &e)),
+            Ok(Some(format)) if !can_decode(format) => Err(SkippedFile::new(
+                path,
+                SkipReason::Unsupported,
+                format!("{:?} images cannot be decoded by this build", format),
+            )),
+            Ok(Some(format)) if formats.contains(format) => Ok(Some(path)),
+            Ok(None) if is_undecodable_photo(&path) => {
+                let ext = path.extension().unwrap_or_default().to_string_lossy();
+                let message = format!(
+                    "{} photos cannot be decoded by this build",
+                    ext.to_uppercase()
+                );
+                Err(SkippedFile::new(path, SkipReason::Unsupported, message))
+            }
+            Ok(_) => Ok(None),
+        })
+        .collect();
+
+    for result in checked {
+        match result {
+            Ok(Some(path)) => scan.files.push(path),
+            Ok(None) => {}
+            Err(skipped) => scan.skipped.push(skipped),
+        }
+    }
+    Ok(scan)
+}
+
+/// Image formats a tile library accepts. Empty accepts every decodable format.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct FormatSet {
+    formats: Vec<ImageFormat>,
+}
+
+impl FormatSet {
+    /// Builds a set from format names or extensions such as "jpeg", "png" or "tif".
+    pub fn parse<S: AsRef<str>>(names: &[S]) -> AppResult<Self> {
+        let mut formats = Vec::with_capacity(names.len());
+        for name in names {
+            let name = name.as_ref().trim();
+            let format = ImageFormat::from_extension(name)
+                .ok_or_else(|| AppError::Config(format!("Unknown image format \"{}\"", name)))?;
+            if !can_decode(format) {
+                return Err(AppError::Config(format!(
+                    "Image format \"{}\" cannot be decoded by this build",
+                    name
+                )));
+            }
+            if !formats.contains(&format) {
+                formats.push(format);
+            }
+        }
+        Ok(Self { formats })
+    }
+
+    #[must_use]
+    pub fn contains(&self, format: ImageFormat) -> bool {
+        can_decode(format) && (self.formats.is_empty() || self.formats.contains(&format))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir;
+    use image::{ImageBuffer, Rgba, RgbaImage};
+
+    #[test]
+    fn detect_format_reads_content_not_extension() {
+        let dir = temp_dir("formats");
+        std::fs::create_dir_all(&dir).unwrap();
+        let img: RgbaImage = ImageBuffer::from_pixel(4, 4, Rgba([1, 2, 3, 255]));
+        img.save_with_format(dir.join("photo.jpg"), ImageFormat::Png)
+            .unwrap();
+        img.save_with_format(dir.join("no-extension"), ImageFormat::Bmp)
+            .unwrap();
+        std::fs::write(dir.join("broken.png"), b"not really a png").unwrap();
+        std::fs::write(dir.join("notes.txt"), b"plain text").unwrap();
+
+        let detect = |name: &str| detect_format(&dir.join(name)).unwrap();
+
+        assert_eq!(detect("photo.jpg"), Some(ImageFormat::Png));
+        assert_eq!(detect("no-extension"), Some(ImageFormat::Bmp));
+        // Unrecognized content keeps the extension's format, so decoding reports the damage
+        assert_eq!(detect("broken.png"), Some(ImageFormat::Png));
+        assert_eq!(detect("notes.txt"), None);
+        assert!(detect_format(&dir.join("missing.png")).is_err());
+
+        let sources = [TileSource::new(&dir)];
+        let scan = find_image_files(&sources, &FormatSet::default()).unwrap();
+        assert_eq!(scan.files.len(), 3);
+        assert!(scan.skipped.is_empty());
+        assert_eq!(
+            find_image_files(&sources, &FormatSet::parse(&["bmp"]).unwrap())
+                .unwrap()
+                .files,
+            vec![dir.join("no-extension")]
+        );
+        // Overlapping sources list each file once, however the folder is spelled
+        let respelled = dir.join("..").join(dir.file_name().unwrap());
+        let overlapping = [
+            TileSource::new(&dir),
+            TileSource::new(&dir),
+            TileSource::new(&respelled),
+        ];
+        let files = find_image_files(&overlapping, &FormatSet::default())
+            .unwrap()
+            .files;
+        assert_eq!(files.len(), 3);
+        assert!(files.iter().all(|path| path.starts_with(&dir)));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[cfg(not(feature = "avif"))]
+    #[test]
+    fn find_image_files_reports_undecodable_images() {
+        let dir = temp_dir("formats-avif");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("photo.avif"), b"\0\0\0\x1cftypavif\0\0\0\0").unwrap();
+        std::fs::write(dir.join("photo.heic"), b"\0\0\0\x18ftypheic\0\0\0\0").unwrap();
+
+        let scan = find_image_files(&[TileSource::new(&dir)], &FormatSet::default()).unwrap();
+
+        assert!(scan.files.is_empty());
+        let mut skipped = scan.skipped;
+        skipped.sort_by(|a, b| a.path.cmp(&b.path));
+        assert_eq!(skipped.len(), 2);
+        assert_eq!(skipped[0].path, dir.join("photo.avif"));
+        assert_eq!(skipped[1].path, dir.join("photo.heic"));
+        assert!(skipped
+            .iter()
+            .all(|skipped| skipped.reason == SkipReason::Unsupported));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn format_set_parses_names_and_extensions() {
+        let set = FormatSet::parse(&["JPG", "webp", "tif"]).unwrap();
+
+        assert!(set.contains(ImageFormat::Jpeg));
+        assert!(set.contains(ImageFormat::WebP));
+        assert!(set.contains(ImageFormat::Tiff));
+        assert!(!set.contains(ImageFormat::Png));
+        assert!(FormatSet::default().contains(ImageFormat::Gif));
+        assert!(matches!(
+            FormatSet::parse(&["psd"]),
+            Err(AppError::Config(message)) if message.contains("psd")
+        ));
+    }
+}
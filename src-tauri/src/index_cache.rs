@@ -0,0 +1,267 @@
+use crate::crop::{CropRect, CropStrategy};
+use crate::dedupe::PerceptualHashes;
+use crate::dihedral::{TileTransforms, TileVariant};
+use crate::errors::{AppError, AppResult};
+use crate::LibraryConfig;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, Metadata};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+/// Bumped whenever the cached features change meaning, so old files are ignored.
+const CACHE_VERSION: u32 = 5;
+
+/// Size and modification time of a tile file when it was analyzed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub(crate) struct FileStamp {
+    len: u64,
+    modified_secs: u64,
+    modified_nanos: u32,
+}
+
+impl FileStamp {
+    pub(crate) fn from_metadata(metadata: &Metadata) -> Self {
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|time| time.duration_since(UNIX_EPOCH).ok())
+            .unwrap_or_default();
+        Self {
+            len: metadata.len(),
+            modified_secs: modified.as_secs(),
+            modified_nanos: modified.subsec_nanos(),
+        }
+    }
+}
+
+/// Features computed for one tile file.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub(crate) struct CachedTile {
+    pub stamp: FileStamp,
+    pub color: [f64; 3],
+    pub descriptor: Vec<f64>,
+    pub structure: Vec<f64>,
+    pub hashes: PerceptualHashes,
+    /// Analyzed as a portrait cell by an orientation-aware library.
+    pub portrait: bool,
+    /// Crop chosen by the library's crop strategy, if not centered.
+    pub crop: Option<CropRect>,
+    /// Features of the rotated and mirrored copies the library matches.
+    pub variants: Vec<TileVariant>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    settings: String,
+    entries: HashMap<PathBuf, CachedTile>,
+}
+
+/// Everything besides the file itself that the cached features depend on.
+/// Source patterns only choose files, so editing them keeps the cache.
+fn settings_key(config: &LibraryConfig) -> String {
+    let roots: Vec<String> = config
+        .sources
+        .iter()
+        .map(|source| source.dir.display().to_string())
+        .collect();
+    // Only clipped shapes change tile features, so square and brick layouts share entries
+    let shape = match config.cell_shape.is_clipped() {
+        true => "clipped",
+        false => "unclipped",
+    };
+    let cell = config.cell_size();
+    let oriented = match config.orientation_aware {
+        true => "|oriented",
+        false => "",
+    };
+    let crop = match config.crop_strategy {
+        CropStrategy::Center => String::new(),
+        strategy => format!("|{:?}", strategy),
+    };
+    let transforms = match config.tile_transforms {
+        TileTransforms::Original => String::new(),
+        transforms => format!("|{:?}", transforms),
+    };
+    format!(
+        "{}|{}|{:016x}|{:?}|{:?}|{}|{}x{}{}{}{}",
+        roots.join(";"),
+        config.tile_size,
+        config.sigma_divisor.to_bits(),
+        config.color_space,
+        config.descriptor_grid,
+        shape,
+        cell.width,
+        cell.height,
+        oriented,
+        crop,
+        transforms
+    )
+}
+
+/// 64-bit FNV-1a, used for file names that stay stable across builds.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, &byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01b3)
+    })
+}
+
+/// Persistent per-file features of a tile library. Each combination of source
+/// directories and analysis settings gets its own file inside `cache_dir`, so
+/// switching settings back and forth keeps earlier results.
+pub(crate) struct IndexCache {
+    path: PathBuf,
+    settings: String,
+    entries: HashMap<PathBuf, CachedTile>,
+}
+
+impl IndexCache {
+    /// Opens the cache for a library. A missing, unreadable or outdated
+    /// cache file starts out empty instead of failing.
+    pub(crate) fn open(cache_dir: &Path, config: &LibraryConfig) -> Self {
+        let settings = settings_key(config);
+        let path = cache_dir.join(format!("tiles-{:016x}.bin", fnv1a(settings.as_bytes())));
+        let entries = File::open(&path)
+            .ok()
+            .and_then(|file| bincode::deserialize_from::<_, CacheFile>(BufReader::new(file)).ok())
+            .filter(|cache| cache.version == CACHE_VERSION && cache.settings == settings)
+            .map(|cache| cache.entries)
+            .unwrap_or_default();
+
+        Self {
+            path,
+            settings,
+            entries,
+        }
+    }
+
+    /// Returns the cached features of `path` if the file is unchanged.
+    pub(crate) fn get(&self, path: &Path, stamp: FileStamp) -> Option<&CachedTile> {
+        self.entries.get(path).filter(|entry| entry.stamp == stamp)
+    }
+
+    /// Replaces the cached entries, dropping files that no longer exist,
+    /// and writes the cache if anything changed.
+    pub(crate) fn update(&mut self, entries: HashMap<PathBuf, CachedTile>) -> AppResult<()> {
+        if entries == self.entries {
+            return Ok(());
+        }
+        self.entries = entries;
+        self.write()
+    }
+
+    fn write(&self) -> AppResult<()> {
+        let write_error = |e: &dyn std::fmt::Display| {
+            AppError::Io(format!("Failed to write {}: {}", self.path.display(), e))
+        };
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent).map_err(|e| write_error(&e))?;
+        }
+
+        // Written next to the cache and renamed, so readers never see half a file
+        let temp = self.path.with_extension("tmp");
+        let file = File::create(&temp).map_err(|e| write_error(&e))?;
+        let mut writer = BufWriter::new(file);
+        let cache = CacheFile {
+            version: CACHE_VERSION,
+            settings: self.settings.clone(),
+            entries: self.entries.clone(),
+        };
+        bincode::serialize_into(&mut writer, &cache).map_err(|e| write_error(&e))?;
+        writer.flush().map_err(|e| write_error(&e))?;
+        drop(writer);
+
+        std::fs::rename(&temp, &self.path).map_err(|e| write_error(&e))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::ColorSpace;
+    use crate::descriptor::DescriptorGrid;
+    use crate::formats::FormatSet;
+    use crate::layout::{CellShape, TileAspect};
+    use crate::sources::TileSource;
+    use crate::temp_dir;
+
+    fn config(tile_size: u32) -> LibraryConfig {
+        LibraryConfig {
+            sources: vec![TileSource::new("/photos")],
+            tile_size,
+            sigma_divisor: 4.0,
+            color_space: ColorSpace::Srgb,
+            descriptor_grid: DescriptorGrid::Single,
+            formats: FormatSet::default(),
+            duplicates: None,
+            cell_shape: CellShape::Square,
+            tile_aspect: TileAspect::default(),
+            orientation_aware: false,
+            crop_strategy: CropStrategy::Center,
+            tile_transforms: TileTransforms::Original,
+        }
+    }
+
+    fn entry(len: u64) -> CachedTile {
+        CachedTile {
+            stamp: FileStamp {
+                len,
+                modified_secs: 1_700_000_000,
+                modified_nanos: 5,
+            },
+            color: [0.1, 0.2, 0.3],
+            descriptor: vec![0.1, 0.2, 0.3],
+            structure: vec![0.5; 64],
+            hashes: PerceptualHashes::default(),
+            portrait: false,
+            crop: None,
+            variants: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn cache_round_trips_and_checks_stamps() {
+        let dir = temp_dir("index-cache");
+        let path = PathBuf::from("/photos/a.jpg");
+
+        let mut cache = IndexCache::open(&dir, &config(32));
+        cache
+            .update(HashMap::from([(path.clone(), entry(100))]))
+            .unwrap();
+
+        let reopened = IndexCache::open(&dir, &config(32));
+        assert_eq!(reopened.get(&path, entry(100).stamp), Some(&entry(100)));
+        assert_eq!(reopened.get(&path, entry(101).stamp), None);
+        // Other settings use a separate file
+        assert!(IndexCache::open(&dir, &config(16)).entries.is_empty());
+        let landscape = LibraryConfig {
+            tile_aspect: TileAspect {
+                width: 4,
+                height: 3,
+            },
+            ..config(32)
+        };
+        assert!(IndexCache::open(&dir, &landscape).entries.is_empty());
+        let salient = LibraryConfig {
+            crop_strategy: CropStrategy::Saliency,
+            ..config(32)
+        };
+        assert!(IndexCache::open(&dir, &salient).entries.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn corrupt_cache_opens_empty() {
+        let dir = temp_dir("index-cache-corrupt");
+        let cache = IndexCache::open(&dir, &config(32));
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(&cache.path, b"not a cache").unwrap();
+
+        assert!(IndexCache::open(&dir, &config(32)).entries.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,760 @@
+use crate::errors::{AppError, AppResult};
+use image::RgbaImage;
+use serde::{Deserialize, Serialize};
+use std::ops::Range;
+
+/// Largest accepted `max_cell_span`.
+pub const MAX_CELL_SPAN: u32 = 16;
+/// Largest accepted term of a `TileAspect`.
+pub const MAX_ASPECT_TERM: u32 = 16;
+/// Cells may be at most this many times longer than they are wide.
+const MAX_ASPECT_RATIO: u32 = 4;
+/// Largest accepted `detail_threshold`. A cell that is half black and half
+/// white has a luma standard deviation of 127.5.
+pub const MAX_DETAIL_THRESHOLD: f64 = 128.0;
+
+/// Settings of the adaptive quadtree layout. The analysis tile size is the
+/// smallest cell; flat regions are covered by larger cells.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct QuadtreeOptions {
+    /// Side of the largest cell in grid cells, a power of two from 2 to 16.
+    pub max_cell_span: u32,
+    /// Luma standard deviation (0-255 levels) above which a cell is split.
+    pub detail_threshold: f64,
+}
+
+impl QuadtreeOptions {
+    pub fn validate(&self) -> AppResult<()> {
+        if !self.max_cell_span.is_power_of_two()
+            || !(2..=MAX_CELL_SPAN).contains(&self.max_cell_span)
+        {
+            return Err(AppError::Config(format!(
+                "Invalid max_cell_span {}. Expected a power of two in range 2..={}",
+                self.max_cell_span, MAX_CELL_SPAN
+            )));
+        }
+        if !self.detail_threshold.is_finite()
+            || !(0.0..=MAX_DETAIL_THRESHOLD).contains(&self.detail_threshold)
+        {
+            return Err(AppError::Config(format!(
+                "Invalid detail_threshold {}. Expected finite value in range 0..={}",
+                self.detail_threshold, MAX_DETAIL_THRESHOLD
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Width-to-height ratio of the cells, such as 4:3 or 3:2. Tile size sets
+/// the longer side and the other follows from the ratio.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileAspect {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Default for TileAspect {
+    fn default() -> Self {
+        Self {
+            width: 1,
+            height: 1,
+        }
+    }
+}
+
+impl TileAspect {
+    pub fn validate(&self) -> AppResult<()> {
+        let terms = 1..=MAX_ASPECT_TERM;
+        let (long, short) = (self.width.max(self.height), self.width.min(self.height));
+        if !terms.contains(&self.width)
+            || !terms.contains(&self.height)
+            || long > short * MAX_ASPECT_RATIO
+        {
+            return Err(AppError::Config(format!(
+                "Invalid tile aspect {}:{}. Expected terms in range 1..={} at most {}:1 apart",
+                self.width, self.height, MAX_ASPECT_TERM, MAX_ASPECT_RATIO
+            )));
+        }
+        Ok(())
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_square(self) -> bool {
+        self.width == self.height
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_landscape(self) -> bool {
+        self.width > self.height
+    }
+
+    /// Cell whose longer side is `size` pixels, the shorter one rounded to the ratio.
+    #[must_use]
+    pub fn cell_size(self, size: u32) -> CellSize {
+        let (long, short) = (self.width.max(self.height), self.width.min(self.height));
+        let short_side = ((size * short + long / 2) / long).max(1);
+        match self.is_landscape() {
+            true => CellSize::new(size, short_side),
+            false => CellSize::new(short_side, size),
+        }
+    }
+}
+
+/// Width and height of a cell in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub struct CellSize {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl CellSize {
+    #[inline]
+    #[must_use]
+    pub const fn new(width: u32, height: u32) -> Self {
+        Self { width, height }
+    }
+
+    #[inline]
+    #[must_use]
+    pub const fn square(side: u32) -> Self {
+        Self::new(side, side)
+    }
+
+    /// This cell repeated `span` times along both axes.
+    #[inline]
+    #[must_use]
+    pub const fn scaled(self, span: u32) -> Self {
+        Self::new(self.width * span, self.height * span)
+    }
+
+    /// A portrait cell of the same height with the ratio turned on its side,
+    /// so a 4:3 cell of 32x24 pixels gives one of 18x24.
+    #[must_use]
+    pub fn portrait(self) -> Self {
+        let width = (self.height * self.height + self.width / 2) / self.width;
+        Self::new(width.max(1), self.height)
+    }
+}
+
+/// Shape of the cells a target is divided into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CellShape {
+    /// Axis-aligned squares in a plain grid.
+    #[default]
+    Square,
+    /// Squares in running bond: every other row is shifted by half a cell.
+    Brick,
+    /// Pointy-top hexagons inscribed in square cells. Every other row is
+    /// shifted by half a cell and rows overlap by a quarter cell, so the
+    /// hexagons interlock without gaps.
+    Hexagon,
+}
+
+impl CellShape {
+    /// Whether every other row is shifted left by half a cell.
+    #[inline]
+    #[must_use]
+    pub fn offsets_rows(self) -> bool {
+        matches!(self, CellShape::Brick | CellShape::Hexagon)
+    }
+
+    /// Whether tiles are clipped to a shape smaller than their square.
+    #[inline]
+    #[must_use]
+    pub fn is_clipped(self) -> bool {
+        self == CellShape::Hexagon
+    }
+
+    /// Distance in pixels between the tops of consecutive rows of `size` cells.
+    #[inline]
+    #[must_use]
+    pub fn row_pitch(self, size: u32) -> u32 {
+        match self {
+            CellShape::Hexagon => size - size / 4,
+            _ => size,
+        }
+    }
+
+    /// Whether pixel (x, y) of a `size` square cell lies inside the shape.
+    /// Hexagon pixels are tested at their centers; where two hexagons meet,
+    /// a pixel belongs to both, so none is left uncovered.
+    #[must_use]
+    pub fn contains(self, x: u32, y: u32, size: u32) -> bool {
+        if self != CellShape::Hexagon {
+            return true;
+        }
+        let quarter = f64::from(size / 4);
+        let half = f64::from(size) / 2.0;
+        let (px, py) = (f64::from(x) + 0.5, f64::from(y) + 0.5);
+        // Distance from the top or bottom vertex, within the slanted edges
+        let from_tip = py.min(f64::from(size) - py);
+        from_tip >= quarter || (px - half).abs() <= half * from_tip / quarter
+    }
+}
+
+/// Makes the pixels of a square tile outside `shape` fully transparent.
+pub(crate) fn clip_to_shape(tile: &mut RgbaImage, shape: CellShape) {
+    if !shape.is_clipped() {
+        return;
+    }
+    let size = tile.width();
+    for (x, y, pixel) in tile.enumerate_pixels_mut() {
+        if !shape.contains(x, y, size) {
+            pixel[3] = 0;
+        }
+    }
+}
+
+/// A cell of the layout, in units of the analysis grid.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct GridCell {
+    /// Column in the grid, or the position within the row for orientation-aware layouts.
+    pub column: u32,
+    pub row: u32,
+    /// Side length in grid cells; 1 is one analysis tile.
+    pub span: u32,
+    /// Narrower cell of an orientation-aware layout that holds a portrait tile.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub portrait: bool,
+    /// Portrait cells left of this one in its row.
+    #[serde(skip)]
+    portraits_before: u32,
+}
+
+impl GridCell {
+    /// A landscape or square cell spanning `span` grid cells.
+    #[inline]
+    #[must_use]
+    pub const fn new(column: u32, row: u32, span: u32) -> Self {
+        Self {
+            column,
+            row,
+            span,
+            portrait: false,
+            portraits_before: 0,
+        }
+    }
+}
+
+/// Cells covering the target, sorted by row and then column.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MosaicLayout {
+    pub shape: CellShape,
+    /// Size of the analysis grid the cells are aligned to. Shifted rows of
+    /// brick and hexagon layouts may hold one more cell.
+    pub columns: u32,
+    pub rows: u32,
+    pub cells: Vec<GridCell>,
+    #[serde(skip)]
+    max_span: u32,
+    /// Size of one analysis grid cell in pixels.
+    #[serde(skip)]
+    cell: CellSize,
+    /// Width of portrait cells at the analysis size, 0 unless orientation-aware.
+    #[serde(skip)]
+    portrait_width: u32,
+}
+
+impl MosaicLayout {
+    /// One cell per analysis tile, covering a `width` x `height` target.
+    #[must_use]
+    pub fn regular(shape: CellShape, width: u32, height: u32, cell: CellSize) -> Self {
+        let columns = width.div_ceil(cell.width);
+        let rows = match shape {
+            // Rows until the last one reaches below the target with its full width
+            CellShape::Hexagon => {
+                let full_width = cell.height - 2 * (cell.height / 4);
+                height
+                    .saturating_sub(full_width)
+                    .div_ceil(shape.row_pitch(cell.height))
+                    + 1
+            }
+            _ => height.div_ceil(cell.height),
+        };
+        // Shifted rows start half a cell left of the target and may need one more cell
+        let shifted_columns = (width + cell.width / 2).div_ceil(cell.width);
+        let cells = (0..rows)
+            .flat_map(|row| {
+                let count = match shape.offsets_rows() && row % 2 == 1 {
+                    true => shifted_columns,
+                    false => columns,
+                };
+                (0..count).map(move |column| GridCell::new(column, row, 1))
+            })
+            .collect();
+        Self {
+            shape,
+            columns,
+            rows,
+            cells,
+            max_span: 1,
+            cell,
+            portrait_width: 0,
+        }
+    }
+
+    /// Rows of landscape `cell`s with narrower portrait cells of the same
+    /// height wherever `rows` marks one, in order from the left. Each row
+    /// must reach at least `width` pixels.
+    #[must_use]
+    pub fn orientation_aware(width: u32, cell: CellSize, rows: &[Vec<bool>]) -> Self {
+        let mut cells = Vec::new();
+        for (row, portraits) in (0u32..).zip(rows) {
+            let mut portraits_before = 0;
+            for (column, &portrait) in (0u32..).zip(portraits) {
+                cells.push(GridCell {
+                    portrait,
+                    portraits_before,
+                    ..GridCell::new(column, row, 1)
+                });
+                portraits_before += u32::from(portrait);
+            }
+        }
+        Self {
+            shape: CellShape::Square,
+            columns: width.div_ceil(cell.width),
+            rows: rows.len() as u32,
+            cells,
+            max_span: 1,
+            cell,
+            portrait_width: cell.portrait().width,
+        }
+    }
+
+    /// Starts from square cells of `max_cell_span` and splits each one in
+    /// four while its luma varies more than the threshold, down to single
+    /// grid cells. Cells that would extend past the grid are always split.
+    #[must_use]
+    pub fn quadtree(target: &RgbaImage, cell: CellSize, options: &QuadtreeOptions) -> Self {
+        let columns = target.width() / cell.width;
+        let rows = target.height() / cell.height;
+        let stats = LumaStats::new(target, cell, columns, rows);
+        let max_span = options.max_cell_span;
+
+        let mut cells = Vec::new();
+        let mut pending: Vec<GridCell> = (0..rows.div_ceil(max_span))
+            .flat_map(|row| {
+                (0..columns.div_ceil(max_span))
+                    .map(move |column| GridCell::new(column * max_span, row * max_span, max_span))
+            })
+            .collect();
+        while let Some(cell) = pending.pop() {
+            if cell.column >= columns || cell.row >= rows {
+                continue;
+            }
+            let fits = cell.column + cell.span <= columns && cell.row + cell.span <= rows;
+            if cell.span == 1 || (fits && stats.std_dev(&cell) <= options.detail_threshold) {
+                cells.push(cell);
+                continue;
+            }
+            let half = cell.span / 2;
+            for (dx, dy) in [(0, 0), (half, 0), (0, half), (half, half)] {
+                pending.push(GridCell::new(cell.column + dx, cell.row + dy, half));
+            }
+        }
+        cells.sort_unstable_by_key(|cell| (cell.row, cell.column));
+
+        Self {
+            shape: CellShape::Square,
+            columns,
+            rows,
+            cells,
+            max_span,
+            cell,
+            portrait_width: 0,
+        }
+    }
+
+    /// True for a plain row-major grid of single cells.
+    #[inline]
+    #[must_use]
+    pub fn is_plain_grid(&self) -> bool {
+        self.shape == CellShape::Square && self.max_span == 1 && self.portrait_width == 0
+    }
+
+    /// Width of portrait cells when grid cells are `size`, rounded up so rows
+    /// still reach as far as they do at the analysis size.
+    fn portrait_width_at(&self, size: CellSize) -> u32 {
+        (u64::from(self.portrait_width) * u64::from(size.width))
+            .div_ceil(u64::from(self.cell.width)) as u32
+    }
+
+    /// Top-left corner and size in pixels of a cell drawn with `size` pixel
+    /// grid cells. Shifted and overlapping rows can start left of or above 0.
+    #[must_use]
+    pub fn cell_rect(&self, cell: &GridCell, size: CellSize) -> (i64, i64, CellSize) {
+        let shift = match self.shape.offsets_rows() && cell.row % 2 == 1 {
+            true => i64::from(size.width / 2),
+            false => 0,
+        };
+        let portrait_width = self.portrait_width_at(size);
+        let landscapes_before = cell.column - cell.portraits_before;
+        let x = i64::from(landscapes_before) * i64::from(size.width)
+            + i64::from(cell.portraits_before) * i64::from(portrait_width)
+            - shift;
+        let y = match self.shape {
+            CellShape::Hexagon => {
+                i64::from(cell.row) * i64::from(self.shape.row_pitch(size.height))
+                    - i64::from(size.height / 4)
+            }
+            _ => i64::from(cell.row) * i64::from(size.height),
+        };
+        let rect = match cell.portrait {
+            true => CellSize::new(portrait_width, size.height),
+            false => size.scaled(cell.span),
+        };
+        (x, y, rect)
+    }
+
+    /// Indices of the cells that overlap the pixel rows `band` when drawn
+    /// with `size` pixel grid cells, with the cells.
+    pub(crate) fn cells_in_band(
+        &self,
+        band: Range<u32>,
+        size: CellSize,
+    ) -> impl Iterator<Item = (usize, &GridCell)> {
+        let (top, bottom) = (i64::from(band.start), i64::from(band.end));
+        let cell_top = move |cell: &GridCell| self.cell_rect(cell, size).1;
+        let tallest = i64::from(self.max_span * size.height);
+        // Rows start lower the further down they are, and no cell is taller than max_span
+        let first = self
+            .cells
+            .partition_point(|cell| cell_top(cell) + tallest <= top);
+        self.cells[first..]
+            .iter()
+            .enumerate()
+            .take_while(move |(_, cell)| cell_top(cell) < bottom)
+            .filter(move |(_, cell)| cell_top(cell) + i64::from(cell.span * size.height) > top)
+            .map(move |(i, cell)| (first + i, cell))
+    }
+
+    /// For every cell, the other cells within `radius` grid cells of it
+    /// (Chebyshev distance between their edges in grid coordinates).
+    pub(crate) fn neighbors(&self, radius: u32) -> Vec<Vec<usize>> {
+        if self.portrait_width > 0 {
+            return self.neighbors_by_extent(radius);
+        }
+        // Shifted rows hold one more cell, so every row gets room for it
+        let stride = self.columns + 1;
+        let mut owner = vec![usize::MAX; stride as usize * self.rows as usize];
+        for (i, cell) in self.cells.iter().enumerate() {
+            for row in cell.row..cell.row + cell.span {
+                let start = (row * stride + cell.column) as usize;
+                owner[start..start + cell.span as usize].fill(i);
+            }
+        }
+
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                let rows =
+                    cell.row.saturating_sub(radius)..(cell.row + cell.span + radius).min(self.rows);
+                let columns = cell.column.saturating_sub(radius)
+                    ..(cell.column + cell.span + radius).min(stride);
+                let mut near: Vec<usize> = rows
+                    .flat_map(|row| {
+                        let owner = &owner;
+                        let columns = columns.clone();
+                        columns.map(move |column| owner[(row * stride + column) as usize])
+                    })
+                    .filter(|&n| n != i && n != usize::MAX)
+                    .collect();
+                near.sort_unstable();
+                near.dedup();
+                near
+            })
+            .collect()
+    }
+
+    /// Neighbors of orientation-aware layouts, whose columns do not line up
+    /// between rows: cells within `radius` rows whose horizontal extents are
+    /// less than `radius` landscape cells apart.
+    fn neighbors_by_extent(&self, radius: u32) -> Vec<Vec<usize>> {
+        let reach = i64::from(radius * self.cell.width);
+        let extent = |cell: &GridCell| {
+            let (x, _, size) = self.cell_rect(cell, self.cell);
+            (x, x + i64::from(size.width))
+        };
+        self.cells
+            .iter()
+            .enumerate()
+            .map(|(i, cell)| {
+                let (left, right) = extent(cell);
+                let first = self
+                    .cells
+                    .partition_point(|other| other.row + radius < cell.row);
+                self.cells[first..]
+                    .iter()
+                    .enumerate()
+                    .take_while(|(_, other)| other.row <= cell.row + radius)
+                    .filter(|&(j, other)| {
+                        let (other_left, other_right) = extent(other);
+                        first + j != i && other_left < right + reach && other_right > left - reach
+                    })
+                    .map(|(j, _)| first + j)
+                    .collect()
+            })
+            .collect()
+    }
+}
+
+/// Summed-area tables of luma and squared luma per grid cell, so the
+/// variance of any aligned block is found in constant time.
+struct LumaStats {
+    /// Row length of the tables, one more than the grid columns.
+    stride: usize,
+    cell: CellSize,
+    sum: Vec<f64>,
+    sum_sq: Vec<f64>,
+}
+
+impl LumaStats {
+    fn new(target: &RgbaImage, cell: CellSize, columns: u32, rows: u32) -> Self {
+        let stride = columns as usize + 1;
+        let mut sum = vec![0.0; stride * (rows as usize + 1)];
+        let mut sum_sq = sum.clone();
+        for (x, y, pixel) in target.enumerate_pixels() {
+            let [r, g, b, _] = pixel.0.map(f64::from);
+            let luma = 0.299 * r + 0.587 * g + 0.114 * b;
+            let index = (y / cell.height + 1) as usize * stride + (x / cell.width + 1) as usize;
+            sum[index] += luma;
+            sum_sq[index] += luma * luma;
+        }
+        for table in [&mut sum, &mut sum_sq] {
+            for row in 1..=rows as usize {
+                for column in 1..stride {
+                    let i = row * stride + column;
+                    table[i] += table[i - 1] + table[i - stride] - table[i - stride - 1];
+                }
+            }
+        }
+        Self {
+            stride,
+            cell,
+            sum,
+            sum_sq,
+        }
+    }
+
+    fn block(&self, table: &[f64], cell: &GridCell) -> f64 {
+        let (left, top) = (cell.column as usize, cell.row as usize);
+        let (right, bottom) = (left + cell.span as usize, top + cell.span as usize);
+        table[bottom * self.stride + right]
+            - table[top * self.stride + right]
+            - table[bottom * self.stride + left]
+            + table[top * self.stride + left]
+    }
+
+    fn std_dev(&self, cell: &GridCell) -> f64 {
+        let size = self.cell.scaled(cell.span);
+        let pixels = f64::from(size.width) * f64::from(size.height);
+        let mean = self.block(&self.sum, cell) / pixels;
+        let variance = self.block(&self.sum_sq, cell) / pixels - mean * mean;
+        variance.max(0.0).sqrt()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::Rgba;
+
+    #[test]
+    fn quadtree_splits_detail_and_merges_flat_regions() {
+        // Flat gray everywhere except a checkerboard in the top-left 8x8 pixels
+        let target = RgbaImage::from_fn(40, 32, |x, y| {
+            if x < 8 && y < 8 && (x + y) % 2 == 0 {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([100, 100, 100, 255])
+            }
+        });
+        let options = QuadtreeOptions {
+            max_cell_span: 4,
+            detail_threshold: 10.0,
+        };
+
+        let layout = MosaicLayout::quadtree(&target, CellSize::square(4), &options);
+
+        assert_eq!((layout.columns, layout.rows), (10, 8));
+        let covered: u32 = layout.cells.iter().map(|c| c.span * c.span).sum();
+        assert_eq!(covered, 80);
+        // The detailed corner is split into single cells, the rest stays coarse
+        let at = |column, row| {
+            layout
+                .cells
+                .iter()
+                .find(|c| (c.column, c.row) == (column, row))
+                .map(|c| c.span)
+        };
+        assert_eq!(at(0, 0), Some(1));
+        assert_eq!(at(1, 1), Some(1));
+        assert_eq!(at(2, 0), Some(2));
+        assert_eq!(at(4, 0), Some(4));
+        assert_eq!(at(0, 4), Some(4));
+        // Two columns are left past the last whole 4x4 cell
+        assert_eq!(at(8, 0), Some(2));
+        assert!(layout
+            .cells
+            .windows(2)
+            .all(|w| (w[0].row, w[0].column) < (w[1].row, w[1].column)));
+    }
+
+    #[test]
+    fn rows_and_neighbors_account_for_large_cells() {
+        let target = RgbaImage::from_pixel(16, 8, Rgba([50, 50, 50, 255]));
+        let options = QuadtreeOptions {
+            max_cell_span: 2,
+            detail_threshold: 1.0,
+        };
+        // A 2x1 grid of 2x2 cells over 4x2 analysis cells
+        let layout = MosaicLayout::quadtree(&target, CellSize::square(4), &options);
+        assert_eq!(layout.cells.len(), 2);
+
+        // Pixel rows 4..8 are the second grid row at 4px per cell
+        let in_second_row: Vec<usize> = layout
+            .cells_in_band(4..8, CellSize::square(4))
+            .map(|(i, _)| i)
+            .collect();
+        assert_eq!(in_second_row, vec![0, 1]);
+        assert_eq!(layout.neighbors(1), vec![vec![1], vec![0]]);
+
+        let grid = MosaicLayout::regular(CellShape::Square, 16, 8, CellSize::square(4));
+        assert!(grid.is_plain_grid());
+        assert_eq!(grid.neighbors(1)[0], vec![1, 4, 5]);
+        assert_eq!(grid.cells_in_band(4..8, CellSize::square(4)).count(), 4);
+    }
+
+    #[test]
+    fn brick_and_hexagon_cells_cover_every_pixel() {
+        let (width, height, size) = (37, 29, 8);
+        for shape in [CellShape::Square, CellShape::Brick, CellShape::Hexagon] {
+            let layout = MosaicLayout::regular(shape, width, height, CellSize::square(size));
+            let mut coverage = vec![0u32; (width * height) as usize];
+            for cell in &layout.cells {
+                let (x, y, rect) = layout.cell_rect(cell, CellSize::square(size));
+                let side = rect.width;
+                let mut visible = false;
+                for dy in 0..side {
+                    for dx in 0..side {
+                        let (px, py) = (x + i64::from(dx), y + i64::from(dy));
+                        let inside = (0..i64::from(width)).contains(&px)
+                            && (0..i64::from(height)).contains(&py);
+                        visible |= inside;
+                        if inside && shape.contains(dx, dy, side) {
+                            coverage[(py * i64::from(width) + px) as usize] += 1;
+                        }
+                    }
+                }
+                assert!(
+                    visible,
+                    "{:?} cell {:?} is entirely outside the target",
+                    shape, cell
+                );
+            }
+
+            assert!(coverage.iter().all(|&count| count >= 1), "{:?}", shape);
+            if shape != CellShape::Hexagon {
+                assert!(coverage.iter().all(|&count| count == 1), "{:?}", shape);
+            }
+        }
+    }
+
+    /// Counts how often each pixel of a `width` x `height` target is covered.
+    fn coverage(layout: &MosaicLayout, width: u32, height: u32, size: CellSize) -> Vec<u32> {
+        let mut coverage = vec![0u32; (width * height) as usize];
+        for cell in &layout.cells {
+            let (x, y, rect) = layout.cell_rect(cell, size);
+            for py in y.max(0)..(y + i64::from(rect.height)).min(i64::from(height)) {
+                for px in x.max(0)..(x + i64::from(rect.width)).min(i64::from(width)) {
+                    coverage[(py * i64::from(width) + px) as usize] += 1;
+                }
+            }
+        }
+        coverage
+    }
+
+    #[test]
+    fn aspect_sets_the_short_side_of_the_cell() {
+        let aspect = |width, height| TileAspect { width, height };
+        assert_eq!(aspect(4, 3).cell_size(32), CellSize::new(32, 24));
+        assert_eq!(aspect(2, 3).cell_size(32), CellSize::new(21, 32));
+        assert_eq!(TileAspect::default().cell_size(32), CellSize::square(32));
+        assert_eq!(CellSize::new(32, 24).portrait(), CellSize::new(18, 24));
+
+        assert!(aspect(16, 9).validate().is_ok());
+        assert!(aspect(5, 1).validate().is_err());
+        assert!(aspect(0, 3).validate().is_err());
+        assert!(aspect(32, 24).validate().is_err());
+
+        // A 4:3 grid covers 70x50 pixels with 3 columns and 3 rows of 32x24 cells
+        let cell = aspect(4, 3).cell_size(32);
+        for shape in [CellShape::Square, CellShape::Brick] {
+            let layout = MosaicLayout::regular(shape, 70, 50, cell);
+            assert_eq!((layout.columns, layout.rows), (3, 3));
+            assert!(coverage(&layout, 70, 50, cell).iter().all(|&c| c == 1));
+            // Drawn twice as large, every cell doubles in both directions
+            let (x, y, rect) = layout.cell_rect(&layout.cells[4], cell.scaled(2));
+            assert_eq!(rect, CellSize::new(64, 48));
+            assert_eq!(y, 48);
+            assert_eq!(x, if shape == CellShape::Brick { 32 } else { 64 });
+        }
+    }
+
+    #[test]
+    fn orientation_aware_rows_cover_the_target_at_any_size() {
+        let cell = CellSize::new(32, 24);
+        // Both rows reach past 70 pixels: 32 + 18 + 32 and 3 * 18 + 32
+        let rows = vec![vec![false, true, false], vec![true, true, true, false]];
+        let layout = MosaicLayout::orientation_aware(70, cell, &rows);
+        assert_eq!(layout.cells.len(), 7);
+        assert!(!layout.is_plain_grid());
+        assert_eq!(
+            layout.cell_rect(&layout.cells[2], cell),
+            (50, 0, CellSize::new(32, 24))
+        );
+        assert_eq!(
+            layout.cell_rect(&layout.cells[4], cell),
+            (18, 24, CellSize::new(18, 24))
+        );
+        assert!(coverage(&layout, 70, 48, cell).iter().all(|&c| c == 1));
+        // At 1.5 times the size, portrait cells round up to 27 pixels and rows still reach
+        let render = CellSize::new(48, 36);
+        assert_eq!(layout.cell_rect(&layout.cells[1], render).2.width, 27);
+        assert!(coverage(&layout, 105, 72, render).iter().all(|&c| c == 1));
+
+        // Cells less than one landscape cell apart are neighbors, even across rows
+        let neighbors = layout.neighbors(1);
+        assert_eq!(neighbors[0], vec![1, 2, 3, 4, 5, 6]);
+        assert_eq!(neighbors[6], vec![0, 1, 2, 4, 5]);
+        assert_eq!(neighbors[3], vec![0, 1, 4, 5]);
+    }
+
+    #[test]
+    fn hexagon_clips_the_corners_of_its_square() {
+        let hexagon = CellShape::Hexagon;
+        assert!(!hexagon.contains(0, 0, 16));
+        assert!(!hexagon.contains(15, 15, 16));
+        assert!(hexagon.contains(7, 0, 16));
+        assert!(hexagon.contains(0, 8, 16));
+        assert_eq!(hexagon.row_pitch(16), 12);
+        assert!(CellShape::Brick.contains(0, 0, 16));
+    }
+
+    #[test]
+    fn validate_rejects_spans_that_are_not_powers_of_two() {
+        let options = |max_cell_span, detail_threshold| QuadtreeOptions {
+            max_cell_span,
+            detail_threshold,
+        };
+        assert!(options(4, 12.0).validate().is_ok());
+        assert!(options(3, 12.0).validate().is_err());
+        assert!(options(32, 12.0).validate().is_err());
+        assert!(options(1, 12.0).validate().is_err());
+        assert!(options(4, f64::NAN).validate().is_err());
+    }
+}
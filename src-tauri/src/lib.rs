@@ -1,21 +1,61 @@
+pub mod assignment;
+pub mod color;
+pub mod compose;
+pub mod coverage;
+pub mod crop;
+pub mod dedupe;
+pub mod deepzoom;
+pub mod descriptor;
+pub mod dihedral;
 pub mod errors;
-
+pub mod export;
+pub mod formats;
+pub mod index_cache;
+pub mod layout;
+pub mod load_report;
+pub mod manifest;
+pub mod sources;
+pub mod structure;
+
+use crate::assignment::{AssignmentMode, AssignmentSolver, UnmetConstraint};
+use crate::color::{ciede2000, srgb_u8_to_linear, ColorSpace, LAB_DISTANCE_SCALE};
+use crate::compose::{blend_overlay, correct_toward, mean_linear_rgb, resize_rows, BlendMode};
+use crate::coverage::{compare_histograms, ColorHistogram, CoverageReport};
+use crate::crop::{fill_cell, CropRect, CropStrategy};
+use crate::dedupe::{group_near_duplicates, DuplicateFilter, DuplicateGroup, PerceptualHashes};
+use crate::deepzoom::{write_deep_zoom, DeepZoomOptions};
+use crate::descriptor::{compute_descriptor, DescriptorGrid, DescriptorIndex};
+use crate::dihedral::{Dihedral, TileTransforms, TileVariant};
 use crate::errors::{AppError, AppResult};
+use crate::export::{write_image, write_image_strips, ExportOptions, StripSink};
+use crate::formats::{find_image_files, FormatSet};
+use crate::index_cache::{CachedTile, FileStamp, IndexCache};
+use crate::layout::{
+    clip_to_shape, CellShape, CellSize, MosaicLayout, QuadtreeOptions, TileAspect,
+};
+use crate::load_report::{LoadReport, SkipReason, SkippedFile};
+use crate::manifest::{write_manifest, ManifestFormat, Placement, PlacementManifest};
+use crate::sources::TileSource;
+use crate::structure::{luma_thumbnail, ssim, structure_mask};
 use base64::{engine::general_purpose, Engine as _};
 use image::{
-    codecs::png::PngEncoder, imageops::FilterType, metadata::Orientation, DynamicImage,
-    GenericImageView, ImageBuffer, ImageDecoder, ImageEncoder, ImageReader,
+    codecs::png::PngEncoder, imageops, imageops::FilterType, metadata::Orientation, DynamicImage,
+    GenericImageView, ImageDecoder, ImageEncoder, ImageReader, RgbaImage,
 };
-use kiddo::{ImmutableKdTree, SquaredEuclidean};
 use rayon::prelude::*;
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::num::NonZeroUsize;
+use std::ops::Range;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::sync::Arc;
-use walkdir::WalkDir;
 
 /// Loads an image and applies EXIF orientation if present.
 pub fn load_image_with_orientation(path: impl AsRef<Path>) -> AppResult<DynamicImage> {
+    // Sniff the content so mislabeled files still decode
     let reader = ImageReader::open(path.as_ref())
+        .and_then(|reader| reader.with_guessed_format())
         .map_err(|e| AppError::Image(format!("Failed to open image: {}", e)))?;
 
     let mut decoder = reader
@@ -61,20 +101,55 @@ pub fn validate_mosaic_inputs(
     Ok(())
 }
 
+/// Rejects quadtree layouts whose largest cell would be rendered wider than
+/// `MAX_RENDER_TILE_SIZE`; every tile is decoded and cached at that size.
+fn validate_largest_cell(render_size: u32, quadtree: &QuadtreeOptions) -> AppResult<()> {
+    let largest = u64::from(render_size) * u64::from(quadtree.max_cell_span);
+    if largest > u64::from(MAX_RENDER_TILE_SIZE) {
+        return Err(AppError::Config(format!(
+            "Quadtree cells of {} tiles rendered at {} pixels would be {} pixels wide. \
+             Expected at most {}; lower max_cell_span or render_tile_size",
+            quadtree.max_cell_span, render_size, largest, MAX_RENDER_TILE_SIZE
+        )));
+    }
+    Ok(())
+}
+
 fn load_resized_image_with_orientation(
     path: impl AsRef<Path>,
-    size: u32,
+    size: CellSize,
+    crop: Option<CropRect>,
 ) -> AppResult<DynamicImage> {
     let img = load_image_with_orientation(path)?;
-    Ok(img.resize_to_fill(size, size, FilterType::Lanczos3))
+    Ok(fill_cell(&img, crop, size))
 }
 
 // Constants for performance tuning
 const PENALTY_MULTIPLIER: f64 = 50.0;
+const STRUCTURE_MULTIPLIER: f64 = 50.0;
+const MAX_REPEAT_DISTANCE: u32 = 10;
+const MAX_RENDER_TILE_SIZE: u32 = 1024;
+/// Source images narrower or shorter than this are skipped as tiles; they are
+/// icons or spacer images rather than photos.
+const MIN_TILE_SOURCE_SIZE: u32 = 8;
+/// Upper bound on a canvas rendered in memory (about 1 GiB of RGBA).
+const MAX_CANVAS_PIXELS: u64 = 1 << 28;
+/// Saved PNG/TIFF output above this many pixels is rendered in bands.
+const STREAMING_MIN_PIXELS: u64 = 1 << 26;
+/// Target size of one streamed band of RGBA rows.
+const STREAM_BAND_BYTES: u64 = 64 << 20;
+/// Render sizes cached per tile: one for each cell span of the largest quadtree.
+/// `validate_largest_cell` keeps the largest of them within `MAX_RENDER_TILE_SIZE`.
+const MAX_CACHED_SIZES: usize = layout::MAX_CELL_SPAN.ilog2() as usize + 1;
 const KD_TREE_K_MIN: usize = 10;
 const KD_TREE_K_MAX: usize = 100;
 const KD_TREE_K_DIVISOR: usize = 10;
-const SUPPORTED_EXTENSIONS: &[&str] = &["jpg", "jpeg", "png"];
+/// Closest cells tried for a tile that no cell lists as a candidate when
+/// every tile has to be used.
+const COVER_CELLS_K: usize = 32;
+/// Added to the cost of a tile placed in a cell of the other orientation, so
+/// that only happens when the rules leave no tile of the right one.
+const ORIENTATION_MISMATCH_COST: f64 = 1.0e6;
 
 /// Pre-calculated Gaussian weights for O(1) weight lookups during color averaging.
 #[derive(Clone)]
@@ -87,25 +162,38 @@ impl GaussianMask {
     /// Creates a new Gaussian mask with the specified size and sigma divisor.
     #[must_use]
     pub fn new(size: u32, sigma_divisor: f64) -> Self {
-        let capacity = (size * size) as usize;
+        Self::for_cell(CellSize::square(size), sigma_divisor, CellShape::Square)
+    }
+
+    /// Creates a Gaussian mask for a possibly rectangular cell, stretched to
+    /// its width and height. Weights are zero outside `shape`, so colors are
+    /// averaged over the part of a cell that is drawn.
+    #[must_use]
+    pub fn for_cell(cell: CellSize, sigma_divisor: f64, shape: CellShape) -> Self {
+        let capacity = (cell.width * cell.height) as usize;
         let mut weights = Vec::with_capacity(capacity);
         let mut total_weight = 0.0;
-        let center = size as f64 / 2.0;
+        let center_x = cell.width as f64 / 2.0;
+        let center_y = cell.height as f64 / 2.0;
 
         let use_gaussian = sigma_divisor > 0.0;
-        let sigma = if use_gaussian {
-            size as f64 / sigma_divisor
+        let (sigma_x, sigma_y) = if use_gaussian {
+            (
+                cell.width as f64 / sigma_divisor,
+                cell.height as f64 / sigma_divisor,
+            )
         } else {
-            1.0
+            (1.0, 1.0)
         };
 
-        for y in 0..size {
-            for x in 0..size {
-                let weight = if use_gaussian {
-                    let dx = x as f64 - center;
-                    let dy = y as f64 - center;
-                    let dist_sq = dx * dx + dy * dy;
-                    (-dist_sq / (2.0 * sigma * sigma)).exp()
+        for y in 0..cell.height {
+            for x in 0..cell.width {
+                let weight = if !shape.contains(x, y, cell.width) {
+                    0.0
+                } else if use_gaussian {
+                    let dx = (x as f64 - center_x) / sigma_x;
+                    let dy = (y as f64 - center_y) / sigma_y;
+                    (-(dx * dx + dy * dy) / 2.0).exp()
                 } else {
                     1.0
                 };
@@ -131,9 +219,199 @@ impl GaussianMask {
 }
 
 /// Configuration for mosaic generation.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub struct MosaicConfig {
     pub penalty_factor: f64,
+    /// Re-rank the nearest candidates by CIEDE2000 instead of KD-tree distance.
+    pub ciede2000_rerank: bool,
+    /// Weight (0-100) of SSIM dissimilarity when re-scoring candidates. 0 disables it.
+    pub structure_weight: f64,
+    pub assignment_mode: AssignmentMode,
+    /// Minimum distance in cells before a tile may repeat. 0 disables the check.
+    pub min_repeat_distance: u32,
+    /// Hard limit on how many cells may use the same tile. 0 means unlimited.
+    pub max_uses_per_tile: u32,
+    /// Place every library tile at least once when the grid has enough cells.
+    pub use_every_tile: bool,
+    /// Strength (0-100) of shifting each placed tile toward its cell's mean color.
+    pub color_correction: f64,
+    /// Opacity (0-100) of the original image blended over the tiles. 0 disables it.
+    pub overlay_opacity: f64,
+    pub overlay_blend: BlendMode,
+    /// Pixel size each tile is rendered at. 0 renders at the analysis tile size.
+    pub render_tile_size: u32,
+    /// Divides the target into cells of varying size when set; None keeps a uniform grid.
+    pub quadtree: Option<QuadtreeOptions>,
+}
+
+/// A generated mosaic and any placement constraints it could not satisfy.
+#[derive(Debug, Clone, Serialize)]
+pub struct MosaicResult {
+    /// PNG data URL of the mosaic.
+    pub image: String,
+    pub unmet_constraints: Vec<UnmetConstraint>,
+    /// Source tile placed in each cell, in the order of `layout.cells`.
+    pub placements: Vec<Placement>,
+    /// Cells the target was divided into.
+    pub layout: MosaicLayout,
+}
+
+/// Changes applied by `TileLibrary::rescan`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct RescanSummary {
+    pub added: usize,
+    pub removed: usize,
+    pub modified: usize,
+    /// Number of tiles in the library after the rescan.
+    pub tile_count: usize,
+}
+
+impl RescanSummary {
+    #[must_use]
+    pub fn has_changes(&self) -> bool {
+        self.added + self.removed + self.modified > 0
+    }
+}
+
+/// A mosaic written to disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedMosaic {
+    pub path: PathBuf,
+    /// Size of the written file in bytes.
+    pub file_size: u64,
+    pub unmet_constraints: Vec<UnmetConstraint>,
+}
+
+/// A placement manifest written to disk.
+#[derive(Debug, Clone, Serialize)]
+pub struct SavedManifest {
+    pub path: PathBuf,
+    /// Size of the written file in bytes.
+    pub file_size: u64,
+    pub placement_count: usize,
+}
+
+/// A mosaic written as a Deep Zoom pyramid.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeepZoomExport {
+    /// Path of the `.dzi` descriptor.
+    pub path: PathBuf,
+    pub width: u32,
+    pub height: u32,
+    pub levels: u32,
+    /// Number of JPEG tiles across all levels.
+    pub tile_count: u64,
+    pub unmet_constraints: Vec<UnmetConstraint>,
+}
+
+impl MosaicConfig {
+    /// Validates generation options that are not covered by `validate_mosaic_inputs`.
+    pub fn validate(&self) -> AppResult<()> {
+        if !self.structure_weight.is_finite() || !(0.0..=100.0).contains(&self.structure_weight) {
+            return Err(AppError::Config(format!(
+                "Invalid structure_weight {}. Expected finite value in range 0..=100",
+                self.structure_weight
+            )));
+        }
+
+        if !self.color_correction.is_finite() || !(0.0..=100.0).contains(&self.color_correction) {
+            return Err(AppError::Config(format!(
+                "Invalid color_correction {}. Expected finite value in range 0..=100",
+                self.color_correction
+            )));
+        }
+
+        if !self.overlay_opacity.is_finite() || !(0.0..=100.0).contains(&self.overlay_opacity) {
+            return Err(AppError::Config(format!(
+                "Invalid overlay_opacity {}. Expected finite value in range 0..=100",
+                self.overlay_opacity
+            )));
+        }
+
+        if self.render_tile_size != 0
+            && !(8..=MAX_RENDER_TILE_SIZE).contains(&self.render_tile_size)
+        {
+            return Err(AppError::Config(format!(
+                "Invalid render_tile_size {}. Expected 0 or value in range 8..={}",
+                self.render_tile_size, MAX_RENDER_TILE_SIZE
+            )));
+        }
+
+        if self.min_repeat_distance > MAX_REPEAT_DISTANCE {
+            return Err(AppError::Config(format!(
+                "Invalid min_repeat_distance {}. Expected value in range 0..={}",
+                self.min_repeat_distance, MAX_REPEAT_DISTANCE
+            )));
+        }
+
+        if let Some(quadtree) = &self.quadtree {
+            quadtree.validate()?;
+            if self.render_tile_size != 0 {
+                validate_largest_cell(self.render_tile_size, quadtree)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Settings that determine the computed tile colors.
+/// A loaded library can be reused as long as these match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LibraryConfig {
+    /// Directories tile images are read from.
+    pub sources: Vec<TileSource>,
+    pub tile_size: u32,
+    pub sigma_divisor: f64,
+    pub color_space: ColorSpace,
+    pub descriptor_grid: DescriptorGrid,
+    /// Image formats picked up from `dir`.
+    pub formats: FormatSet,
+    /// Collapses near-duplicate images into one tile when set.
+    pub duplicates: Option<DuplicateFilter>,
+    /// Shape of the cells tiles are drawn in; hexagons are analyzed without their corners.
+    pub cell_shape: CellShape,
+    /// Width-to-height ratio of the cells, with `tile_size` as the longer side.
+    pub tile_aspect: TileAspect,
+    /// Analyze portrait photos as portrait cells and place them where they
+    /// fit best. Needs a landscape `tile_aspect`.
+    pub orientation_aware: bool,
+    /// How photos are cropped to the cell aspect.
+    pub crop_strategy: CropStrategy,
+    /// Rotated and mirrored copies of each tile that are matched as well.
+    pub tile_transforms: TileTransforms,
+}
+
+impl LibraryConfig {
+    /// Validates the cell aspect and the layouts it is combined with.
+    pub fn validate(&self) -> AppResult<()> {
+        self.tile_aspect.validate()?;
+        if self.orientation_aware && !self.tile_aspect.is_landscape() {
+            return Err(AppError::Config(format!(
+                "Orientation-aware cells need a landscape tile aspect such as 4:3, got {}:{}",
+                self.tile_aspect.width, self.tile_aspect.height
+            )));
+        }
+        if self.orientation_aware && self.cell_shape != CellShape::Square {
+            return Err(AppError::Config(
+                "Orientation-aware cells only support the square grid".into(),
+            ));
+        }
+        if self.cell_shape == CellShape::Hexagon && !self.tile_aspect.is_square() {
+            return Err(AppError::Config(format!(
+                "Hexagonal cells need a square tile aspect, got {}:{}",
+                self.tile_aspect.width, self.tile_aspect.height
+            )));
+        }
+        Ok(())
+    }
+
+    /// Size of the analysis cells tiles are resized to.
+    #[inline]
+    #[must_use]
+    pub fn cell_size(&self) -> CellSize {
+        self.tile_aspect.cell_size(self.tile_size)
+    }
 }
 
 /// Represents a single tile with its metadata.
@@ -141,43 +419,187 @@ pub struct MosaicConfig {
 #[derive(Debug, Clone)]
 pub struct Tile {
     pub path: PathBuf,
+    /// Average color over the whole tile.
     pub color: [f64; 3],
-    /// Cached resized image. None if not yet loaded.
-    image_cache: Option<Arc<DynamicImage>>,
-    tile_size: u32,
+    /// Region-averaged colors used for KD-tree matching.
+    pub descriptor: Vec<f64>,
+    /// Downsampled luma used for structural re-ranking.
+    pub structure: Vec<f64>,
+    /// Analyzed and drawn in the portrait cells of an orientation-aware layout.
+    pub portrait: bool,
+    /// Part of the source photo the tile shows, chosen by the library's crop
+    /// strategy. None keeps the centered crop.
+    pub crop: Option<CropRect>,
+    /// Features of the rotated and mirrored copies the library matches
+    /// besides the original. All of them count as uses of the same photo.
+    pub variants: Vec<TileVariant>,
+    /// Resized images by the size they were rendered at. Layouts with cells
+    /// of several sizes keep one copy per size.
+    image_cache: Vec<(CellSize, Arc<DynamicImage>)>,
+    /// Size and modification time of the file when it was analyzed.
+    stamp: Option<FileStamp>,
+    /// Perceptual hashes of the source image, used to find near-duplicates.
+    hashes: Option<PerceptualHashes>,
 }
 
 impl Tile {
     /// Creates a new tile with color information.
     /// The image will be loaded lazily when needed.
     #[must_use]
-    pub fn new(path: PathBuf, color: [f64; 3], tile_size: u32) -> Self {
+    pub fn new(path: PathBuf, color: [f64; 3], descriptor: Vec<f64>, structure: Vec<f64>) -> Self {
         Self {
             path,
             color,
-            image_cache: None,
-            tile_size,
+            descriptor,
+            structure,
+            portrait: false,
+            crop: None,
+            variants: Vec::new(),
+            image_cache: Vec::new(),
+            stamp: None,
+            hashes: None,
         }
     }
 
-    /// Gets the cached image or loads it if needed.
-    pub fn get_image(&mut self) -> AppResult<Arc<DynamicImage>> {
-        if let Some(ref cached) = self.image_cache {
+    /// Loads the image from disk resized to `size`, bypassing the cache.
+    pub fn load_image(&self, size: CellSize) -> AppResult<DynamicImage> {
+        load_resized_image_with_orientation(&self.path, size, self.crop).map_err(|e| {
+            AppError::Image(format!(
+                "Failed to load tile {}: {}",
+                self.path.display(),
+                e
+            ))
+        })
+    }
+
+    /// Gets the image resized to `size`, loading it from disk if it is not
+    /// cached at that size yet.
+    pub fn get_image(&mut self, size: CellSize) -> AppResult<Arc<DynamicImage>> {
+        if let Some((_, cached)) = self.image_cache.iter().find(|(s, _)| *s == size) {
             return Ok(Arc::clone(cached));
         }
 
-        let resized =
-            load_resized_image_with_orientation(&self.path, self.tile_size).map_err(|e| {
-                AppError::Image(format!(
-                    "Failed to load tile {}: {}",
-                    self.path.display(),
-                    e
-                ))
-            })?;
-        let cached = Arc::new(resized);
-        self.image_cache = Some(Arc::clone(&cached));
+        let cached = Arc::new(self.load_image(size)?);
+        if self.image_cache.len() == MAX_CACHED_SIZES {
+            // The oldest size most likely belongs to an earlier render size
+            self.image_cache.remove(0);
+        }
+        self.image_cache.push((size, Arc::clone(&cached)));
         Ok(cached)
     }
+
+    /// Descriptor and structure of the original followed by those of each variant.
+    fn features(&self) -> impl Iterator<Item = (Dihedral, &[f64], &[f64])> {
+        let original = (
+            Dihedral::Identity,
+            self.descriptor.as_slice(),
+            self.structure.as_slice(),
+        );
+        std::iter::once(original).chain(self.variants.iter().map(|variant| {
+            (
+                variant.transform,
+                variant.descriptor.as_slice(),
+                variant.structure.as_slice(),
+            )
+        }))
+    }
+}
+
+/// Tile files with their size and modification time, if readable.
+type StampedFiles = Vec<(PathBuf, Option<FileStamp>)>;
+
+/// Image files of the library's formats with their size and modification
+/// time, and the entries that could not be used.
+fn scan_tile_files(config: &LibraryConfig) -> AppResult<(StampedFiles, Vec<SkippedFile>)> {
+    let scan = find_image_files(&config.sources, &config.formats)?;
+    let files = scan
+        .files
+        .into_iter()
+        .map(|path| {
+            let stamp = std::fs::metadata(&path)
+                .ok()
+                .map(|metadata| FileStamp::from_metadata(&metadata));
+            (path, stamp)
+        })
+        .collect();
+    Ok((files, scan.skipped))
+}
+
+fn no_images_error(skipped: &[SkippedFile]) -> AppError {
+    match skipped.len() {
+        0 => AppError::Config("No valid images found in directory".into()),
+        skipped => AppError::Config(format!(
+            "No valid images found in directory ({} files skipped)",
+            skipped
+        )),
+    }
+}
+
+/// Replaces the cached features with those of `tiles`, returning why the
+/// cache could not be written if it failed.
+fn store_in_cache<'a>(
+    cache: &mut IndexCache,
+    tiles: impl IntoIterator<Item = &'a Tile>,
+) -> Option<String> {
+    let entries = tiles
+        .into_iter()
+        .filter_map(|tile| {
+            let entry = CachedTile {
+                stamp: tile.stamp?,
+                color: tile.color,
+                descriptor: tile.descriptor.clone(),
+                structure: tile.structure.clone(),
+                hashes: tile.hashes?,
+                portrait: tile.portrait,
+                crop: tile.crop,
+                variants: tile.variants.clone(),
+            };
+            Some((tile.path.clone(), entry))
+        })
+        .collect();
+    // The cache only saves work, so failing to write it must not fail loading
+    cache.update(entries).err().map(|e| e.to_string())
+}
+
+/// Splits `tiles` into the tiles to use and the near-duplicates collapsed
+/// into them. The first file of each group in directory order is kept.
+fn collapse_duplicates(
+    tiles: Vec<Tile>,
+    filter: Option<DuplicateFilter>,
+) -> (Vec<Tile>, Vec<Tile>, Vec<DuplicateGroup>) {
+    let Some(filter) = filter else {
+        return (tiles, Vec::new(), Vec::new());
+    };
+    let hashes: Vec<Option<u64>> = tiles
+        .iter()
+        .map(|tile| tile.hashes.map(|h| h.get(filter.hash)))
+        .collect();
+    let representatives = group_near_duplicates(&hashes, filter.max_distance);
+
+    let mut groups: BTreeMap<usize, DuplicateGroup> = BTreeMap::new();
+    for (i, &rep) in representatives.iter().enumerate() {
+        if rep != i {
+            groups
+                .entry(rep)
+                .or_insert_with(|| DuplicateGroup {
+                    representative: tiles[rep].path.clone(),
+                    duplicates: Vec::new(),
+                })
+                .duplicates
+                .push(tiles[i].path.clone());
+        }
+    }
+
+    let mut kept = Vec::with_capacity(tiles.len());
+    let mut collapsed = Vec::new();
+    for (i, (tile, &rep)) in tiles.into_iter().zip(&representatives).enumerate() {
+        if rep == i {
+            kept.push(tile);
+        } else {
+            collapsed.push(tile);
+        }
+    }
+    (kept, collapsed, groups.into_values().collect())
 }
 
 /// Calculates average RGB color using a Gaussian mask.
@@ -202,10 +624,31 @@ fn avg_rgb_with_mask(img: &DynamicImage, mask: &GaussianMask) -> [f64; 3] {
     [r / total, g / total, b / total]
 }
 
+/// Calculates the Gaussian-weighted average color in the given color space.
+/// Non-sRGB spaces average in linear light before converting.
+#[must_use]
+fn avg_color_with_mask(img: &DynamicImage, mask: &GaussianMask, space: ColorSpace) -> [f64; 3] {
+    if space == ColorSpace::Srgb {
+        return avg_rgb_with_mask(img, mask);
+    }
+
+    let mut linear = [0.0; 3];
+    let weights = mask.weights();
+    for (i, (_, _, pixel)) in img.pixels().enumerate() {
+        let weight = weights[i];
+        for (channel, value) in linear.iter_mut().zip(pixel.0.iter()) {
+            *channel += srgb_u8_to_linear(*value) * weight;
+        }
+    }
+
+    let total = mask.total_weight();
+    space.from_linear(linear.map(|c| c / total))
+}
+
 #[inline]
-fn padded_dimensions(width: u32, height: u32, tile_size: u32) -> (u32, u32) {
-    let pad_w = width.div_ceil(tile_size) * tile_size;
-    let pad_h = height.div_ceil(tile_size) * tile_size;
+fn padded_dimensions(width: u32, height: u32, cell: CellSize) -> (u32, u32) {
+    let pad_w = width.div_ceil(cell.width) * cell.width;
+    let pad_h = height.div_ceil(cell.height) * cell.height;
     (pad_w, pad_h)
 }
 
@@ -225,126 +668,565 @@ fn pad_target_to_tile_grid(
     }
 }
 
-fn build_tile_coordinates(width: u32, height: u32, tile_size: u32) -> Vec<(u32, u32)> {
+/// Pixels of a cell at (x, y), with coordinates outside the target clamped
+/// to its edges. Shifted, overlapping and orientation-aware rows reach past the target.
+fn cell_region(target: &RgbaImage, x: i64, y: i64, size: CellSize) -> RgbaImage {
+    let (width, height) = (target.width() as i64, target.height() as i64);
+    let (cell_w, cell_h) = (size.width as i64, size.height as i64);
+    if x >= 0 && y >= 0 && x + cell_w <= width && y + cell_h <= height {
+        return target
+            .view(x as u32, y as u32, size.width, size.height)
+            .to_image();
+    }
+    RgbaImage::from_fn(size.width, size.height, |dx, dy| {
+        let px = (x + dx as i64).clamp(0, width - 1);
+        let py = (y + dy as i64).clamp(0, height - 1);
+        *target.get_pixel(px as u32, py as u32)
+    })
+}
+
+fn build_tile_coordinates(width: u32, height: u32, cell: CellSize) -> Vec<(u32, u32)> {
     (0..height)
-        .step_by(tile_size as usize)
-        .flat_map(|y| (0..width).step_by(tile_size as usize).map(move |x| (x, y)))
+        .step_by(cell.height as usize)
+        .flat_map(|y| (0..width).step_by(cell.width as usize).map(move |x| (x, y)))
         .collect()
 }
 
+/// Analysis cell sizes of a library with their Gaussian masks.
+#[derive(Clone)]
+struct CellMasks {
+    cell: (CellSize, GaussianMask),
+    /// Portrait cells, only used by orientation-aware libraries.
+    portrait: Option<(CellSize, GaussianMask)>,
+    /// Pixels of the structure thumbnails inside the cell shape.
+    structure: Vec<bool>,
+}
+
+impl CellMasks {
+    fn new(config: &LibraryConfig) -> Self {
+        let mask = |cell: CellSize| {
+            let mask = GaussianMask::for_cell(cell, config.sigma_divisor, config.cell_shape);
+            (cell, mask)
+        };
+        let cell = config.cell_size();
+        Self {
+            cell: mask(cell),
+            portrait: config.orientation_aware.then(|| mask(cell.portrait())),
+            structure: structure_mask(config.cell_shape),
+        }
+    }
+
+    /// The analysis size and mask of portrait or other cells.
+    #[inline]
+    fn get(&self, portrait: bool) -> (CellSize, &GaussianMask) {
+        match (portrait, &self.portrait) {
+            (true, Some((cell, mask))) => (*cell, mask),
+            _ => (self.cell.0, &self.cell.1),
+        }
+    }
+}
+
+/// KD-tree over the descriptors of the library tiles of one orientation,
+/// with one entry per variant of each tile.
+struct TileIndex {
+    tree: DescriptorIndex,
+    /// Library index of the tile of each tree entry.
+    tiles: Vec<usize>,
+}
+
+impl TileIndex {
+    /// Indexes the tiles whose orientation is `portrait`, if there are any.
+    fn build(grid: DescriptorGrid, tiles: &[Tile], portrait: bool) -> Option<Self> {
+        let (tiles, descriptors): (Vec<usize>, Vec<Vec<f64>>) = tiles
+            .iter()
+            .enumerate()
+            .filter(|(_, tile)| tile.portrait == portrait)
+            .flat_map(|(i, tile)| {
+                tile.features()
+                    .map(move |(_, descriptor, _)| (i, descriptor.to_vec()))
+            })
+            .unzip();
+        (!tiles.is_empty()).then(|| Self {
+            tree: DescriptorIndex::build(grid, &descriptors),
+            tiles,
+        })
+    }
+
+    /// Library indices of the `k` nearest entries with their squared
+    /// distances. A tile appears once for each of its variants among them.
+    fn nearest_n(&self, query: &[f64], k: usize) -> Vec<(usize, f64)> {
+        // k is clamped to at least 1, so unwrap is safe
+        let k = NonZeroUsize::new(k.clamp(1, self.tiles.len())).unwrap();
+        self.tree
+            .nearest_n(query, k)
+            .into_iter()
+            .map(|(i, distance)| (self.tiles[i], distance))
+            .collect()
+    }
+}
+
+/// Matching features of one target cell.
+struct TargetCell {
+    descriptor: Vec<f64>,
+    /// Per-region CIELAB colors, only computed when CIEDE2000 re-ranking is enabled.
+    lab: Option<Vec<[f64; 3]>>,
+    /// Luma thumbnail, only computed when structural re-ranking is enabled.
+    structure: Option<Vec<f64>>,
+    /// Mean linear RGB, only computed when color correction is enabled.
+    mean: Option<[f64; 3]>,
+    /// Portrait cell of an orientation-aware layout.
+    portrait: bool,
+}
+
+/// Best variant of a tile for one cell.
+#[derive(Debug, Clone, Copy)]
+struct VariantMatch {
+    transform: Dihedral,
+    /// Mean squared color distance per region, as from `color_error`.
+    color_error: f64,
+    /// Match cost from color, structure and orientation.
+    cost: f64,
+}
+
+impl VariantMatch {
+    /// Worse than any variant, to fold over them from.
+    const NONE: Self = Self {
+        transform: Dihedral::Identity,
+        color_error: f64::INFINITY,
+        cost: f64::INFINITY,
+    };
+}
+
+/// Tile assignment for a target, ready to be composed in full or in bands.
+/// Built by `TileLibrary::plan_mosaic` and only valid for that library.
+pub struct MosaicPlan {
+    target_path: String,
+    config: MosaicConfig,
+    /// `TileLibrary::revision` the tiles were assigned from.
+    revision: u64,
+    /// Target padded to the analysis grid.
+    target: RgbaImage,
+    layout: MosaicLayout,
+    /// Matching features per layout cell.
+    cells: Vec<TargetCell>,
+    /// Tile index per layout cell.
+    tiles: Vec<usize>,
+    /// Rotation or mirroring each cell's tile is drawn with.
+    transforms: Vec<Dihedral>,
+    /// Color error of each cell's tile variant, as from `color_error`.
+    color_errors: Vec<f64>,
+    unmet: Vec<UnmetConstraint>,
+    tile_size: u32,
+    render_size: u32,
+    /// Grid cell at the analysis size and at the size tiles are drawn at.
+    cell: CellSize,
+    render_cell: CellSize,
+    /// Output dimensions with the padding cropped.
+    width: u32,
+    height: u32,
+}
+
+impl MosaicPlan {
+    /// Pixel rows per band when rendering in strips of about `STREAM_BAND_BYTES`,
+    /// a whole number of grid rows.
+    fn band_height(&self) -> u32 {
+        let row_height = self.render_cell.height;
+        let row_bytes = self.width as u64 * row_height as u64 * 4;
+        (STREAM_BAND_BYTES / row_bytes).max(1) as u32 * row_height
+    }
+
+    /// Size of the padded target scaled from the analysis grid to the
+    /// render size; the output is its top-left part.
+    fn padded_render_size(&self) -> (u32, u32) {
+        (
+            self.target.width() / self.cell.width * self.render_cell.width,
+            self.target.height() / self.cell.height * self.render_cell.height,
+        )
+    }
+
+    /// Tiles drawn in the given pixel rows, with the size each is rendered at.
+    fn tiles_in_band(&self, band: Range<u32>) -> impl Iterator<Item = (usize, CellSize)> + '_ {
+        self.layout
+            .cells_in_band(band, self.render_cell)
+            .map(|(cell, placed)| {
+                let (_, _, size) = self.layout.cell_rect(placed, self.render_cell);
+                (self.tiles[cell], size)
+            })
+    }
+
+    /// Composes the given pixel rows into a horizontal strip of the output.
+    /// Each pixel depends only on its position, so consecutive strips
+    /// concatenate to exactly the full render; cells that cross a band edge
+    /// are drawn clipped into every band they overlap.
+    fn compose_band(
+        &self,
+        config: &MosaicConfig,
+        band: Range<u32>,
+        mut tile_image: impl FnMut(usize, CellSize) -> AppResult<Arc<DynamicImage>>,
+    ) -> AppResult<RgbaImage> {
+        let top = band.start;
+        let mut strip = RgbaImage::new(self.width, band.end - band.start);
+
+        let correction = config.color_correction / 100.0;
+        for (cell, placed) in self.layout.cells_in_band(band, self.render_cell) {
+            let (x, y, size) = self.layout.cell_rect(placed, self.render_cell);
+            let tile_img = tile_image(self.tiles[cell], size)?;
+            // Convert to RgbaImage for overlay to ensure proper format.
+            // Correction and clipping only touch this copy, never the cache or the file.
+            let mut tile_rgba = self.transforms[cell].apply(tile_img.to_rgba8());
+            if let Some(mean) = self.cells[cell].mean {
+                correct_toward(&mut tile_rgba, mean, correction);
+            }
+            clip_to_shape(&mut tile_rgba, self.layout.shape);
+            imageops::overlay(&mut strip, &tile_rgba, x, y - top as i64);
+        }
+
+        if config.overlay_opacity > 0.0 {
+            // Resampled per grid row, so strips match a render in one piece
+            let overlay = resize_rows(
+                &self.target,
+                self.padded_render_size(),
+                (self.cell.height, self.render_cell.height),
+                top,
+                strip.width(),
+                strip.height(),
+            );
+            blend_overlay(
+                &mut strip,
+                &overlay,
+                config.overlay_blend,
+                config.overlay_opacity / 100.0,
+            );
+        }
+
+        Ok(strip)
+    }
+}
+
 /// Tile library with KD-tree acceleration for fast color matching.
 pub struct TileLibrary {
     tiles: Vec<Tile>,
-    color_index: ImmutableKdTree<f64, 3>,
-    src_dir: PathBuf,
-    tile_size: u32,
-    sigma_divisor: f64,
-    mask: GaussianMask,
+    /// Tiles of square and landscape cells, and of portrait cells in
+    /// orientation-aware libraries. Either is None without such tiles.
+    color_index: Option<TileIndex>,
+    portrait_index: Option<TileIndex>,
+    config: LibraryConfig,
+    masks: CellMasks,
+    /// Near-duplicates left out of `tiles`, kept so rescans can diff them.
+    collapsed: Vec<Tile>,
+    report: LoadReport,
+    /// Changes whenever `tiles` does, so plans made before a rescan are not reused.
+    revision: u64,
+}
+
+/// Source of `TileLibrary::revision`, unique across all libraries.
+static NEXT_REVISION: AtomicU64 = AtomicU64::new(0);
+
+fn next_revision() -> u64 {
+    NEXT_REVISION.fetch_add(1, Ordering::Relaxed)
 }
 
 impl TileLibrary {
-    /// Creates a new tile library from a directory.
-    pub fn new(dir: PathBuf, size: u32, sigma_divisor: f64) -> AppResult<Self> {
-        let mask = GaussianMask::new(size, sigma_divisor);
-        let tiles = Self::load_library(&dir, size, &mask)?;
+    /// Creates a tile library from the folders in `config.sources`, analyzing
+    /// every file without a cache.
+    pub fn new(config: LibraryConfig) -> AppResult<Self> {
+        Self::with_cache(config, None)
+    }
 
-        if tiles.is_empty() {
-            return Err(AppError::Config(
-                "No valid images found in directory".into(),
-            ));
+    /// Creates a tile library, reusing features stored in `cache_dir` for files
+    /// whose size and modification time are unchanged. Only new or changed files
+    /// are decoded, and the cache is updated afterwards.
+    pub fn with_cache(config: LibraryConfig, cache_dir: Option<&Path>) -> AppResult<Self> {
+        config.validate()?;
+        let masks = CellMasks::new(&config);
+        let mut cache = cache_dir.map(|dir| IndexCache::open(dir, &config));
+        let (files, mut skipped) = scan_tile_files(&config)?;
+        let (analyzed, failed) = Self::analyze_files(&config, &masks, files, cache.as_ref());
+        skipped.extend(failed);
+
+        if analyzed.is_empty() {
+            return Err(no_images_error(&skipped));
         }
+        let cache_error = cache
+            .as_mut()
+            .and_then(|cache| store_in_cache(cache, &analyzed));
+        let loaded = analyzed.len();
+        let (tiles, collapsed, duplicate_groups) = collapse_duplicates(analyzed, config.duplicates);
+        let report = LoadReport {
+            loaded,
+            skipped,
+            duplicate_groups,
+            cache_error,
+        };
 
-        // Build KD-tree for fast color matching
-        let color_points: Vec<[f64; 3]> = tiles.iter().map(|t| t.color).collect();
-        let color_index = ImmutableKdTree::new_from_slice(&color_points);
+        // Build KD-trees over region descriptors for fast color matching
+        let color_index = TileIndex::build(config.descriptor_grid, &tiles, false);
+        let portrait_index = TileIndex::build(config.descriptor_grid, &tiles, true);
 
         Ok(Self {
             tiles,
             color_index,
-            src_dir: dir,
-            tile_size: size,
-            sigma_divisor,
-            mask,
+            portrait_index,
+            config,
+            masks,
+            collapsed,
+            report,
+            revision: next_revision(),
         })
     }
 
-    /// Loads tiles from a directory in parallel.
-    fn load_library(src: &Path, size: u32, mask: &GaussianMask) -> AppResult<Vec<Tile>> {
-        // Collect entries first to avoid holding WalkDir in the parallel bridge
-        let entries: Vec<PathBuf> = WalkDir::new(src)
+    /// Computes tile features in parallel, taking them from `cache` for
+    /// unchanged files. Files that fail to decode or are too small are
+    /// returned separately, in directory order.
+    fn analyze_files(
+        config: &LibraryConfig,
+        masks: &CellMasks,
+        files: StampedFiles,
+        cache: Option<&IndexCache>,
+    ) -> (Vec<Tile>, Vec<SkippedFile>) {
+        let results: Vec<Result<Tile, SkippedFile>> = files
+            .into_par_iter()
+            .map(|(path, stamp)| {
+                let hit = cache.zip(stamp).and_then(|(c, s)| c.get(&path, s));
+                let (mut tile, hashes) = match hit {
+                    Some(hit) => {
+                        let mut tile = Tile::new(
+                            path,
+                            hit.color,
+                            hit.descriptor.clone(),
+                            hit.structure.clone(),
+                        );
+                        tile.portrait = hit.portrait;
+                        tile.crop = hit.crop;
+                        tile.variants = hit.variants.clone();
+                        (tile, hit.hashes)
+                    }
+                    None => {
+                        let img = match load_image_with_orientation(&path) {
+                            Ok(img) => img,
+                            Err(e) => {
+                                return Err(SkippedFile::new(
+                                    path,
+                                    SkipReason::DecodeError,
+                                    e.to_string(),
+                                ))
+                            }
+                        };
+                        let (width, height) = img.dimensions();
+                        if width.min(height) < MIN_TILE_SOURCE_SIZE {
+                            return Err(SkippedFile::new(
+                                path,
+                                SkipReason::TooSmall,
+                                format!(
+                                    "{}x{} is below the {}px minimum",
+                                    width, height, MIN_TILE_SOURCE_SIZE
+                                ),
+                            ));
+                        }
+                        // Only orientation-aware libraries keep portrait photos upright
+                        let portrait = config.orientation_aware && height > width;
+                        let (size, mask) = masks.get(portrait);
+                        // Rendering reuses the crop, so tiles look like their colors
+                        let crop = config.crop_strategy.choose(&img, size);
+                        let tile_img = fill_cell(&img, crop, size);
+                        // Calculate color and region descriptor from resized image
+                        let color = avg_color_with_mask(&tile_img, mask, config.color_space);
+                        let descriptor = |img: &DynamicImage| match config.descriptor_grid {
+                            DescriptorGrid::Single => {
+                                avg_color_with_mask(img, mask, config.color_space).to_vec()
+                            }
+                            grid => compute_descriptor(img, mask, config.color_space, grid),
+                        };
+                        let variants = config
+                            .tile_transforms
+                            .variants(size.width == size.height)
+                            .into_iter()
+                            .map(|transform| {
+                                let img =
+                                    DynamicImage::ImageRgba8(transform.apply(tile_img.to_rgba8()));
+                                TileVariant {
+                                    transform,
+                                    descriptor: descriptor(&img),
+                                    structure: luma_thumbnail(&img),
+                                }
+                            })
+                            .collect();
+                        let mut tile = Tile::new(
+                            path,
+                            color,
+                            descriptor(&tile_img),
+                            luma_thumbnail(&tile_img),
+                        );
+                        tile.portrait = portrait;
+                        tile.crop = crop;
+                        tile.variants = variants;
+                        (tile, PerceptualHashes::compute(&img))
+                    }
+                };
+                tile.stamp = stamp;
+                tile.hashes = Some(hashes);
+                Ok(tile)
+            })
+            .collect();
+
+        let mut tiles = Vec::with_capacity(results.len());
+        let mut skipped = Vec::new();
+        for result in results {
+            match result {
+                Ok(tile) => tiles.push(tile),
+                Err(file) => skipped.push(file),
+            }
+        }
+        (tiles, skipped)
+    }
+
+    /// Re-reads the tile directory and applies added, removed and modified
+    /// files. Unchanged tiles keep their features and loaded images, and the
+    /// KD-tree is only rebuilt when something changed.
+    pub fn rescan(&mut self, cache_dir: Option<&Path>) -> AppResult<RescanSummary> {
+        let mut cache = cache_dir.map(|dir| IndexCache::open(dir, &self.config));
+        let (files, mut skipped) = scan_tile_files(&self.config)?;
+        // Collapsed near-duplicates are diffed like every other file
+        let previous: Vec<&Tile> = self.tiles.iter().chain(&self.collapsed).collect();
+        let known: HashMap<&Path, usize> = previous
+            .iter()
+            .enumerate()
+            .map(|(i, tile)| (tile.path.as_path(), i))
+            .collect();
+
+        let changed: StampedFiles = files
+            .iter()
+            .filter(|(path, stamp)| {
+                known
+                    .get(path.as_path())
+                    .is_none_or(|&i| previous[i].stamp != *stamp)
+            })
+            .cloned()
+            .collect();
+        // Previously skipped files are never known, so they are retried here
+        let (analyzed, failed) =
+            Self::analyze_files(&self.config, &self.masks, changed, cache.as_ref());
+        skipped.extend(failed);
+        let mut analyzed: HashMap<PathBuf, Tile> = analyzed
             .into_iter()
-            .filter_map(|e| e.ok())
-            .filter(|e| e.file_type().is_file())
-            .map(|e| e.path().to_path_buf())
+            .map(|tile| (tile.path.clone(), tile))
             .collect();
 
-        let tiles: Vec<Tile> = entries
-            .into_par_iter()
-            .filter_map(|path| {
-                let ext = path.extension()?.to_string_lossy().to_lowercase();
-                if !SUPPORTED_EXTENSIONS.contains(&ext.as_str()) {
-                    return None;
+        enum Slot {
+            Unchanged(usize),
+            Analyzed(Box<Tile>),
+        }
+
+        // Keep directory order: unchanged tiles as they are, changed ones re-analyzed
+        let mut summary = RescanSummary::default();
+        let mut kept: Vec<Slot> = Vec::with_capacity(files.len());
+        for (path, stamp) in &files {
+            match (analyzed.remove(path), known.get(path.as_path()).copied()) {
+                (Some(tile), Some(_)) => {
+                    summary.modified += 1;
+                    kept.push(Slot::Analyzed(Box::new(tile)));
+                }
+                (Some(tile), None) => {
+                    summary.added += 1;
+                    kept.push(Slot::Analyzed(Box::new(tile)));
                 }
+                (None, Some(i)) if previous[i].stamp == *stamp => kept.push(Slot::Unchanged(i)),
+                (None, _) => {}
+            }
+        }
+        summary.removed = previous.len() - (kept.len() - summary.added);
 
-                let tile_img = load_resized_image_with_orientation(&path, size).ok()?;
-                // Calculate color from resized image
-                let color = avg_rgb_with_mask(&tile_img, mask);
+        if kept.is_empty() {
+            return Err(no_images_error(&skipped));
+        }
+        self.report.loaded = kept.len();
+        self.report.skipped = skipped;
+        if !summary.has_changes() {
+            summary.tile_count = self.tiles.len();
+            return Ok(summary);
+        }
 
-                Some(Tile::new(path, color, size))
+        let mut previous: Vec<Option<Tile>> = std::mem::take(&mut self.tiles)
+            .into_iter()
+            .chain(std::mem::take(&mut self.collapsed))
+            .map(Some)
+            .collect();
+        let all: Vec<Tile> = kept
+            .into_iter()
+            .filter_map(|slot| match slot {
+                Slot::Unchanged(i) => previous[i].take(),
+                Slot::Analyzed(tile) => Some(*tile),
             })
             .collect();
+        self.report.cache_error = cache.as_mut().and_then(|cache| store_in_cache(cache, &all));
+        let (tiles, collapsed, duplicate_groups) = collapse_duplicates(all, self.config.duplicates);
+        self.tiles = tiles;
+        self.collapsed = collapsed;
+        self.report.duplicate_groups = duplicate_groups;
+        summary.tile_count = self.tiles.len();
+
+        self.color_index = TileIndex::build(self.config.descriptor_grid, &self.tiles, false);
+        self.portrait_index = TileIndex::build(self.config.descriptor_grid, &self.tiles, true);
+        self.revision = next_revision();
+
+        Ok(summary)
+    }
 
-        Ok(tiles)
+    /// Directories the library was loaded from.
+    #[must_use]
+    pub fn sources(&self) -> &[TileSource] {
+        &self.config.sources
     }
 
-    /// Checks if the library matches the given configuration.
-    #[inline]
+    /// Files loaded and skipped by the last load or rescan.
     #[must_use]
-    pub fn matches_config(&self, dir: &Path, size: u32, sigma_divisor: f64) -> bool {
-        self.src_dir == dir && self.tile_size == size && self.sigma_divisor == sigma_divisor
+    pub fn load_report(&self) -> &LoadReport {
+        &self.report
     }
 
-    /// Generates a mosaic from a target image.
-    pub fn generate_mosaic(
-        &mut self,
-        target_path: &str,
-        config: &MosaicConfig,
-    ) -> AppResult<String> {
+    /// Compares the colors of the library's tiles with those of the target's
+    /// cells at the library tile size, to show which colors the library lacks.
+    pub fn color_coverage(&self, target_path: &str) -> AppResult<CoverageReport> {
         let target_img = load_image_with_orientation(target_path)?;
         let (orig_w, orig_h) = target_img.dimensions();
-
-        let (pad_w, pad_h) = padded_dimensions(orig_w, orig_h, self.tile_size);
+        let (cell, mask) = self.masks.get(false);
+        let (pad_w, pad_h) = padded_dimensions(orig_w, orig_h, cell);
         let target = pad_target_to_tile_grid(&target_img, orig_w, orig_h, pad_w, pad_h);
-        let coords = build_tile_coordinates(pad_w, pad_h, self.tile_size);
+        let space = self.config.color_space;
 
-        // Process tiles sequentially with usage tracking
-        // Penalty must be applied sequentially to maintain deterministic results
-        let mut usage_counts = vec![0usize; self.tiles.len()];
-        let matches: Vec<usize> = coords
-            .iter()
+        // Cells are averaged exactly like tiles, then both are binned in sRGB
+        let cell_colors: Vec<[f64; 3]> = build_tile_coordinates(pad_w, pad_h, cell)
+            .par_iter()
             .map(|&(x, y)| {
-                let region = target.view(x, y, self.tile_size, self.tile_size).to_image();
-                let target_color = avg_rgb_with_mask(&DynamicImage::ImageRgba8(region), &self.mask);
-                let best_idx =
-                    self.find_best_tile(target_color, &usage_counts, config.penalty_factor);
-                usage_counts[best_idx] += 1;
-                best_idx
+                let region = target.view(x, y, cell.width, cell.height).to_image();
+                let color = avg_color_with_mask(&DynamicImage::ImageRgba8(region), mask, space);
+                space.to_srgb(color)
             })
             .collect();
+        let target_histogram = ColorHistogram::from_colors(cell_colors);
+        let library_histogram =
+            ColorHistogram::from_colors(self.tiles.iter().map(|tile| space.to_srgb(tile.color)));
 
-        // Build canvas sequentially (required for image operations)
-        // Use RgbaImage for better quality preservation
-        let mut canvas = ImageBuffer::<image::Rgba<u8>, Vec<u8>>::new(pad_w, pad_h);
-        for ((x, y), &best_idx) in coords.iter().zip(matches.iter()) {
-            // Load image if not cached
-            let tile_img = self.tiles[best_idx].get_image()?;
-            // Convert to RgbaImage for overlay to ensure proper format
-            let tile_rgba = tile_img.to_rgba8();
-            image::imageops::overlay(&mut canvas, &tile_rgba, *x as i64, *y as i64);
-        }
+        Ok(compare_histograms(&target_histogram, &library_histogram))
+    }
+
+    /// Checks if the library matches the given configuration.
+    #[inline]
+    #[must_use]
+    pub fn matches_config(&self, config: &LibraryConfig) -> bool {
+        self.config == *config
+    }
+
+    /// Checks if `plan` was made by this library, since its last rescan, for
+    /// the same target and configuration.
+    #[must_use]
+    pub fn is_current(&self, plan: &MosaicPlan, target_path: &str, config: &MosaicConfig) -> bool {
+        plan.revision == self.revision && plan.target_path == target_path && plan.config == *config
+    }
 
-        // Crop back to original user dimensions
-        let final_canvas = image::imageops::crop_imm(&canvas, 0, 0, orig_w, orig_h).to_image();
+    /// Renders a planned mosaic as a PNG data URL.
+    pub fn generate_mosaic(&mut self, plan: &MosaicPlan) -> AppResult<MosaicResult> {
+        let final_canvas = self.render_plan(plan, &plan.config)?;
 
         // Encode as base64 PNG
         let mut buffer = Vec::new();
@@ -359,100 +1241,1354 @@ impl TileLibrary {
             )
             .map_err(|e| AppError::Image(format!("Failed to encode image: {}", e)))?;
 
-        Ok(format!(
-            "data:image/png;base64,{}",
-            general_purpose::STANDARD.encode(buffer)
-        ))
+        Ok(MosaicResult {
+            image: format!(
+                "data:image/png;base64,{}",
+                general_purpose::STANDARD.encode(buffer)
+            ),
+            placements: self.placement_manifest(plan).placements,
+            unmet_constraints: plan.unmet.clone(),
+            layout: plan.layout.clone(),
+        })
     }
 
-    /// Finds the best tile match considering both color and usage penalty.
-    /// Uses KD-tree acceleration for O(log n) lookup.
-    #[inline]
-    fn find_best_tile(
-        &self,
-        target_color: [f64; 3],
-        usage_counts: &[usize],
-        penalty: f64,
-    ) -> usize {
-        // Adaptive k: query 10-100 nearest neighbors
-        let k = (self.tiles.len() / KD_TREE_K_DIVISOR)
-            .clamp(KD_TREE_K_MIN, KD_TREE_K_MAX)
-            .min(self.tiles.len())
-            .max(1);
-
-        // k is guaranteed to be >= 1, so unwrap is safe
-        let k_nonzero = NonZeroUsize::new(k).unwrap();
-        let nearest = self
-            .color_index
-            .nearest_n::<SquaredEuclidean>(&target_color, k_nonzero);
-
-        // Find best among candidates considering penalty
-        let mut best_idx = 0;
-        let mut min_score = f64::MAX;
-
-        for neighbor in nearest {
-            let idx = neighbor.item as usize;
-            let color_dist = neighbor.distance;
-            let penalty_score = usage_counts[idx] as f64 * penalty * PENALTY_MULTIPLIER;
-            let total_score = color_dist + penalty_score;
-
-            if total_score < min_score {
-                min_score = total_score;
-                best_idx = idx;
-            }
-        }
+    /// Renders a planned mosaic and writes it to `path` instead of returning pixel data.
+    pub fn save_mosaic(
+        &mut self,
+        plan: &MosaicPlan,
+        path: &Path,
+        options: &ExportOptions,
+    ) -> AppResult<SavedMosaic> {
+        options.validate()?;
+        let config = &plan.config;
+
+        // Large PNG/TIFF output is streamed in bands instead of held in memory
+        let pixels = plan.width as u64 * plan.height as u64;
+        let file_size = if options.format.supports_streaming() && pixels > STREAMING_MIN_PIXELS {
+            write_image_strips(path, plan.width, plan.height, options, |sink| {
+                self.render_streamed(plan, config, plan.band_height(), sink)
+            })?
+        } else {
+            let canvas = self.render_plan(plan, config)?;
+            write_image(&canvas, path, options)?
+        };
 
-        best_idx
+        Ok(SavedMosaic {
+            path: path.to_path_buf(),
+            file_size,
+            unmet_constraints: plan.unmet.clone(),
+        })
     }
-}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use image::{ImageBuffer, Rgba};
-    use std::time::{SystemTime, UNIX_EPOCH};
 
-    fn build_test_library() -> TileLibrary {
-        let points = [[0.0, 0.0, 0.0]];
-        TileLibrary {
-            tiles: vec![Tile::new(
-                PathBuf::from("/tmp/tile.png"),
-                [0.0, 0.0, 0.0],
-                32,
-            )],
-            color_index: ImmutableKdTree::new_from_slice(&points),
-            src_dir: PathBuf::from("/tmp/tiles"),
-            tile_size: 32,
-            sigma_divisor: 4.0,
-            mask: GaussianMask::new(32, 4.0),
-        }
+    /// Writes a planned mosaic as a Deep Zoom pyramid at `dzi_path`.
+    /// The finest level is composed from the original tile files at render size
+    /// and streamed in bands; coarser levels are averaged down from it.
+    pub fn export_deep_zoom(
+        &self,
+        plan: &MosaicPlan,
+        dzi_path: &Path,
+        options: &DeepZoomOptions,
+    ) -> AppResult<DeepZoomExport> {
+        options.validate()?;
+
+        let summary = write_deep_zoom(dzi_path, plan.width, plan.height, options, |sink| {
+            self.render_streamed(plan, &plan.config, plan.band_height(), sink)
+        })?;
+
+        Ok(DeepZoomExport {
+            path: dzi_path.to_path_buf(),
+            width: plan.width,
+            height: plan.height,
+            levels: summary.levels,
+            tile_count: summary.tile_count,
+            unmet_constraints: plan.unmet.clone(),
+        })
     }
 
-    #[test]
-    fn matches_config_includes_sigma_divisor() {
-        let lib = build_test_library();
-
-        assert!(lib.matches_config(Path::new("/tmp/tiles"), 32, 4.0));
-        assert!(!lib.matches_config(Path::new("/tmp/tiles"), 32, 8.0));
+    /// Writes which file went where in a planned mosaic to `path`, without
+    /// rendering it.
+    pub fn export_manifest(
+        &self,
+        plan: &MosaicPlan,
+        path: &Path,
+        format: ManifestFormat,
+    ) -> AppResult<SavedManifest> {
+        let manifest = self.placement_manifest(plan);
+        let file_size = write_manifest(&manifest, path, format)?;
+
+        Ok(SavedManifest {
+            path: path.to_path_buf(),
+            file_size,
+            placement_count: manifest.placements.len(),
+        })
     }
 
-    #[test]
-    fn load_resized_image_with_orientation_resizes_to_tile_size() {
-        let timestamp = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_nanos();
-        let test_path = std::env::temp_dir().join(format!("mosaic-test-{}.png", timestamp));
+    /// Lists the placements of a plan, with rectangles clipped to the output.
+    fn placement_manifest(&self, plan: &MosaicPlan) -> PlacementManifest {
+        let mut usage = vec![0u32; self.tiles.len()];
+        for &idx in &plan.tiles {
+            usage[idx] += 1;
+        }
 
-        let img: ImageBuffer<Rgba<u8>, Vec<u8>> =
-            ImageBuffer::from_fn(20, 10, |_x, _y| Rgba([255, 0, 0, 255]));
-        img.save(&test_path).unwrap();
+        // Padding is smaller than a grid cell, so every cell overlaps the output
+        let placements = plan
+            .layout
+            .cells
+            .iter()
+            .zip(&plan.tiles)
+            .enumerate()
+            .map(|(cell, (placed, &idx))| {
+                let (x, y, size) = plan.layout.cell_rect(placed, plan.render_cell);
+                let clip = |start: i64, length: u32, limit: u32| {
+                    let end = (start + length as i64).min(limit as i64);
+                    let start = start.max(0);
+                    (start as u32, (end - start).max(0) as u32)
+                };
+                let (x, width) = clip(x, size.width, plan.width);
+                let (y, height) = clip(y, size.height, plan.height);
+                Placement {
+                    row: placed.row,
+                    column: placed.column,
+                    x,
+                    y,
+                    width,
+                    height,
+                    tile_path: self.tiles[idx].path.clone(),
+                    transform: plan.transforms[cell],
+                    color_distance: plan.color_errors[cell].sqrt(),
+                    usage_count: usage[idx],
+                }
+            })
+            .collect();
 
-        let resized = load_resized_image_with_orientation(&test_path, 8).unwrap();
+        PlacementManifest {
+            width: plan.width,
+            height: plan.height,
+            columns: plan.layout.columns,
+            rows: plan.layout.rows,
+            tile_size: plan.tile_size,
+            render_tile_size: plan.render_size,
+            placements,
+        }
+    }
 
-        assert_eq!(resized.dimensions(), (8, 8));
+    /// Composes a planned mosaic in one piece, caching tiles at each size they are rendered at.
+    fn render_plan(&mut self, plan: &MosaicPlan, config: &MosaicConfig) -> AppResult<RgbaImage> {
+        if plan.width as u64 * plan.height as u64 > MAX_CANVAS_PIXELS {
+            return Err(AppError::Config(format!(
+                "Output of {}x{} pixels is too large to render in memory. \
+                 Lower render_tile_size or save as PNG or TIFF",
+                plan.width, plan.height
+            )));
+        }
 
-        std::fs::remove_file(test_path).unwrap();
+        let tiles = &mut self.tiles;
+        plan.compose_band(config, 0..plan.height, |idx, size| {
+            tiles[idx].get_image(size)
+        })
+    }
+
+    /// Composes a planned mosaic in bands of `band_height` pixel rows and
+    /// passes each band to `sink`. Tiles are loaded per band and dropped
+    /// afterwards, so memory stays bounded by the band height.
+    fn render_streamed(
+        &self,
+        plan: &MosaicPlan,
+        config: &MosaicConfig,
+        band_height: u32,
+        sink: &mut StripSink,
+    ) -> AppResult<()> {
+        for top in (0..plan.height).step_by(band_height.max(1) as usize) {
+            let band = top..(top + band_height).min(plan.height);
+
+            let mut used: Vec<(usize, CellSize)> = plan.tiles_in_band(band.clone()).collect();
+            used.sort_unstable();
+            used.dedup();
+            let loaded: HashMap<(usize, CellSize), Arc<DynamicImage>> = used
+                .into_par_iter()
+                .map(|(idx, size)| {
+                    let img = self.tiles[idx].load_image(size)?;
+                    Ok(((idx, size), Arc::new(img)))
+                })
+                .collect::<AppResult<_>>()?;
+
+            let strip = plan.compose_band(config, band, |idx, size| {
+                Ok(Arc::clone(&loaded[&(idx, size)]))
+            })?;
+            sink(&strip)?;
+        }
+        Ok(())
+    }
+
+    /// Lays out the target's cells and assigns a tile to every one of them.
+    pub fn plan_mosaic(&self, target_path: &str, config: &MosaicConfig) -> AppResult<MosaicPlan> {
+        let target_img = load_image_with_orientation(target_path)?;
+        let (orig_w, orig_h) = target_img.dimensions();
+
+        let tile_size = self.config.tile_size;
+        let render_size = match config.render_tile_size {
+            0 => tile_size,
+            size => size,
+        };
+        let shape = self.config.cell_shape;
+        if config.quadtree.is_some() && shape != CellShape::Square {
+            return Err(AppError::Config(
+                "The quadtree layout only supports square cells".into(),
+            ));
+        }
+        if config.quadtree.is_some() && self.config.orientation_aware {
+            return Err(AppError::Config(
+                "The quadtree layout cannot be combined with orientation-aware cells".into(),
+            ));
+        }
+        if let Some(ref options) = config.quadtree {
+            validate_largest_cell(render_size, options)?;
+        }
+        // Hexagon rows overlap by a quarter cell and shift by half of one
+        if shape == CellShape::Hexagon
+            && (!tile_size.is_multiple_of(4) || !render_size.is_multiple_of(4))
+        {
+            return Err(AppError::Config(format!(
+                "Hexagonal cells need tile_size and render_tile_size divisible by 4, got {} and {}",
+                tile_size, render_size
+            )));
+        }
+
+        let cell = self.config.cell_size();
+        let render_cell = self.config.tile_aspect.cell_size(render_size);
+        let (pad_w, pad_h) = padded_dimensions(orig_w, orig_h, cell);
+        let target = pad_target_to_tile_grid(&target_img, orig_w, orig_h, pad_w, pad_h);
+        let (layout, cells) = match config.quadtree {
+            _ if self.config.orientation_aware => {
+                self.orientation_aware_layout(&target, orig_w, config)
+            }
+            Some(ref options) => {
+                let layout = MosaicLayout::quadtree(&target, cell, options);
+                let cells = self.analyze_layout(&layout, &target, config);
+                (layout, cells)
+            }
+            None => {
+                let layout = MosaicLayout::regular(shape, orig_w, orig_h, cell);
+                let cells = self.analyze_layout(&layout, &target, config);
+                (layout, cells)
+            }
+        };
+
+        // Candidate lookup is independent per cell
+        let scored: Vec<Vec<(usize, VariantMatch)>> = cells
+            .par_iter()
+            .map(|cell| self.candidate_costs(cell, config))
+            .collect();
+        let candidates: Vec<Vec<(usize, f64)>> = scored
+            .iter()
+            .map(|cell| cell.iter().map(|&(idx, found)| (idx, found.cost)).collect())
+            .collect();
+        let matches: Vec<HashMap<usize, VariantMatch>> = scored
+            .into_iter()
+            .map(|cell| cell.into_iter().collect())
+            .collect();
+        // Candidates already know their best variant; only other tiles search again
+        let best_match = |cell: usize, idx: usize| match matches[cell].get(&idx) {
+            Some(&found) => found,
+            None => self.best_variant(&cells[cell], idx, config),
+        };
+
+        // Assignment runs sequentially to keep results deterministic. The
+        // solver's nearest-tile lookup borrows the cells until it is dropped.
+        let outcome = {
+            let solver = AssignmentSolver::new(
+                &candidates,
+                self.tiles.len(),
+                config.penalty_factor * PENALTY_MULTIPLIER,
+                |cell, idx| best_match(cell, idx).cost,
+            );
+            let radius = config.min_repeat_distance;
+            let solver = if layout.is_plain_grid() {
+                solver.with_exclusion(layout.columns as usize, radius as usize)
+            } else if radius > 0 {
+                solver.with_neighbor_exclusion(layout.neighbors(radius))
+            } else {
+                solver
+            }
+            .with_usage_cap(config.max_uses_per_tile as usize)
+            .with_every_tile_used(config.use_every_tile)
+            .with_nearest_tiles(|cell, k| self.nearest_tiles(&cells[cell], k));
+            let solver = match config.use_every_tile {
+                true => solver.with_cover_cells(self.cover_cells(&cells)),
+                false => solver,
+            };
+            solver.solve(config.assignment_mode)
+        };
+        // The solver counts uses per photo; each cell then gets the photo's best variant
+        let (transforms, color_errors) = outcome
+            .tiles
+            .iter()
+            .enumerate()
+            .map(|(cell, &idx)| {
+                let found = best_match(cell, idx);
+                (found.transform, found.color_error)
+            })
+            .unzip();
+
+        // Output is the original size scaled to the render cell; padding is cropped
+        let scale = |length: u32, from: u32, to: u32| {
+            (length as u64 * to as u64).div_ceil(from as u64) as u32
+        };
+
+        Ok(MosaicPlan {
+            target_path: target_path.to_string(),
+            config: *config,
+            revision: self.revision,
+            target,
+            layout,
+            cells,
+            tiles: outcome.tiles,
+            transforms,
+            color_errors,
+            unmet: outcome.unmet,
+            tile_size,
+            render_size,
+            cell,
+            render_cell,
+            width: scale(orig_w, cell.width, render_cell.width),
+            height: scale(orig_h, cell.height, render_cell.height),
+        })
+    }
+
+    /// Computes the matching features of every cell of `layout`, in parallel.
+    fn analyze_layout(
+        &self,
+        layout: &MosaicLayout,
+        target: &RgbaImage,
+        config: &MosaicConfig,
+    ) -> Vec<TargetCell> {
+        let cell = self.config.cell_size();
+        layout
+            .cells
+            .par_iter()
+            .map(|placed| {
+                let (x, y, size) = layout.cell_rect(placed, cell);
+                let region = cell_region(target, x, y, size);
+                // Larger cells are matched at the size tiles were analyzed at
+                let region = match placed.span {
+                    1 => region,
+                    _ => imageops::resize(&region, cell.width, cell.height, FilterType::Triangle),
+                };
+                self.analyze_cell(&DynamicImage::ImageRgba8(region), placed.portrait, config)
+            })
+            .collect()
+    }
+
+    /// Fills every row from the left with landscape cells, putting a narrower
+    /// portrait cell wherever the closest portrait tile matches the target
+    /// better than the closest landscape one. Returns the layout with the
+    /// matching features of its cells.
+    fn orientation_aware_layout(
+        &self,
+        target: &RgbaImage,
+        width: u32,
+        config: &MosaicConfig,
+    ) -> (MosaicLayout, Vec<TargetCell>) {
+        let (cell, _) = self.masks.get(false);
+        let (portrait, _) = self.masks.get(true);
+        let rows: Vec<Vec<TargetCell>> = (0..target.height() / cell.height)
+            .into_par_iter()
+            .map(|row| {
+                let y = i64::from(row * cell.height);
+                let analyze = |x: u32, is_portrait: bool, size: CellSize| {
+                    let region = cell_region(target, i64::from(x), y, size);
+                    let features =
+                        self.analyze_cell(&DynamicImage::ImageRgba8(region), is_portrait, config);
+                    let cost = self.closest_cost(&features, config);
+                    (features, cost)
+                };
+
+                let mut cells = Vec::new();
+                let mut x = 0;
+                while x < width {
+                    let (landscape, landscape_cost) = analyze(x, false, cell);
+                    let (upright, upright_cost) = analyze(x, true, portrait);
+                    if upright_cost < landscape_cost {
+                        x += portrait.width;
+                        cells.push(upright);
+                    } else {
+                        x += cell.width;
+                        cells.push(landscape);
+                    }
+                }
+                cells
+            })
+            .collect();
+
+        let portraits: Vec<Vec<bool>> = rows
+            .iter()
+            .map(|row| row.iter().map(|cell| cell.portrait).collect())
+            .collect();
+        let layout = MosaicLayout::orientation_aware(width, cell, &portraits);
+        (layout, rows.into_iter().flatten().collect())
+    }
+
+    /// Computes the matching features of a target cell.
+    fn analyze_cell(
+        &self,
+        region: &DynamicImage,
+        portrait: bool,
+        config: &MosaicConfig,
+    ) -> TargetCell {
+        let space = self.config.color_space;
+        let (_, mask) = self.masks.get(portrait);
+        let descriptor = compute_descriptor(region, mask, space, self.config.descriptor_grid);
+        // Re-ranking needs the target regions in CIELAB regardless of the index space
+        let lab = config.ciede2000_rerank.then(|| {
+            descriptor
+                .chunks_exact(3)
+                .map(|c| space.to_lab([c[0], c[1], c[2]]))
+                .collect()
+        });
+        let structure = (config.structure_weight > 0.0).then(|| luma_thumbnail(region));
+        let mean = (config.color_correction > 0.0).then(|| mean_linear_rgb(region));
+        TargetCell {
+            descriptor,
+            lab,
+            structure,
+            mean,
+            portrait,
+        }
+    }
+
+    /// The cells closest to each tile by descriptor, from a KD-tree over the
+    /// target cells. Covering unused tiles only tries these, instead of
+    /// every cell of the mosaic.
+    fn cover_cells(&self, cells: &[TargetCell]) -> Vec<Vec<usize>> {
+        let descriptors: Vec<Vec<f64>> = cells.iter().map(|cell| cell.descriptor.clone()).collect();
+        if descriptors.is_empty() {
+            return Vec::new();
+        }
+        let index = DescriptorIndex::build(self.config.descriptor_grid, &descriptors);
+        // Non-zero since descriptors is not empty
+        let k = NonZeroUsize::new(COVER_CELLS_K.min(descriptors.len())).unwrap();
+        self.tiles
+            .par_iter()
+            .map(|tile| {
+                index
+                    .nearest_n(&tile.descriptor, k)
+                    .into_iter()
+                    .map(|(cell, _)| cell)
+                    .collect()
+            })
+            .collect()
+    }
+
+    /// Indexes of the tiles for a cell's orientation and then of the other
+    /// one, skipping orientations the library has no tiles of.
+    fn indexes_for(&self, cell: &TargetCell) -> impl Iterator<Item = &TileIndex> {
+        let (own, other) = match cell.portrait {
+            true => (&self.portrait_index, &self.color_index),
+            false => (&self.color_index, &self.portrait_index),
+        };
+        own.iter().chain(other)
+    }
+
+    /// Distinct tiles of `index` nearest to a cell, nearest first, from a
+    /// query with room for `k` tiles and each of their variants.
+    fn nearest_in(&self, index: &TileIndex, cell: &TargetCell, k: usize) -> Vec<usize> {
+        let (size, _) = self.masks.get(cell.portrait);
+        let square = size.width == size.height;
+        let variants = 1 + self.config.tile_transforms.variants(square).len();
+        let mut seen = HashSet::new();
+        index
+            .nearest_n(&cell.descriptor, k * variants)
+            .into_iter()
+            .filter_map(|(idx, _)| seen.insert(idx).then_some(idx))
+            .collect()
+    }
+
+    /// At least `k` tiles nearest to a cell when the library has that many,
+    /// those of the other orientation following once its own run out.
+    fn nearest_tiles(&self, cell: &TargetCell, k: usize) -> Vec<usize> {
+        let mut tiles = Vec::new();
+        for index in self.indexes_for(cell) {
+            if tiles.len() >= k {
+                break;
+            }
+            tiles.extend(self.nearest_in(index, cell, k - tiles.len()));
+        }
+        tiles
+    }
+
+    /// Match cost of the closest tile of the cell's own orientation, or
+    /// infinity when the library has none.
+    fn closest_cost(&self, cell: &TargetCell, config: &MosaicConfig) -> f64 {
+        let index = match cell.portrait {
+            true => &self.portrait_index,
+            false => &self.color_index,
+        };
+        index
+            .as_ref()
+            .and_then(|index| index.nearest_n(&cell.descriptor, 1).first().copied())
+            .map_or(f64::INFINITY, |(idx, _)| self.match_cost(cell, idx, config))
+    }
+
+    /// Returns the nearest tiles for a cell with their best variant for it.
+    /// Uses KD-tree acceleration for O(log n) lookup.
+    fn candidate_costs(
+        &self,
+        cell: &TargetCell,
+        config: &MosaicConfig,
+    ) -> Vec<(usize, VariantMatch)> {
+        // Other orientations only stand in when the library has none of the cell's
+        let Some(index) = self.indexes_for(cell).next() else {
+            return Vec::new();
+        };
+        // Adaptive k: query 10-100 nearest tiles
+        let k = (self.tiles.len() / KD_TREE_K_DIVISOR).clamp(KD_TREE_K_MIN, KD_TREE_K_MAX);
+        self.nearest_in(index, cell, k)
+            .into_iter()
+            .map(|idx| (idx, self.best_variant(cell, idx, config)))
+            .collect()
+    }
+
+    /// Mean squared color distance per region between a cell and a tile
+    /// descriptor, measured with CIEDE2000 when the cell carries CIELAB colors.
+    fn color_error(&self, cell: &TargetCell, descriptor: &[f64]) -> f64 {
+        let space = self.config.color_space;
+        let regions = self.config.descriptor_grid.regions() as f64;
+
+        let sum: f64 = match cell.lab {
+            Some(ref lab) => lab
+                .iter()
+                .zip(descriptor.chunks_exact(3))
+                .map(|(target, c)| {
+                    let delta_e = ciede2000(*target, space.to_lab([c[0], c[1], c[2]]));
+                    delta_e * delta_e
+                })
+                .sum(),
+            None => cell
+                .descriptor
+                .iter()
+                .zip(descriptor)
+                .map(|(a, b)| (a - b) * (a - b))
+                .sum(),
+        };
+        sum / regions
+    }
+
+    /// Cost of placing a tile in a cell, excluding usage penalty, with the
+    /// tile's best variant for it.
+    #[inline]
+    fn match_cost(&self, cell: &TargetCell, idx: usize, config: &MosaicConfig) -> f64 {
+        self.best_variant(cell, idx, config).cost
+    }
+
+    /// The variant of a tile that fits a cell best with its cost from color
+    /// and structure. Distances are averaged per region so the penalty scale
+    /// is grid independent. Ties keep the original orientation.
+    fn best_variant(&self, cell: &TargetCell, idx: usize, config: &MosaicConfig) -> VariantMatch {
+        let tile = &self.tiles[idx];
+        let scale = match cell.lab {
+            Some(_) => LAB_DISTANCE_SCALE,
+            None => self.config.color_space.distance_scale(),
+        };
+        // A tile in a cell of the other orientation is cropped to fit
+        let mismatch = match tile.portrait == cell.portrait {
+            true => 0.0,
+            false => ORIENTATION_MISMATCH_COST,
+        };
+
+        tile.features()
+            .map(|(transform, descriptor, structure)| {
+                let color_error = self.color_error(cell, descriptor);
+                // SSIM is 1 for identical structure, so dissimilarity is in 0..=2
+                let structure_score = match cell.structure {
+                    Some(ref target) => {
+                        let dissimilarity = 1.0 - ssim(target, structure, &self.masks.structure);
+                        dissimilarity * config.structure_weight * STRUCTURE_MULTIPLIER
+                    }
+                    None => 0.0,
+                };
+                VariantMatch {
+                    transform,
+                    color_error,
+                    cost: color_error * scale + structure_score + mismatch,
+                }
+            })
+            .fold(VariantMatch::NONE, |best, variant| {
+                if variant.cost < best.cost {
+                    variant
+                } else {
+                    best
+                }
+            })
+    }
+}
+
+/// A fresh path under the system temp directory, unique to this run.
+#[cfg(test)]
+pub(crate) fn temp_dir(name: &str) -> PathBuf {
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_nanos();
+    std::env::temp_dir().join(format!("mosaic-{}-{}", name, timestamp))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dedupe::{noise, HashKind};
+    use crate::export::OutputFormat;
+    use crate::layout::MAX_DETAIL_THRESHOLD;
+    use image::{ImageBuffer, Rgba};
+
+    fn build_test_library() -> TileLibrary {
+        let tiles = vec![Tile::new(
+            PathBuf::from("/tmp/tile.png"),
+            [0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0],
+            vec![0.0; 64],
+        )];
+        TileLibrary {
+            color_index: TileIndex::build(DescriptorGrid::Single, &tiles, false),
+            portrait_index: None,
+            tiles,
+            config: test_library_config(),
+            masks: CellMasks::new(&test_library_config()),
+            collapsed: Vec::new(),
+            report: LoadReport {
+                loaded: 1,
+                ..LoadReport::default()
+            },
+            revision: next_revision(),
+        }
+    }
+
+    fn test_library_config() -> LibraryConfig {
+        LibraryConfig {
+            sources: vec![TileSource::new("/tmp/tiles")],
+            tile_size: 32,
+            sigma_divisor: 4.0,
+            color_space: ColorSpace::Srgb,
+            descriptor_grid: DescriptorGrid::Single,
+            formats: FormatSet::default(),
+            duplicates: None,
+            cell_shape: CellShape::Square,
+            tile_aspect: TileAspect::default(),
+            orientation_aware: false,
+            crop_strategy: CropStrategy::Center,
+            tile_transforms: TileTransforms::Original,
+        }
+    }
+
+    fn test_mosaic_config() -> MosaicConfig {
+        MosaicConfig {
+            penalty_factor: 0.0,
+            ciede2000_rerank: false,
+            structure_weight: 0.0,
+            assignment_mode: AssignmentMode::Greedy,
+            min_repeat_distance: 0,
+            max_uses_per_tile: 0,
+            use_every_tile: false,
+            color_correction: 0.0,
+            overlay_opacity: 0.0,
+            overlay_blend: BlendMode::Normal,
+            render_tile_size: 0,
+            quadtree: None,
+        }
+    }
+
+    #[test]
+    fn matches_config_includes_sigma_divisor() {
+        let lib = build_test_library();
+
+        assert!(lib.matches_config(&test_library_config()));
+        assert!(!lib.matches_config(&LibraryConfig {
+            sigma_divisor: 8.0,
+            ..test_library_config()
+        }));
+    }
+
+    #[test]
+    fn matches_config_includes_color_space() {
+        let lib = build_test_library();
+
+        assert!(!lib.matches_config(&LibraryConfig {
+            color_space: ColorSpace::Lab,
+            ..test_library_config()
+        }));
+    }
+
+    #[test]
+    fn matches_config_includes_descriptor_grid() {
+        let lib = build_test_library();
+
+        assert!(!lib.matches_config(&LibraryConfig {
+            descriptor_grid: DescriptorGrid::Grid3x3,
+            ..test_library_config()
+        }));
+    }
+
+    #[test]
+    fn matches_config_includes_formats() {
+        let lib = build_test_library();
+
+        assert!(!lib.matches_config(&LibraryConfig {
+            formats: FormatSet::parse(&["png"]).unwrap(),
+            ..test_library_config()
+        }));
+    }
+
+    #[test]
+    fn matches_config_includes_sources() {
+        let lib = build_test_library();
+
+        assert!(!lib.matches_config(&LibraryConfig {
+            sources: vec![TileSource {
+                exclude: vec!["**/.*".into()],
+                ..TileSource::new("/tmp/tiles")
+            }],
+            ..test_library_config()
+        }));
+        assert!(!lib.matches_config(&LibraryConfig {
+            sources: vec![TileSource::new("/tmp/tiles"), TileSource::new("/tmp/more")],
+            ..test_library_config()
+        }));
+    }
+
+    #[test]
+    fn avg_color_with_mask_converts_to_requested_space() {
+        let mask = GaussianMask::new(4, 0.0);
+        let img = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 4, |_x, _y| {
+            Rgba([255, 255, 255, 255])
+        }));
+
+        let srgb = avg_color_with_mask(&img, &mask, ColorSpace::Srgb);
+        let lab = avg_color_with_mask(&img, &mask, ColorSpace::Lab);
+
+        assert!((srgb[0] - 255.0).abs() < 1e-9);
+        assert!((lab[0] - 100.0).abs() < 1e-3);
+        assert!(lab[1].abs() < 1e-3 && lab[2].abs() < 1e-3);
+    }
+
+    #[test]
+    fn load_resized_image_with_orientation_resizes_to_tile_size() {
+        let test_path = temp_dir("test").with_extension("png");
+
+        let img: ImageBuffer<Rgba<u8>, Vec<u8>> =
+            ImageBuffer::from_fn(20, 10, |_x, _y| Rgba([255, 0, 0, 255]));
+        img.save(&test_path).unwrap();
+
+        let resized =
+            load_resized_image_with_orientation(&test_path, CellSize::square(8), None).unwrap();
+        assert_eq!(resized.dimensions(), (8, 8));
+        let resized =
+            load_resized_image_with_orientation(&test_path, CellSize::new(16, 12), None).unwrap();
+        assert_eq!(resized.dimensions(), (16, 12));
+
+        std::fs::remove_file(test_path).unwrap();
+    }
+
+    #[test]
+    fn tile_get_image_reloads_for_a_new_render_size() {
+        let test_path = temp_dir("tile").with_extension("png");
+
+        let img: ImageBuffer<Rgba<u8>, Vec<u8>> =
+            ImageBuffer::from_fn(40, 40, |_x, _y| Rgba([0, 255, 0, 255]));
+        img.save(&test_path).unwrap();
+
+        let mut tile = Tile::new(test_path.clone(), [0.0; 3], vec![0.0; 3], vec![0.0; 64]);
+        let analysis = tile.get_image(CellSize::square(8)).unwrap();
+        let render = tile.get_image(CellSize::square(32)).unwrap();
+
+        assert_eq!(analysis.dimensions(), (8, 8));
+        assert_eq!(render.dimensions(), (32, 32));
+        assert!(Arc::ptr_eq(
+            &render,
+            &tile.get_image(CellSize::square(32)).unwrap()
+        ));
+
+        std::fs::remove_file(test_path).unwrap();
+    }
+
+    /// Writes four small tiles and a 37x29 target into a fresh temp directory
+    /// and loads them with an 8px analysis grid.
+    fn build_disk_fixture(name: &str) -> (PathBuf, TileLibrary, PathBuf) {
+        let dir = temp_dir(name);
+        let tile_dir = dir.join("tiles");
+        std::fs::create_dir_all(&tile_dir).unwrap();
+        for (i, rgb) in [
+            [200u8, 40, 40],
+            [40, 200, 40],
+            [40, 40, 200],
+            [220, 220, 220],
+        ]
+        .iter()
+        .enumerate()
+        {
+            let tile: RgbaImage = ImageBuffer::from_fn(12, 12, |x, y| {
+                Rgba([
+                    rgb[0],
+                    rgb[1].saturating_add((x * 3) as u8),
+                    rgb[2],
+                    (y * 20) as u8,
+                ])
+            });
+            tile.save(tile_dir.join(format!("tile{}.png", i))).unwrap();
+        }
+        let target_path = dir.join("target.png");
+        let target: RgbaImage = ImageBuffer::from_fn(37, 29, |x, y| {
+            Rgba([(x * 6) as u8, (y * 8) as u8, 128, 255])
+        });
+        target.save(&target_path).unwrap();
+
+        let library = TileLibrary::new(LibraryConfig {
+            sources: vec![TileSource::new(tile_dir)],
+            tile_size: 8,
+            ..test_library_config()
+        })
+        .unwrap();
+        (dir, library, target_path)
+    }
+
+    #[test]
+    fn streamed_output_matches_in_memory_output() {
+        let (dir, mut library, target_path) = build_disk_fixture("stream");
+        let config = MosaicConfig {
+            penalty_factor: 10.0,
+            color_correction: 40.0,
+            overlay_opacity: 30.0,
+            overlay_blend: BlendMode::SoftLight,
+            render_tile_size: 20,
+            ..test_mosaic_config()
+        };
+        let plan = library
+            .plan_mosaic(target_path.to_str().unwrap(), &config)
+            .unwrap();
+        let canvas = library.render_plan(&plan, &config).unwrap();
+        assert_eq!(canvas.dimensions(), (93, 73));
+
+        for format in [OutputFormat::Png, OutputFormat::Tiff] {
+            let options = ExportOptions {
+                format,
+                ..ExportOptions::default()
+            };
+            let in_memory = dir.join("in-memory.out");
+            let streamed = dir.join("streamed.out");
+            write_image(&canvas, &in_memory, &options).unwrap();
+            write_image_strips(&streamed, plan.width, plan.height, &options, |sink| {
+                library.render_streamed(&plan, &config, 7, sink)
+            })
+            .unwrap();
+
+            assert_eq!(
+                std::fs::read(&in_memory).unwrap(),
+                std::fs::read(&streamed).unwrap()
+            );
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cells_crossing_band_edges_stream_like_in_memory_output() {
+        // Quadtree cells and overlapping hexagon rows cross band edges, so
+        // they are drawn clipped into each band
+        let (dir, library, target_path) = build_disk_fixture("stream-layouts");
+        let config = MosaicConfig {
+            penalty_factor: 10.0,
+            color_correction: 40.0,
+            overlay_opacity: 30.0,
+            overlay_blend: BlendMode::SoftLight,
+            render_tile_size: 20,
+            ..test_mosaic_config()
+        };
+        let quadtree = Some(QuadtreeOptions {
+            max_cell_span: 4,
+            detail_threshold: MAX_DETAIL_THRESHOLD,
+        });
+        let shaped = |cell_shape| LibraryConfig {
+            cell_shape,
+            ..library.config.clone()
+        };
+        // 4:3 cells of 8x6 pixels are drawn at 20x15, so the output size is unchanged
+        let landscape = LibraryConfig {
+            tile_aspect: TileAspect {
+                width: 4,
+                height: 3,
+            },
+            ..library.config.clone()
+        };
+        for (library_config, quadtree) in [
+            (shaped(CellShape::Square), quadtree),
+            (shaped(CellShape::Brick), None),
+            (shaped(CellShape::Hexagon), None),
+            (landscape.clone(), quadtree),
+            (
+                LibraryConfig {
+                    orientation_aware: true,
+                    ..landscape.clone()
+                },
+                None,
+            ),
+        ] {
+            let mut library = TileLibrary::new(library_config).unwrap();
+            let config = MosaicConfig { quadtree, ..config };
+            let plan = library
+                .plan_mosaic(target_path.to_str().unwrap(), &config)
+                .unwrap();
+            let canvas = library.render_plan(&plan, &config).unwrap();
+            assert_eq!(canvas.dimensions(), (93, 73));
+
+            let options = ExportOptions::default();
+            let in_memory = dir.join("in-memory.png");
+            let streamed = dir.join("streamed.png");
+            write_image(&canvas, &in_memory, &options).unwrap();
+            write_image_strips(&streamed, plan.width, plan.height, &options, |sink| {
+                library.render_streamed(&plan, &config, 7, sink)
+            })
+            .unwrap();
+
+            assert_eq!(
+                std::fs::read(&in_memory).unwrap(),
+                std::fs::read(&streamed).unwrap()
+            );
+        }
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn overlay_is_a_lanczos_resize_of_the_target_in_bands() {
+        let (dir, mut library, target_path) = build_disk_fixture("overlay");
+        let config = MosaicConfig {
+            overlay_opacity: 100.0,
+            render_tile_size: 20,
+            ..test_mosaic_config()
+        };
+        let plan = library
+            .plan_mosaic(target_path.to_str().unwrap(), &config)
+            .unwrap();
+        let canvas = library.render_plan(&plan, &config).unwrap();
+
+        // A fully opaque normal overlay shows only the resized target, up to rounding
+        let resized =
+            image::imageops::resize(&plan.target, 100, 80, image::imageops::FilterType::Lanczos3);
+        for (x, y, pixel) in canvas.enumerate_pixels() {
+            let expected = resized.get_pixel(x, y).0;
+            assert!(
+                (0..3).all(|c| pixel.0[c].abs_diff(expected[c]) <= 1),
+                "({}, {}): {:?} != {:?}",
+                x,
+                y,
+                pixel,
+                expected
+            );
+        }
+        let mut rows = Vec::new();
+        let mut sink = |strip: &RgbaImage| {
+            rows.extend_from_slice(strip.as_raw());
+            Ok(())
+        };
+        library
+            .render_streamed(&plan, &config, 7, &mut sink)
+            .unwrap();
+        assert_eq!(rows, canvas.into_raw());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cached_library_skips_decoding_unchanged_files() {
+        let (dir, library, _) = build_disk_fixture("cached");
+        let cache_dir = dir.join("cache");
+        let config = library.config.clone();
+
+        let first = TileLibrary::with_cache(config.clone(), Some(&cache_dir)).unwrap();
+        assert_eq!(first.tiles.len(), 4);
+
+        // Same size and mtime but undecodable: only a cache hit keeps the tile
+        let tile_path = config.sources[0].dir.join("tile0.png");
+        let metadata = std::fs::metadata(&tile_path).unwrap();
+        std::fs::write(&tile_path, vec![0u8; metadata.len() as usize]).unwrap();
+        std::fs::File::options()
+            .write(true)
+            .open(&tile_path)
+            .unwrap()
+            .set_modified(metadata.modified().unwrap())
+            .unwrap();
+
+        let cached = TileLibrary::with_cache(config.clone(), Some(&cache_dir)).unwrap();
+        let uncached = TileLibrary::new(config.clone()).unwrap();
+        assert_eq!(cached.tiles.len(), 4);
+        assert_eq!(uncached.tiles.len(), 3);
+        for (a, b) in cached.tiles.iter().zip(&first.tiles) {
+            assert_eq!(a.path, b.path);
+            assert_eq!(a.descriptor, b.descriptor);
+            assert_eq!(a.structure, b.structure);
+        }
+        assert_eq!(first.load_report().cache_error, None);
+
+        // A cache that cannot be written still loads, but says why
+        let blocked = dir.join("blocked");
+        std::fs::write(&blocked, b"not a directory").unwrap();
+        let library = TileLibrary::with_cache(config, Some(&blocked)).unwrap();
+        assert_eq!(library.tiles.len(), 3);
+        assert!(library.load_report().cache_error.is_some());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn smart_crop_is_stored_cached_and_rendered() {
+        let dir = temp_dir("crop");
+        let tile_dir = dir.join("tiles");
+        std::fs::create_dir_all(&tile_dir).unwrap();
+        // Flat blue with a red and white checkerboard in the right third
+        RgbaImage::from_fn(90, 30, |x, y| match x >= 60 {
+            true if (x / 3 + y / 3) % 2 == 0 => Rgba([255, 255, 255, 255]),
+            true => Rgba([255, 0, 0, 255]),
+            false => Rgba([0, 0, 255, 255]),
+        })
+        .save(tile_dir.join("wide.png"))
+        .unwrap();
+        // Nothing stands out in a flat photo, so it keeps the center crop
+        RgbaImage::from_pixel(90, 30, Rgba([0, 160, 0, 255]))
+            .save(tile_dir.join("flat.png"))
+            .unwrap();
+        let wide = |library: &TileLibrary| {
+            let path = tile_dir.join("wide.png");
+            library.tiles.iter().position(|t| t.path == path).unwrap()
+        };
+        let config = LibraryConfig {
+            sources: vec![TileSource::new(&tile_dir)],
+            tile_size: 8,
+            crop_strategy: CropStrategy::Saliency,
+            ..test_library_config()
+        };
+
+        let cache_dir = dir.join("cache");
+        let mut library = TileLibrary::with_cache(config.clone(), Some(&cache_dir)).unwrap();
+        let idx = wide(&library);
+        assert_eq!(library.tiles[1 - idx].crop, None);
+        let crop = library.tiles[idx].crop.unwrap();
+        assert_eq!((crop.width, crop.height), (30, 30));
+        // Right of the centered crop at x = 30
+        assert!(crop.x >= 45, "{:?}", crop);
+        // Color analysis and rendering both see the checkerboard, not the blue
+        let color = library.tiles[idx].color;
+        assert!(color[0] > color[2], "{:?}", color);
+        let rendered = library.tiles[idx].get_image(CellSize::square(8)).unwrap();
+        let [r, _, b, _] = rendered.get_pixel(4, 4).0;
+        assert!(r > b);
+
+        let cached = TileLibrary::with_cache(config.clone(), Some(&cache_dir)).unwrap();
+        assert_eq!(cached.tiles[wide(&cached)].crop, Some(crop));
+        let centered = TileLibrary::new(LibraryConfig {
+            crop_strategy: CropStrategy::Center,
+            ..config
+        })
+        .unwrap();
+        let centered = &centered.tiles[wide(&centered)];
+        assert_eq!(centered.crop, None);
+        assert!(centered.color[2] > centered.color[0]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn mirrored_variants_match_and_count_as_one_photo() {
+        let dir = temp_dir("variants");
+        let tile_dir = dir.join("tiles");
+        std::fs::create_dir_all(&tile_dir).unwrap();
+        let red = Rgba([250, 0, 0, 255]);
+        let blue = Rgba([0, 0, 250, 255]);
+        // One red-left tile; the target's second cell is its mirror image
+        RgbaImage::from_fn(16, 16, |x, _| if x < 8 { red } else { blue })
+            .save(tile_dir.join("half.png"))
+            .unwrap();
+        let target_path = dir.join("target.png");
+        RgbaImage::from_fn(32, 16, |x, _| match (8..24).contains(&x) {
+            true => blue,
+            false => red,
+        })
+        .save(&target_path)
+        .unwrap();
+
+        let mut library = TileLibrary::new(LibraryConfig {
+            sources: vec![TileSource::new(&tile_dir)],
+            tile_size: 16,
+            descriptor_grid: DescriptorGrid::Grid2x2,
+            tile_transforms: TileTransforms::Mirror,
+            ..test_library_config()
+        })
+        .unwrap();
+        assert_eq!(library.tiles[0].variants.len(), 1);
+        let config = MosaicConfig {
+            render_tile_size: 16,
+            ..test_mosaic_config()
+        };
+
+        let plan = library
+            .plan_mosaic(target_path.to_str().unwrap(), &config)
+            .unwrap();
+        assert_eq!(
+            plan.transforms,
+            [Dihedral::Identity, Dihedral::FlipHorizontal]
+        );
+        let manifest = library.placement_manifest(&plan);
+        assert!(manifest.placements.iter().all(|p| p.usage_count == 2));
+        assert!(manifest.placements[1].color_distance < 1.0);
+        let canvas = library.render_plan(&plan, &config).unwrap();
+        assert_eq!(canvas.get_pixel(20, 8).0, blue.0);
+        assert_eq!(canvas.get_pixel(28, 8).0, red.0);
+
+        // The mirror image is the same photo, so it cannot lift a usage cap
+        let capped = MosaicConfig {
+            max_uses_per_tile: 1,
+            ..config
+        };
+        let plan = library
+            .plan_mosaic(target_path.to_str().unwrap(), &capped)
+            .unwrap();
+        assert!(matches!(
+            plan.unmet[..],
+            [UnmetConstraint::UsageCapExceeded { cap: 1, cells: 1 }]
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn rescan_applies_added_removed_and_modified_files() {
+        let (dir, mut library, _) = build_disk_fixture("rescan");
+        let tile_dir = library.sources()[0].dir.clone();
+        assert_eq!(
+            library.rescan(None).unwrap(),
+            RescanSummary {
+                tile_count: 4,
+                ..RescanSummary::default()
+            }
+        );
+
+        let red: RgbaImage = ImageBuffer::from_pixel(12, 12, Rgba([250, 0, 0, 255]));
+        let blue: RgbaImage = ImageBuffer::from_pixel(16, 16, Rgba([0, 0, 250, 255]));
+        red.save(tile_dir.join("added.png")).unwrap();
+        std::fs::remove_file(tile_dir.join("tile1.png")).unwrap();
+        blue.save(tile_dir.join("tile2.png")).unwrap();
+
+        let summary = library.rescan(None).unwrap();
+
+        assert_eq!(
+            summary,
+            RescanSummary {
+                added: 1,
+                removed: 1,
+                modified: 1,
+                tile_count: 4,
+            }
+        );
+        let modified = library
+            .tiles
+            .iter()
+            .find(|t| t.path.ends_with("tile2.png"))
+            .unwrap();
+        assert!(modified.color[2] > modified.color[0]);
+        assert!(!library.rescan(None).unwrap().has_changes());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn plans_go_stale_when_the_target_settings_or_tiles_change() {
+        let (dir, mut library, target_path) = build_disk_fixture("plan-current");
+        let target = target_path.to_str().unwrap();
+        let config = test_mosaic_config();
+        let plan = library.plan_mosaic(target, &config).unwrap();
+        assert!(library.is_current(&plan, target, &config));
+
+        let capped = MosaicConfig {
+            max_uses_per_tile: 1,
+            ..config
+        };
+        assert!(!library.is_current(&plan, target, &capped));
+        assert!(!library.is_current(&plan, "/tmp/other.png", &config));
+
+        // A rescan without changes keeps the tile indexes the plan refers to
+        library.rescan(None).unwrap();
+        assert!(library.is_current(&plan, target, &config));
+
+        let tile_dir = library.sources()[0].dir.clone();
+        std::fs::remove_file(tile_dir.join("tile1.png")).unwrap();
+        library.rescan(None).unwrap();
+        assert!(!library.is_current(&plan, target, &config));
+        assert!(!build_test_library().is_current(&plan, target, &config));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn load_report_lists_corrupt_and_tiny_files() {
+        let (dir, mut library, _) = build_disk_fixture("load-report");
+        let tile_dir = library.sources()[0].dir.clone();
+        assert_eq!(library.load_report().loaded, 4);
+        assert!(library.load_report().skipped.is_empty());
+
+        std::fs::write(tile_dir.join("truncated.png"), b"\x89PNG\r\n\x1a\n").unwrap();
+        let icon: RgbaImage = ImageBuffer::from_pixel(4, 4, Rgba([9, 9, 9, 255]));
+        icon.save(tile_dir.join("icon.png")).unwrap();
+        std::fs::write(tile_dir.join("notes.txt"), b"not an image").unwrap();
+
+        let summary = library.rescan(None).unwrap();
+        assert!(!summary.has_changes());
+        let report = library.load_report();
+        assert_eq!(report.loaded, 4);
+        assert_eq!(report.skipped.len(), 2);
+        assert_eq!(report.count(SkipReason::DecodeError), 1);
+        assert_eq!(report.count(SkipReason::TooSmall), 1);
+        let tiny = report
+            .skipped
+            .iter()
+            .find(|s| s.reason == SkipReason::TooSmall)
+            .unwrap();
+        assert_eq!(tiny.path, tile_dir.join("icon.png"));
+        assert!(tiny.detail.contains("4x4"));
+
+        for i in 0..4 {
+            std::fs::remove_file(tile_dir.join(format!("tile{}.png", i))).unwrap();
+        }
+        let reloaded = TileLibrary::new(LibraryConfig {
+            sources: vec![TileSource::new(tile_dir)],
+            tile_size: 8,
+            ..test_library_config()
+        });
+        assert!(matches!(
+            reloaded,
+            Err(AppError::Config(message)) if message.contains("2 files skipped")
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn near_duplicates_collapse_into_one_tile() {
+        let dir = temp_dir("dedupe");
+        std::fs::create_dir_all(&dir).unwrap();
+        for seed in 1..=3u32 {
+            noise(seed)
+                .save(dir.join(format!("shot{}.png", seed)))
+                .unwrap();
+        }
+        std::fs::copy(dir.join("shot1.png"), dir.join("shot1-export.png")).unwrap();
+
+        let mut library = TileLibrary::new(LibraryConfig {
+            sources: vec![TileSource::new(&dir)],
+            tile_size: 8,
+            duplicates: Some(DuplicateFilter {
+                hash: HashKind::Perceptual,
+                max_distance: 4,
+            }),
+            ..test_library_config()
+        })
+        .unwrap();
+
+        assert_eq!(library.tiles.len(), 3);
+        assert_eq!(library.load_report().loaded, 4);
+        let groups = &library.load_report().duplicate_groups;
+        assert_eq!(groups.len(), 1);
+        let mut grouped = vec![groups[0].representative.clone()];
+        grouped.extend(groups[0].duplicates.iter().cloned());
+        grouped.sort();
+        assert_eq!(
+            grouped,
+            vec![dir.join("shot1-export.png"), dir.join("shot1.png")]
+        );
+
+        // The collapsed copy takes over when its representative goes away
+        std::fs::remove_file(&groups[0].representative).unwrap();
+        let summary = library.rescan(None).unwrap();
+        assert_eq!((summary.removed, summary.tile_count), (1, 3));
+        assert!(library.load_report().duplicate_groups.is_empty());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn color_coverage_counts_every_cell_and_tile() {
+        let (dir, library, target_path) = build_disk_fixture("coverage");
+
+        let report = library
+            .color_coverage(target_path.to_str().unwrap())
+            .unwrap();
+
+        // 37x29 at tile size 8 pads to a 5x4 grid
+        assert_eq!((report.cell_count, report.tile_count), (20, 4));
+        let target_total: f64 = report.bins.iter().map(|b| b.target_share).sum();
+        let tiles: u32 = report.bins.iter().map(|b| b.tile_count).sum();
+        assert!((target_total - 1.0).abs() < 1e-9);
+        assert_eq!(tiles, 4);
+        assert!((0.0..=1.0).contains(&report.coverage_score));
+        assert!(report
+            .under_covered
+            .iter()
+            .all(|b| b.library_share < b.target_share));
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn placement_manifest_covers_every_cell_clipped_to_output() {
+        let (dir, library, target_path) = build_disk_fixture("manifest");
+        let config = MosaicConfig {
+            penalty_factor: 10.0,
+            use_every_tile: true,
+            render_tile_size: 20,
+            ..test_mosaic_config()
+        };
+        let plan = library
+            .plan_mosaic(target_path.to_str().unwrap(), &config)
+            .unwrap();
+        let manifest = library.placement_manifest(&plan);
+
+        assert_eq!((manifest.width, manifest.height), (93, 73));
+        assert_eq!(manifest.placements.len(), 5 * 4);
+        let corner = manifest.placements.last().unwrap();
+        assert_eq!((corner.row, corner.column), (3, 4));
+        assert_eq!(
+            (corner.x, corner.y, corner.width, corner.height),
+            (80, 60, 13, 13)
+        );
+        for placement in &manifest.placements {
+            let uses = manifest
+                .placements
+                .iter()
+                .filter(|p| p.tile_path == placement.tile_path)
+                .count();
+            assert_eq!(placement.usage_count as usize, uses);
+            assert!(placement.color_distance.is_finite());
+        }
+
+        // Quadtree placements are larger but still tile the output exactly
+        let quadtree = MosaicConfig {
+            quadtree: Some(QuadtreeOptions {
+                max_cell_span: 2,
+                detail_threshold: MAX_DETAIL_THRESHOLD,
+            }),
+            ..config
+        };
+        let plan = library
+            .plan_mosaic(target_path.to_str().unwrap(), &quadtree)
+            .unwrap();
+        let manifest = library.placement_manifest(&plan);
+        assert!(manifest.placements.len() < 5 * 4);
+        assert_eq!(
+            manifest
+                .placements
+                .iter()
+                .map(|p| p.width * p.height)
+                .sum::<u32>(),
+            93 * 73
+        );
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 
     #[test]
@@ -500,15 +2636,135 @@ mod tests {
         ));
     }
 
+    #[test]
+    fn mosaic_config_validate_rejects_out_of_range_structure_weight() {
+        let config = MosaicConfig {
+            penalty_factor: 50.0,
+            structure_weight: 150.0,
+            ..test_mosaic_config()
+        };
+
+        assert!(matches!(
+            config.validate(),
+            Err(AppError::Config(message)) if message.contains("structure_weight")
+        ));
+    }
+
+    #[test]
+    fn mosaic_config_validate_bounds_the_largest_quadtree_cell() {
+        let quadtree = QuadtreeOptions {
+            max_cell_span: 16,
+            detail_threshold: 20.0,
+        };
+        let config = |render_tile_size| MosaicConfig {
+            penalty_factor: 50.0,
+            render_tile_size,
+            quadtree: Some(quadtree),
+            ..test_mosaic_config()
+        };
+
+        // 16 cells of 1024 pixels would decode and cache a 16384x16384 tile
+        assert!(matches!(
+            config(1024).validate(),
+            Err(AppError::Config(message)) if message.contains("max_cell_span")
+        ));
+        assert!(config(64).validate().is_ok());
+        // The analysis size is only known to the library
+        assert!(config(0).validate().is_ok());
+    }
+
     #[test]
     fn padded_dimensions_rounds_up_to_tile_multiple() {
-        assert_eq!(padded_dimensions(100, 65, 32), (128, 96));
-        assert_eq!(padded_dimensions(64, 96, 32), (64, 96));
+        assert_eq!(padded_dimensions(100, 65, CellSize::square(32)), (128, 96));
+        assert_eq!(padded_dimensions(64, 96, CellSize::square(32)), (64, 96));
+        assert_eq!(padded_dimensions(100, 65, CellSize::new(32, 24)), (128, 72));
     }
 
     #[test]
     fn build_tile_coordinates_is_row_major() {
-        let coords = build_tile_coordinates(64, 64, 32);
+        let coords = build_tile_coordinates(64, 64, CellSize::square(32));
         assert_eq!(coords, vec![(0, 0), (32, 0), (0, 32), (32, 32)]);
+        let coords = build_tile_coordinates(64, 48, CellSize::new(32, 24));
+        assert_eq!(coords, vec![(0, 0), (32, 0), (0, 24), (32, 24)]);
+    }
+
+    #[test]
+    fn orientation_aware_layout_puts_portrait_photos_where_they_fit() {
+        let dir = temp_dir("oriented");
+        let tile_dir = dir.join("tiles");
+        std::fs::create_dir_all(&tile_dir).unwrap();
+        let red = Rgba([200, 30, 30, 255]);
+        let blue = Rgba([30, 30, 200, 255]);
+        RgbaImage::from_pixel(24, 12, red)
+            .save(tile_dir.join("wide.png"))
+            .unwrap();
+        RgbaImage::from_pixel(12, 24, blue)
+            .save(tile_dir.join("tall.png"))
+            .unwrap();
+        // Red on the left half, blue on the right
+        let target_path = dir.join("target.png");
+        RgbaImage::from_fn(48, 24, |x, _| if x < 24 { red } else { blue })
+            .save(&target_path)
+            .unwrap();
+
+        let library_config = LibraryConfig {
+            sources: vec![TileSource::new(&tile_dir)],
+            tile_size: 8,
+            tile_aspect: TileAspect {
+                width: 4,
+                height: 3,
+            },
+            orientation_aware: true,
+            ..test_library_config()
+        };
+        assert!(library_config.validate().is_ok());
+        let mut library = TileLibrary::new(library_config.clone()).unwrap();
+        let tall = tile_dir.join("tall.png");
+        assert!(library.tiles.iter().all(|t| t.portrait == (t.path == tall)));
+
+        let config = MosaicConfig {
+            render_tile_size: 16,
+            ..test_mosaic_config()
+        };
+        let plan = library
+            .plan_mosaic(target_path.to_str().unwrap(), &config)
+            .unwrap();
+        // 8x6 landscape and 5x6 portrait cells, drawn at 16x12 and 10x12
+        assert_eq!((plan.width, plan.height), (96, 48));
+        let manifest = library.placement_manifest(&plan);
+        for (placed, placement) in plan.layout.cells.iter().zip(&manifest.placements) {
+            assert_eq!(placed.portrait, placement.tile_path == tall);
+            let (_, _, size) = plan.layout.cell_rect(placed, plan.render_cell);
+            assert_eq!(size.width, if placed.portrait { 10 } else { 16 });
+            if placement.x + placement.width <= 48 {
+                assert!(!placed.portrait);
+            } else if placement.x >= 48 {
+                assert!(placed.portrait);
+            }
+        }
+        assert_eq!(
+            library.render_plan(&plan, &config).unwrap().dimensions(),
+            (96, 48)
+        );
+
+        // Portrait cells need a landscape aspect and the square grid
+        assert!(LibraryConfig {
+            tile_aspect: TileAspect::default(),
+            ..library_config.clone()
+        }
+        .validate()
+        .is_err());
+        let brick = LibraryConfig {
+            cell_shape: CellShape::Brick,
+            ..library_config
+        };
+        assert!(brick.validate().is_err());
+        // Libraries refuse invalid settings before touching the files
+        assert!(matches!(
+            TileLibrary::new(brick),
+            Err(AppError::Config(message)) if message.contains("square grid")
+        ));
+
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
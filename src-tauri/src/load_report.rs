@@ -0,0 +1,91 @@
+use crate::dedupe::DuplicateGroup;
+use serde::Serialize;
+use std::path::PathBuf;
+
+/// Why a file in the tile folder did not become a tile.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SkipReason {
+    PermissionDenied,
+    /// Any other I/O failure while listing or reading the file.
+    Unreadable,
+    /// Recognized as an image but truncated or corrupt.
+    DecodeError,
+    TooSmall,
+    /// An image format this build cannot decode.
+    Unsupported,
+}
+
+/// A file that was left out of the library, with a human-readable detail.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SkippedFile {
+    pub path: PathBuf,
+    pub reason: SkipReason,
+    pub detail: String,
+}
+
+impl SkippedFile {
+    pub fn new(path: PathBuf, reason: SkipReason, detail: impl Into<String>) -> Self {
+        Self {
+            path,
+            reason,
+            detail: detail.into(),
+        }
+    }
+
+    /// Classifies an I/O error on `path`.
+    pub fn from_io(path: PathBuf, err: &std::io::Error) -> Self {
+        let reason = match err.kind() {
+            std::io::ErrorKind::PermissionDenied => SkipReason::PermissionDenied,
+            _ => SkipReason::Unreadable,
+        };
+        Self::new(path, reason, err.to_string())
+    }
+}
+
+/// Outcome of loading or rescanning a tile folder.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct LoadReport {
+    /// Number of files that were analyzed, including near-duplicates
+    /// collapsed into another tile.
+    pub loaded: usize,
+    pub skipped: Vec<SkippedFile>,
+    /// Near-duplicates collapsed into one tile, when duplicate filtering is on.
+    pub duplicate_groups: Vec<DuplicateGroup>,
+    /// Why the feature cache could not be written. Loading still succeeds,
+    /// but the next load analyzes every file again.
+    pub cache_error: Option<String>,
+}
+
+impl LoadReport {
+    /// Number of skipped files with the given reason.
+    #[must_use]
+    pub fn count(&self, reason: SkipReason) -> usize {
+        self.skipped.iter().filter(|s| s.reason == reason).count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn from_io_separates_permission_errors() {
+        let denied = std::io::Error::from(std::io::ErrorKind::PermissionDenied);
+        let missing = std::io::Error::from(std::io::ErrorKind::NotFound);
+
+        let report = LoadReport {
+            loaded: 3,
+            skipped: vec![
+                SkippedFile::from_io(PathBuf::from("/photos/a.jpg"), &denied),
+                SkippedFile::from_io(PathBuf::from("/photos/b.jpg"), &missing),
+            ],
+            duplicate_groups: Vec::new(),
+            cache_error: None,
+        };
+
+        assert_eq!(report.count(SkipReason::PermissionDenied), 1);
+        assert_eq!(report.count(SkipReason::Unreadable), 1);
+        assert_eq!(report.count(SkipReason::DecodeError), 0);
+    }
+}
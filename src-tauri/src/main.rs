@@ -1,18 +1,53 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
 use image::GenericImageView;
+use notify_debouncer_mini::notify::{RecommendedWatcher, RecursiveMode};
+use notify_debouncer_mini::{new_debouncer, DebounceEventResult, Debouncer};
 use serde::{Deserialize, Serialize};
 use std::path::{Path, PathBuf};
-use tauri::State;
+use std::sync::{Mutex, PoisonError};
+use std::time::Duration;
+use tauri::{AppHandle, Emitter, Manager, State};
 use tokio::sync::RwLock;
 
+use mosaic_gui::assignment::AssignmentMode;
+use mosaic_gui::color::ColorSpace;
+use mosaic_gui::compose::BlendMode;
+use mosaic_gui::coverage::CoverageReport;
+use mosaic_gui::crop::CropStrategy;
+use mosaic_gui::dedupe::{DuplicateFilter, HashKind};
+use mosaic_gui::deepzoom::DeepZoomOptions;
+use mosaic_gui::descriptor::DescriptorGrid;
+use mosaic_gui::dihedral::TileTransforms;
 use mosaic_gui::errors::AppError;
-use mosaic_gui::{load_image_with_orientation, validate_mosaic_inputs, MosaicConfig, TileLibrary};
+use mosaic_gui::export::{ExportOptions, OutputFormat, DEFAULT_JPEG_QUALITY};
+use mosaic_gui::formats::{decodable_extensions, find_image_files, FormatSet};
+use mosaic_gui::layout::{CellShape, QuadtreeOptions, TileAspect};
+use mosaic_gui::load_report::LoadReport;
+use mosaic_gui::manifest::ManifestFormat;
+use mosaic_gui::sources::TileSource;
+use mosaic_gui::{
+    load_image_with_orientation, validate_mosaic_inputs, DeepZoomExport, LibraryConfig,
+    MosaicConfig, MosaicPlan, MosaicResult, RescanSummary, SavedManifest, SavedMosaic, TileLibrary,
+};
+
+/// Emitted with a `RescanSummary` when a watched folder changed the library.
+const LIBRARY_CHANGED_EVENT: &str = "library-changed";
+/// Emitted with an error message when watching or rescanning a folder failed.
+const LIBRARY_WATCH_ERROR_EVENT: &str = "library-watch-error";
+/// Quiet period before a burst of file changes triggers a rescan.
+const WATCH_DEBOUNCE: Duration = Duration::from_secs(1);
 
 /// Application state managed by Tauri.
 #[derive(Default)]
 struct AppState {
     library: RwLock<Option<TileLibrary>>,
+    /// Where computed tile features persist between runs. None disables the cache.
+    cache_dir: Option<PathBuf>,
+    /// Watcher of the tile folder, if enabled. Dropping it stops watching.
+    watcher: Mutex<Option<Debouncer<RecommendedWatcher>>>,
+    /// Last tile assignment, so exports of the mosaic on screen skip re-matching.
+    plan: Mutex<Option<MosaicPlan>>,
 }
 
 /// Parameters for mosaic generation from the frontend.
@@ -21,9 +56,114 @@ struct AppState {
 struct MosaicParams {
     target_image_path: String,
     tile_directory: String,
+    /// Tile folders with their patterns. When given, replaces `tile_directory`.
+    #[serde(default)]
+    tile_sources: Vec<TileSource>,
     tile_size: u32,
     penalty_factor: f64,
     sigma_divisor: f64,
+    #[serde(default)]
+    color_space: ColorSpace,
+    #[serde(default)]
+    ciede2000_rerank: bool,
+    #[serde(default)]
+    descriptor_grid: DescriptorGrid,
+    #[serde(default)]
+    structure_weight: f64,
+    #[serde(default)]
+    assignment_mode: AssignmentMode,
+    #[serde(default)]
+    min_repeat_distance: u32,
+    #[serde(default)]
+    max_uses_per_tile: u32,
+    #[serde(default)]
+    use_every_tile: bool,
+    #[serde(default)]
+    color_correction: f64,
+    #[serde(default)]
+    overlay_opacity: f64,
+    #[serde(default)]
+    overlay_blend: BlendMode,
+    #[serde(default)]
+    render_tile_size: u32,
+    #[serde(default)]
+    cell_shape: CellShape,
+    /// Width-to-height ratio of the cells; `tile_size` is the longer side.
+    #[serde(default)]
+    tile_aspect: TileAspect,
+    /// Place portrait photos in narrower portrait cells where they fit best.
+    #[serde(default)]
+    orientation_aware: bool,
+    /// How photos are cropped to the cell aspect: center, saliency or faces.
+    #[serde(default)]
+    crop_strategy: CropStrategy,
+    /// Also match rotated and mirrored copies of each tile: original, mirror, rotate or all.
+    #[serde(default)]
+    tile_transforms: TileTransforms,
+    /// Use larger cells for flat regions and split detailed ones.
+    #[serde(default)]
+    quadtree_layout: bool,
+    /// Side of the largest quadtree cell, in tiles.
+    #[serde(default)]
+    max_cell_span: u32,
+    /// Luma standard deviation above which a quadtree cell is split.
+    #[serde(default)]
+    detail_threshold: f64,
+    /// Tile formats to use, such as "jpeg" or "webp". Empty uses every decodable format.
+    #[serde(default)]
+    tile_formats: Vec<String>,
+    /// Collapse near-duplicate tile images into one tile.
+    #[serde(default)]
+    collapse_duplicates: bool,
+    #[serde(default)]
+    duplicate_hash: HashKind,
+    /// Largest Hamming distance between hashes of images treated as duplicates.
+    #[serde(default)]
+    duplicate_threshold: u32,
+}
+
+/// Destination and encoding for a saved mosaic.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct SaveParams {
+    path: String,
+    #[serde(default)]
+    format: OutputFormat,
+    /// JPEG quality (1-100); defaults to 90.
+    #[serde(default)]
+    jpeg_quality: Option<u8>,
+}
+
+/// Destination and tile encoding for a Deep Zoom export.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct DeepZoomParams {
+    /// Path of the `.dzi` descriptor; tiles go to a `<name>_files` folder beside it.
+    path: String,
+    /// JPEG quality (1-100) of the pyramid tiles; defaults to 90.
+    #[serde(default)]
+    jpeg_quality: Option<u8>,
+}
+
+/// Destination and format for a placement manifest.
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+struct ManifestParams {
+    path: String,
+    #[serde(default)]
+    format: ManifestFormat,
+}
+
+/// Validated tile sources, falling back to the whole of `directory`.
+fn resolve_sources(directory: &str, sources: &[TileSource]) -> Result<Vec<TileSource>, AppError> {
+    let sources = match sources {
+        [] => vec![TileSource::new(directory)],
+        sources => sources.to_vec(),
+    };
+    for source in &sources {
+        source.validate()?;
+    }
+    Ok(sources)
 }
 
 /// Calculates adaptive settings based on inputs.
@@ -31,6 +171,8 @@ struct MosaicParams {
 async fn get_adaptive_settings(
     target_image_path: String,
     tile_directory: String,
+    tile_sources: Option<Vec<TileSource>>,
+    tile_formats: Option<Vec<String>>,
 ) -> Result<serde_json::Value, AppError> {
     let target_img = load_image_with_orientation(&target_image_path)?;
 
@@ -46,21 +188,10 @@ async fn get_adaptive_settings(
         suggested.clamp(8, 128)
     };
 
-    // Count tiles in directory
-    use walkdir::WalkDir;
-    let tile_count = WalkDir::new(&tile_directory)
-        .into_iter()
-        .filter_map(|e| e.ok())
-        .filter(|e| e.file_type().is_file())
-        .filter_map(|e| {
-            let ext = e.path().extension()?.to_string_lossy().to_lowercase();
-            if ["jpg", "jpeg", "png"].contains(&ext.as_str()) {
-                Some(())
-            } else {
-                None
-            }
-        })
-        .count();
+    // Count tiles in the tile folders
+    let sources = resolve_sources(&tile_directory, &tile_sources.unwrap_or_default())?;
+    let formats = FormatSet::parse(&tile_formats.unwrap_or_default())?;
+    let tile_count = find_image_files(&sources, &formats)?.files.len();
 
     // Calculate adaptive penalty factor based on tile count
     // Fewer tiles = lower penalty (need to reuse), more tiles = higher penalty (can diversify)
@@ -86,57 +217,334 @@ async fn get_adaptive_settings(
     }))
 }
 
-/// Generates a mosaic image from the provided parameters.
+/// Lists the file extensions of every image format this build can decode.
 #[tauri::command]
-async fn generate_mosaic(
-    params: MosaicParams,
-    state: State<'_, AppState>,
-) -> Result<String, AppError> {
+fn get_supported_formats() -> Vec<&'static str> {
+    decodable_extensions()
+}
+
+/// Validates the request and splits it into generation and library settings.
+fn build_configs(params: &MosaicParams) -> Result<(MosaicConfig, LibraryConfig), AppError> {
     validate_mosaic_inputs(
         params.tile_size,
         params.penalty_factor,
         params.sigma_divisor,
     )?;
 
-    let mut library_guard = state.library.write().await;
+    let config = MosaicConfig {
+        penalty_factor: params.penalty_factor,
+        ciede2000_rerank: params.ciede2000_rerank,
+        structure_weight: params.structure_weight,
+        assignment_mode: params.assignment_mode,
+        min_repeat_distance: params.min_repeat_distance,
+        max_uses_per_tile: params.max_uses_per_tile,
+        use_every_tile: params.use_every_tile,
+        color_correction: params.color_correction,
+        overlay_opacity: params.overlay_opacity,
+        overlay_blend: params.overlay_blend,
+        render_tile_size: params.render_tile_size,
+        quadtree: params.quadtree_layout.then_some(QuadtreeOptions {
+            max_cell_span: params.max_cell_span,
+            detail_threshold: params.detail_threshold,
+        }),
+    };
+    config.validate()?;
+
+    let duplicates = params.collapse_duplicates.then_some(DuplicateFilter {
+        hash: params.duplicate_hash,
+        max_distance: params.duplicate_threshold,
+    });
+    if let Some(filter) = &duplicates {
+        filter.validate()?;
+    }
+
+    let library_config = LibraryConfig {
+        sources: resolve_sources(&params.tile_directory, &params.tile_sources)?,
+        tile_size: params.tile_size,
+        sigma_divisor: params.sigma_divisor,
+        color_space: params.color_space,
+        descriptor_grid: params.descriptor_grid,
+        formats: FormatSet::parse(&params.tile_formats)?,
+        duplicates,
+        cell_shape: params.cell_shape,
+        tile_aspect: params.tile_aspect,
+        orientation_aware: params.orientation_aware,
+        crop_strategy: params.crop_strategy,
+        tile_transforms: params.tile_transforms,
+    };
+    library_config.validate()?;
+
+    Ok((config, library_config))
+}
 
-    let needs_reload = match *library_guard {
-        Some(ref lib) => !lib.matches_config(
-            Path::new(&params.tile_directory),
-            params.tile_size,
-            params.sigma_divisor,
-        ),
+/// Returns the cached library, reloading it if its settings changed.
+fn ensure_library<'a>(
+    library: &'a mut Option<TileLibrary>,
+    library_config: LibraryConfig,
+    cache_dir: Option<&Path>,
+) -> Result<&'a mut TileLibrary, AppError> {
+    let needs_reload = match *library {
+        Some(ref lib) => !lib.matches_config(&library_config),
         None => true,
     };
 
     if needs_reload {
-        let new_lib = TileLibrary::new(
-            PathBuf::from(&params.tile_directory),
-            params.tile_size,
-            params.sigma_divisor,
-        )?;
-        *library_guard = Some(new_lib);
+        let new_lib = TileLibrary::with_cache(library_config, cache_dir)?;
+        *library = Some(new_lib);
     }
 
+    library
+        .as_mut()
+        .ok_or_else(|| AppError::Config("Library failed to initialize".into()))
+}
+
+/// Returns the cached plan, re-planning if the library, target or settings
+/// changed since it was made.
+fn ensure_plan<'a>(
+    plan: &'a mut Option<MosaicPlan>,
+    lib: &TileLibrary,
+    target_path: &str,
+    config: &MosaicConfig,
+) -> Result<&'a MosaicPlan, AppError> {
+    let needs_plan = match *plan {
+        Some(ref plan) => !lib.is_current(plan, target_path, config),
+        None => true,
+    };
+
+    if needs_plan {
+        *plan = Some(lib.plan_mosaic(target_path, config)?);
+    }
+
+    plan.as_ref()
+        .ok_or_else(|| AppError::Config("Mosaic plan failed to initialize".into()))
+}
+
+/// Generates a mosaic image from the provided parameters.
+#[tauri::command]
+async fn generate_mosaic(
+    params: MosaicParams,
+    state: State<'_, AppState>,
+) -> Result<MosaicResult, AppError> {
+    let (config, library_config) = build_configs(&params)?;
+
+    let mut library_guard = state.library.write().await;
+    let lib = ensure_library(
+        &mut library_guard,
+        library_config,
+        state.cache_dir.as_deref(),
+    )?;
+    let mut plan_guard = state.plan.lock().unwrap_or_else(PoisonError::into_inner);
+    let plan = ensure_plan(&mut plan_guard, lib, &params.target_image_path, &config)?;
+
+    lib.generate_mosaic(plan)
+}
+
+/// Generates a mosaic and writes it to a user-chosen file.
+#[tauri::command]
+async fn save_mosaic(
+    params: MosaicParams,
+    output: SaveParams,
+    state: State<'_, AppState>,
+) -> Result<SavedMosaic, AppError> {
+    let (config, library_config) = build_configs(&params)?;
+    let options = ExportOptions {
+        format: output.format,
+        jpeg_quality: output.jpeg_quality.unwrap_or(DEFAULT_JPEG_QUALITY),
+    };
+    options.validate()?;
+
+    let mut library_guard = state.library.write().await;
+    let lib = ensure_library(
+        &mut library_guard,
+        library_config,
+        state.cache_dir.as_deref(),
+    )?;
+    let mut plan_guard = state.plan.lock().unwrap_or_else(PoisonError::into_inner);
+    let plan = ensure_plan(&mut plan_guard, lib, &params.target_image_path, &config)?;
+
+    lib.save_mosaic(plan, &PathBuf::from(output.path), &options)
+}
+
+/// Generates a mosaic and writes it as a Deep Zoom pyramid for web viewers.
+#[tauri::command]
+async fn export_deep_zoom(
+    params: MosaicParams,
+    output: DeepZoomParams,
+    state: State<'_, AppState>,
+) -> Result<DeepZoomExport, AppError> {
+    let (config, library_config) = build_configs(&params)?;
+    let options = DeepZoomOptions {
+        jpeg_quality: output.jpeg_quality.unwrap_or(DEFAULT_JPEG_QUALITY),
+        ..DeepZoomOptions::default()
+    };
+    options.validate()?;
+
+    let mut library_guard = state.library.write().await;
+    let lib = ensure_library(
+        &mut library_guard,
+        library_config,
+        state.cache_dir.as_deref(),
+    )?;
+    let mut plan_guard = state.plan.lock().unwrap_or_else(PoisonError::into_inner);
+    let plan = ensure_plan(&mut plan_guard, lib, &params.target_image_path, &config)?;
+
+    lib.export_deep_zoom(plan, &PathBuf::from(output.path), &options)
+}
+
+/// Writes which source tile was placed in each cell as JSON or CSV.
+#[tauri::command]
+async fn export_manifest(
+    params: MosaicParams,
+    output: ManifestParams,
+    state: State<'_, AppState>,
+) -> Result<SavedManifest, AppError> {
+    let (config, library_config) = build_configs(&params)?;
+
+    let mut library_guard = state.library.write().await;
+    let lib = ensure_library(
+        &mut library_guard,
+        library_config,
+        state.cache_dir.as_deref(),
+    )?;
+    let mut plan_guard = state.plan.lock().unwrap_or_else(PoisonError::into_inner);
+    let plan = ensure_plan(&mut plan_guard, lib, &params.target_image_path, &config)?;
+
+    lib.export_manifest(plan, &PathBuf::from(output.path), output.format)
+}
+
+/// Reports which colors of the target the tile library covers poorly.
+#[tauri::command]
+async fn analyze_coverage(
+    params: MosaicParams,
+    state: State<'_, AppState>,
+) -> Result<CoverageReport, AppError> {
+    let (_, library_config) = build_configs(&params)?;
+
+    let mut library_guard = state.library.write().await;
+    let lib = ensure_library(
+        &mut library_guard,
+        library_config,
+        state.cache_dir.as_deref(),
+    )?;
+
+    lib.color_coverage(&params.target_image_path)
+}
+
+/// Rescans the loaded library's folder for added, removed or modified images.
+#[tauri::command]
+async fn rescan_library(state: State<'_, AppState>) -> Result<RescanSummary, AppError> {
+    let mut library_guard = state.library.write().await;
     let lib = library_guard
         .as_mut()
-        .ok_or_else(|| AppError::Config("Library failed to initialize".into()))?;
+        .ok_or_else(|| AppError::Config("No tile library loaded yet".into()))?;
 
-    let config = MosaicConfig {
-        penalty_factor: params.penalty_factor,
+    lib.rescan(state.cache_dir.as_deref())
+}
+
+/// Files loaded and skipped when the current library was last read.
+#[tauri::command]
+async fn get_load_report(state: State<'_, AppState>) -> Result<LoadReport, AppError> {
+    let library_guard = state.library.read().await;
+    let lib = library_guard
+        .as_ref()
+        .ok_or_else(|| AppError::Config("No tile library loaded yet".into()))?;
+
+    Ok(lib.load_report().clone())
+}
+
+/// Watches tile folders and applies changes to the loaded library as they
+/// happen. Passing no directories stops watching.
+#[tauri::command]
+fn watch_library(
+    directories: Vec<String>,
+    app: AppHandle,
+    state: State<'_, AppState>,
+) -> Result<(), AppError> {
+    let mut watcher = state
+        .watcher
+        .lock()
+        .map_err(|_| AppError::Config("Folder watcher is unavailable".into()))?;
+    *watcher = None;
+
+    if directories.is_empty() {
+        return Ok(());
+    }
+    let dirs: Vec<PathBuf> = directories.into_iter().map(PathBuf::from).collect();
+    let watched = dirs.clone();
+    // Watcher errors are reported like failed rescans instead of being dropped
+    let on_change = move |result: DebounceEventResult| match result {
+        Ok(_) => {
+            let app = app.clone();
+            let dirs = watched.clone();
+            tauri::async_runtime::spawn(async move { rescan_watched(app, dirs).await });
+        }
+        Err(e) => {
+            let message = format!("Folder watcher failed: {}", e);
+            let _ = app.emit(LIBRARY_WATCH_ERROR_EVENT, message);
+        }
+    };
+    let mut debouncer = new_debouncer(WATCH_DEBOUNCE, on_change)
+        .map_err(|e| AppError::Io(format!("Failed to start folder watcher: {}", e)))?;
+    for dir in &dirs {
+        debouncer
+            .watcher()
+            .watch(dir, RecursiveMode::Recursive)
+            .map_err(|e| AppError::Io(format!("Failed to watch {}: {}", dir.display(), e)))?;
+    }
+
+    *watcher = Some(debouncer);
+    Ok(())
+}
+
+/// Rescans the loaded library after a change in `dirs` and notifies the UI.
+async fn rescan_watched(app: AppHandle, dirs: Vec<PathBuf>) {
+    let state = app.state::<AppState>();
+    let mut library_guard = state.library.write().await;
+    // The library may have been reloaded from other folders since watching began
+    let loaded_from_dirs =
+        |lib: &&mut TileLibrary| lib.sources().iter().map(|s| &s.dir).eq(dirs.iter());
+    let Some(lib) = library_guard.as_mut().filter(loaded_from_dirs) else {
+        return;
     };
 
-    lib.generate_mosaic(&params.target_image_path, &config)
+    match lib.rescan(state.cache_dir.as_deref()) {
+        Ok(summary) if summary.has_changes() => {
+            let _ = app.emit(LIBRARY_CHANGED_EVENT, summary);
+        }
+        Ok(_) => {}
+        Err(e) => {
+            let _ = app.emit(LIBRARY_WATCH_ERROR_EVENT, e.to_string());
+        }
+    }
 }
 
 /// Initializes and runs the Tauri application.
 fn main() {
     tauri::Builder::default()
         .plugin(tauri_plugin_dialog::init())
-        .manage(AppState::default())
+        .setup(|app| {
+            let cache_dir = app
+                .path()
+                .app_cache_dir()
+                .ok()
+                .map(|dir| dir.join("tile-index"));
+            app.manage(AppState {
+                cache_dir,
+                ..AppState::default()
+            });
+            Ok(())
+        })
         .invoke_handler(tauri::generate_handler![
             generate_mosaic,
-            get_adaptive_settings
+            get_adaptive_settings,
+            get_supported_formats,
+            save_mosaic,
+            export_deep_zoom,
+            export_manifest,
+            analyze_coverage,
+            rescan_library,
+            get_load_report,
+            watch_library
         ])
         .run(tauri::generate_context!())
         .expect("error while running tauri application");
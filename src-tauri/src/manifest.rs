@@ -0,0 +1,182 @@
+use crate::dihedral::Dihedral;
+use crate::errors::{AppError, AppResult};
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+const CSV_HEADER: &str =
+    "row,column,x,y,width,height,tile_path,color_distance,usage_count,transform";
+
+/// Where one source tile was placed in the output.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct Placement {
+    /// Top-left analysis grid cell covered by the tile. In orientation-aware
+    /// layouts, whose rows mix cell widths, `column` counts cells from the left.
+    pub row: u32,
+    pub column: u32,
+    /// Pixel rectangle in the output image, clipped to its edges. For
+    /// hexagonal cells this is the square the hexagon is inscribed in.
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+    pub tile_path: PathBuf,
+    /// Root mean square color distance per region between the tile and its
+    /// cell, in the library's color space (CIEDE2000 when re-ranking is enabled).
+    pub color_distance: f64,
+    /// Number of cells in the whole mosaic that show this tile, in any
+    /// rotation or mirroring.
+    pub usage_count: u32,
+    /// Rotation or mirroring the tile is drawn with.
+    pub transform: Dihedral,
+}
+
+/// Placement map of a mosaic with the grid it was laid out on.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct PlacementManifest {
+    pub width: u32,
+    pub height: u32,
+    pub columns: u32,
+    pub rows: u32,
+    /// Analysis cell size in target pixels; the longer side of rectangular cells.
+    pub tile_size: u32,
+    /// Pixel size each tile was rendered at, along the same side.
+    pub render_tile_size: u32,
+    pub placements: Vec<Placement>,
+}
+
+/// File format for placement manifests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ManifestFormat {
+    /// The full manifest, including grid dimensions.
+    #[default]
+    Json,
+    /// One line per placement.
+    Csv,
+}
+
+/// Quotes a CSV field when it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// Writes the manifest as CSV, one row per placement.
+pub fn write_csv(manifest: &PlacementManifest, mut writer: impl Write) -> std::io::Result<()> {
+    writeln!(writer, "{}", CSV_HEADER)?;
+    for p in &manifest.placements {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{:.4},{},{}",
+            p.row,
+            p.column,
+            p.x,
+            p.y,
+            p.width,
+            p.height,
+            csv_field(&p.tile_path.to_string_lossy()),
+            p.color_distance,
+            p.usage_count,
+            p.transform.as_str()
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a manifest to `path` and returns the size of the written file in bytes.
+pub fn write_manifest(
+    manifest: &PlacementManifest,
+    path: &Path,
+    format: ManifestFormat,
+) -> AppResult<u64> {
+    let write_error = |e: &dyn std::fmt::Display| {
+        AppError::Io(format!("Failed to write {}: {}", path.display(), e))
+    };
+
+    let file = File::create(path)
+        .map_err(|e| AppError::Io(format!("Failed to create {}: {}", path.display(), e)))?;
+    let mut writer = BufWriter::new(file);
+    match format {
+        ManifestFormat::Json => {
+            serde_json::to_writer_pretty(&mut writer, manifest).map_err(|e| write_error(&e))?
+        }
+        ManifestFormat::Csv => write_csv(manifest, &mut writer).map_err(|e| write_error(&e))?,
+    }
+    writer.flush().map_err(|e| write_error(&e))?;
+
+    Ok(std::fs::metadata(path)?.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_manifest() -> PlacementManifest {
+        PlacementManifest {
+            width: 30,
+            height: 16,
+            columns: 2,
+            rows: 1,
+            tile_size: 8,
+            render_tile_size: 16,
+            placements: vec![
+                Placement {
+                    row: 0,
+                    column: 0,
+                    x: 0,
+                    y: 0,
+                    width: 16,
+                    height: 16,
+                    tile_path: PathBuf::from("/photos/beach.jpg"),
+                    color_distance: 1.5,
+                    usage_count: 1,
+                    transform: Dihedral::Identity,
+                },
+                Placement {
+                    row: 0,
+                    column: 1,
+                    x: 16,
+                    y: 0,
+                    width: 14,
+                    height: 16,
+                    tile_path: PathBuf::from("/photos/a \"b\", c.jpg"),
+                    color_distance: 0.25,
+                    usage_count: 1,
+                    transform: Dihedral::Rotate90,
+                },
+            ],
+        }
+    }
+
+    #[test]
+    fn write_csv_quotes_paths_that_need_it() {
+        let mut out = Vec::new();
+        write_csv(&sample_manifest(), &mut out).unwrap();
+
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "row,column,x,y,width,height,tile_path,color_distance,usage_count,transform\n\
+             0,0,0,0,16,16,/photos/beach.jpg,1.5000,1,identity\n\
+             0,1,16,0,14,16,\"/photos/a \"\"b\"\", c.jpg\",0.2500,1,rotate90\n"
+        );
+    }
+
+    #[test]
+    fn manifest_json_includes_grid_and_placements() {
+        let value = serde_json::to_value(sample_manifest()).unwrap();
+
+        assert_eq!(value["columns"], 2);
+        assert_eq!(value["render_tile_size"], 16);
+        assert_eq!(
+            value["placements"][1]["tile_path"],
+            "/photos/a \"b\", c.jpg"
+        );
+        assert_eq!(value["placements"][1]["width"], 14);
+        assert_eq!(value["placements"][1]["transform"], "rotate90");
+    }
+}
@@ -0,0 +1,212 @@
+use crate::errors::{AppError, AppResult};
+use crate::load_report::{SkipReason, SkippedFile};
+use glob::{MatchOptions, Pattern};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Patterns match paths relative to the source directory, `*` stays within
+/// one path component and letter case is ignored, so `*.jpg` matches `IMG.JPG`.
+const MATCH_OPTIONS: MatchOptions = MatchOptions {
+    case_sensitive: false,
+    require_literal_separator: true,
+    require_literal_leading_dot: false,
+};
+
+/// A directory tile images are read from.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TileSource {
+    pub dir: PathBuf,
+    /// Glob patterns a file must match, e.g. `**/*.jpg` or `2023/**`. Empty includes every file.
+    #[serde(default)]
+    pub include: Vec<String>,
+    /// Glob patterns for files and folders to leave out, e.g. `**/.*` or
+    /// `**/@eaDir`. A matching folder is not descended into.
+    #[serde(default)]
+    pub exclude: Vec<String>,
+    /// How deep to descend; 1 reads only the files directly in `dir`. None is unlimited.
+    #[serde(default)]
+    pub max_depth: Option<usize>,
+    #[serde(default)]
+    pub follow_symlinks: bool,
+}
+
+struct CompiledPatterns {
+    include: Vec<Pattern>,
+    exclude: Vec<Pattern>,
+}
+
+impl CompiledPatterns {
+    fn included(&self, relative: &Path) -> bool {
+        self.include.is_empty()
+            || self
+                .include
+                .iter()
+                .any(|p| p.matches_path_with(relative, MATCH_OPTIONS))
+    }
+
+    fn excluded(&self, relative: &Path) -> bool {
+        self.exclude
+            .iter()
+            .any(|p| p.matches_path_with(relative, MATCH_OPTIONS))
+    }
+}
+
+impl TileSource {
+    /// Every file below `dir`, without patterns or a depth limit.
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self {
+            dir: dir.into(),
+            include: Vec::new(),
+            exclude: Vec::new(),
+            max_depth: None,
+            follow_symlinks: false,
+        }
+    }
+
+    pub fn validate(&self) -> AppResult<()> {
+        if self.dir.as_os_str().is_empty() {
+            return Err(AppError::Config("Tile source directory is empty".into()));
+        }
+        if self.max_depth == Some(0) {
+            return Err(AppError::Config(
+                "Invalid max_depth 0. Expected at least 1".into(),
+            ));
+        }
+        self.compile().map(|_| ())
+    }
+
+    fn compile(&self) -> AppResult<CompiledPatterns> {
+        let compile = |kind: &str, patterns: &[String]| {
+            patterns
+                .iter()
+                .map(|pattern| {
+                    Pattern::new(pattern).map_err(|e| {
+                        AppError::Config(format!(
+                            "Invalid {} pattern \"{}\": {}",
+                            kind, pattern, e.msg
+                        ))
+                    })
+                })
+                .collect::<AppResult<Vec<_>>>()
+        };
+        Ok(CompiledPatterns {
+            include: compile("include", &self.include)?,
+            exclude: compile("exclude", &self.exclude)?,
+        })
+    }
+
+    /// Files below `dir` that pass the patterns, in directory order, and the
+    /// entries that could not be listed.
+    pub(crate) fn list_files(&self) -> AppResult<(Vec<PathBuf>, Vec<SkippedFile>)> {
+        let patterns = self.compile()?;
+        let relative = |path: &Path| path.strip_prefix(&self.dir).unwrap_or(path).to_path_buf();
+
+        let mut walker = WalkDir::new(&self.dir).follow_links(self.follow_symlinks);
+        if let Some(depth) = self.max_depth {
+            walker = walker.max_depth(depth);
+        }
+
+        let mut files = Vec::new();
+        let mut skipped = Vec::new();
+        // Excluded folders are pruned, so caches and hidden trees are never walked
+        let entries = walker
+            .into_iter()
+            .filter_entry(|e| e.depth() == 0 || !patterns.excluded(&relative(e.path())));
+        for entry in entries {
+            match entry {
+                Ok(entry) if entry.file_type().is_file() => {
+                    if patterns.included(&relative(entry.path())) {
+                        files.push(entry.into_path());
+                    }
+                }
+                Ok(_) => {}
+                Err(e) => {
+                    let path = e.path().unwrap_or(&self.dir).to_path_buf();
+                    skipped.push(match e.io_error() {
+                        Some(io) => SkippedFile::from_io(path, io),
+                        None => SkippedFile::new(path, SkipReason::Unreadable, e.to_string()),
+                    });
+                }
+            }
+        }
+        Ok((files, skipped))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::temp_dir;
+
+    #[test]
+    fn list_files_applies_patterns_and_depth() {
+        let dir = temp_dir("sources");
+        for sub in ["2023/trip", ".thumbnails", "raw"] {
+            std::fs::create_dir_all(dir.join(sub)).unwrap();
+        }
+        for file in [
+            "top.JPG",
+            "2023/beach.jpg",
+            "2023/trip/hike.jpg",
+            "2023/trip/notes.txt",
+            ".thumbnails/top.jpg",
+            "raw/top.jpg",
+        ] {
+            std::fs::write(dir.join(file), b"x").unwrap();
+        }
+        let list = |source: &TileSource| {
+            let (mut files, skipped) = source.list_files().unwrap();
+            assert!(skipped.is_empty());
+            files.sort();
+            files
+                .iter()
+                .map(|f| {
+                    f.strip_prefix(&dir)
+                        .unwrap()
+                        .to_string_lossy()
+                        .replace('\\', "/")
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let source = TileSource {
+            include: vec!["**/*.jpg".into()],
+            exclude: vec!["**/.*".into(), "raw".into()],
+            ..TileSource::new(&dir)
+        };
+        assert_eq!(
+            list(&source),
+            vec!["2023/beach.jpg", "2023/trip/hike.jpg", "top.JPG"]
+        );
+        assert_eq!(
+            list(&TileSource {
+                max_depth: Some(2),
+                ..source.clone()
+            }),
+            vec!["2023/beach.jpg", "top.JPG"]
+        );
+        assert_eq!(list(&TileSource::new(&dir)).len(), 6);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn validate_rejects_bad_patterns_and_zero_depth() {
+        let source = TileSource {
+            exclude: vec!["[unclosed".into()],
+            ..TileSource::new("/photos")
+        };
+        assert!(matches!(
+            source.validate(),
+            Err(AppError::Config(message)) if message.contains("exclude pattern")
+        ));
+        assert!(TileSource {
+            max_depth: Some(0),
+            ..TileSource::new("/photos")
+        }
+        .validate()
+        .is_err());
+        assert!(TileSource::new("/photos").validate().is_ok());
+    }
+}
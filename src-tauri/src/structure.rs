@@ -0,0 +1,139 @@
+use crate::layout::CellShape;
+use image::{imageops::FilterType, DynamicImage};
+
+/// Side length of the luma thumbnail used for structural comparison.
+pub const STRUCTURE_SIZE: u32 = 8;
+
+/// Side length of the SSIM windows within the thumbnail.
+const SSIM_WINDOW: usize = 4;
+
+// Standard SSIM stabilizers for 8-bit dynamic range
+const SSIM_C1: f64 = (0.01 * 255.0) * (0.01 * 255.0);
+const SSIM_C2: f64 = (0.03 * 255.0) * (0.03 * 255.0);
+
+/// Downsamples an image to a row-major luma thumbnail for SSIM scoring.
+#[must_use]
+pub fn luma_thumbnail(img: &DynamicImage) -> Vec<f64> {
+    img.resize_exact(STRUCTURE_SIZE, STRUCTURE_SIZE, FilterType::Triangle)
+        .to_luma8()
+        .pixels()
+        .map(|p| p.0[0] as f64)
+        .collect()
+}
+
+/// Which pixels of a luma thumbnail lie inside `shape`, row-major. Pixels
+/// outside it are clipped away when the tile is drawn.
+#[must_use]
+pub fn structure_mask(shape: CellShape) -> Vec<bool> {
+    (0..STRUCTURE_SIZE)
+        .flat_map(|y| (0..STRUCTURE_SIZE).map(move |x| shape.contains(x, y, STRUCTURE_SIZE)))
+        .collect()
+}
+
+/// Mean SSIM over non-overlapping windows of two luma thumbnails, counting
+/// only the pixels set in `mask`. Windows entirely outside it are skipped.
+/// Returns a value in -1..=1 where 1 means structurally identical.
+#[must_use]
+pub fn ssim(a: &[f64], b: &[f64], mask: &[bool]) -> f64 {
+    let size = STRUCTURE_SIZE as usize;
+    let windows_per_side = size / SSIM_WINDOW;
+    let mut total = 0.0;
+    let mut windows = 0;
+
+    for wy in 0..windows_per_side {
+        for wx in 0..windows_per_side {
+            let indices = (0..SSIM_WINDOW)
+                .flat_map(|dy| {
+                    let row = (wy * SSIM_WINDOW + dy) * size + wx * SSIM_WINDOW;
+                    row..row + SSIM_WINDOW
+                })
+                .filter(|&i| mask[i]);
+            let n = indices.clone().count() as f64;
+            if n == 0.0 {
+                continue;
+            }
+
+            let (mut sum_a, mut sum_b) = (0.0, 0.0);
+            for i in indices.clone() {
+                sum_a += a[i];
+                sum_b += b[i];
+            }
+            let (mean_a, mean_b) = (sum_a / n, sum_b / n);
+
+            let (mut var_a, mut var_b, mut cov) = (0.0, 0.0, 0.0);
+            for i in indices {
+                let da = a[i] - mean_a;
+                let db = b[i] - mean_b;
+                var_a += da * da;
+                var_b += db * db;
+                cov += da * db;
+            }
+            var_a /= n;
+            var_b /= n;
+            cov /= n;
+
+            total += ((2.0 * mean_a * mean_b + SSIM_C1) * (2.0 * cov + SSIM_C2))
+                / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2));
+            windows += 1;
+        }
+    }
+
+    total / f64::from(windows.max(1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::{ImageBuffer, Luma};
+
+    fn gradient(horizontal: bool) -> Vec<f64> {
+        let img = DynamicImage::ImageLuma8(ImageBuffer::from_fn(32, 32, |x, y| {
+            let t = if horizontal { x } else { y };
+            Luma([(t * 8) as u8])
+        }));
+        luma_thumbnail(&img)
+    }
+
+    #[test]
+    fn luma_thumbnail_has_fixed_size() {
+        assert_eq!(
+            gradient(true).len(),
+            (STRUCTURE_SIZE * STRUCTURE_SIZE) as usize
+        );
+    }
+
+    #[test]
+    fn ssim_prefers_matching_structure() {
+        let horizontal = gradient(true);
+        let vertical = gradient(false);
+
+        let square = structure_mask(CellShape::Square);
+
+        assert!((ssim(&horizontal, &horizontal, &square) - 1.0).abs() < 1e-9);
+        assert!(ssim(&horizontal, &vertical, &square) < ssim(&horizontal, &horizontal, &square));
+    }
+
+    #[test]
+    fn ssim_ignores_pixels_clipped_away_by_the_shape() {
+        let hexagon = structure_mask(CellShape::Hexagon);
+        assert!(structure_mask(CellShape::Brick)
+            .iter()
+            .all(|&inside| inside));
+        assert!(!hexagon[0] && hexagon[3] && hexagon[8 * 4]);
+
+        // Bright corners only differ where a hexagon tile is transparent
+        let plain = gradient(true);
+        let mut cornered = plain.clone();
+        for (value, _) in cornered
+            .iter_mut()
+            .zip(&hexagon)
+            .filter(|(_, &inside)| !inside)
+        {
+            *value = 255.0;
+        }
+
+        assert!((ssim(&plain, &cornered, &hexagon) - 1.0).abs() < 1e-9);
+        let square = structure_mask(CellShape::Square);
+        assert!(ssim(&plain, &cornered, &square) < 0.99);
+    }
+}
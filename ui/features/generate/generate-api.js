@@ -2,15 +2,49 @@ import { invokeCommand } from '../../shared/api/tauri-client.js';
 
 export function createGenerateApi(client = { invokeCommand }) {
     return {
-        async getAdaptiveSettings({ targetPath, tileDir }) {
+        async getAdaptiveSettings({ targetPath, tileDir, tileFormats, tileSources }) {
             return client.invokeCommand('get_adaptive_settings', {
                 target_image_path: targetPath,
-                tile_directory: tileDir
+                tile_directory: tileDir,
+                ...(tileFormats?.length ? { tile_formats: tileFormats } : {}),
+                ...(tileSources?.length ? { tile_sources: tileSources } : {})
             });
         },
 
+        async getSupportedFormats() {
+            return client.invokeCommand('get_supported_formats', {});
+        },
+
         async generateMosaic(params) {
             return client.invokeCommand('generate_mosaic', { params });
+        },
+
+        async saveMosaic(params, output) {
+            return client.invokeCommand('save_mosaic', { params, output });
+        },
+
+        async exportDeepZoom(params, output) {
+            return client.invokeCommand('export_deep_zoom', { params, output });
+        },
+
+        async exportManifest(params, output) {
+            return client.invokeCommand('export_manifest', { params, output });
+        },
+
+        async analyzeCoverage(params) {
+            return client.invokeCommand('analyze_coverage', { params });
+        },
+
+        async rescanLibrary() {
+            return client.invokeCommand('rescan_library', {});
+        },
+
+        async getLoadReport() {
+            return client.invokeCommand('get_load_report', {});
+        },
+
+        async watchLibrary(directories) {
+            return client.invokeCommand('watch_library', { directories });
         }
     };
 }
@@ -27,6 +27,30 @@ test('getAdaptiveSettings maps camelCase inputs to command payload', async () =>
     ]);
 });
 
+test('getAdaptiveSettings forwards tile formats and sources when given', async () => {
+    const calls = [];
+    const api = createGenerateApi({
+        async invokeCommand(command, payload) {
+            calls.push({ command, payload });
+            return { ok: true };
+        }
+    });
+
+    await api.getAdaptiveSettings({
+        targetPath: '/tmp/target.png',
+        tileDir: '/tmp/tiles',
+        tileFormats: ['webp', 'avif'],
+        tileSources: [{ dir: '/tmp/tiles' }, { dir: '/tmp/phone' }]
+    });
+
+    assert.deepEqual(calls[0].payload, {
+        target_image_path: '/tmp/target.png',
+        tile_directory: '/tmp/tiles',
+        tile_formats: ['webp', 'avif'],
+        tile_sources: [{ dir: '/tmp/tiles' }, { dir: '/tmp/phone' }]
+    });
+});
+
 test('generateMosaic wraps params under params key', async () => {
     const calls = [];
     const api = createGenerateApi({
@@ -65,3 +89,102 @@ test('generate API propagates transport errors', async () => {
         /boom/
     );
 });
+
+test('saveMosaic sends params and output destination', async () => {
+    const calls = [];
+    const api = createGenerateApi({
+        async invokeCommand(command, payload) {
+            calls.push({ command, payload });
+            return { path: '/tmp/out.jpg', file_size: 1024 };
+        }
+    });
+
+    const params = { target_image_path: '/tmp/target.png', tile_directory: '/tmp/tiles' };
+    const output = { path: '/tmp/out.jpg', format: 'jpeg', jpeg_quality: 85 };
+    const saved = await api.saveMosaic(params, output);
+
+    assert.deepEqual(calls, [{ command: 'save_mosaic', payload: { params, output } }]);
+    assert.equal(saved.file_size, 1024);
+});
+
+test('exportDeepZoom sends params and descriptor path', async () => {
+    const calls = [];
+    const api = createGenerateApi({
+        async invokeCommand(command, payload) {
+            calls.push({ command, payload });
+            return { path: '/tmp/mosaic.dzi', levels: 14, tile_count: 354 };
+        }
+    });
+
+    const params = { target_image_path: '/tmp/target.png', tile_directory: '/tmp/tiles' };
+    const output = { path: '/tmp/mosaic.dzi', jpeg_quality: 85 };
+    const exported = await api.exportDeepZoom(params, output);
+
+    assert.deepEqual(calls, [{ command: 'export_deep_zoom', payload: { params, output } }]);
+    assert.equal(exported.tile_count, 354);
+});
+
+test('exportManifest sends params and manifest format', async () => {
+    const calls = [];
+    const api = createGenerateApi({
+        async invokeCommand(command, payload) {
+            calls.push({ command, payload });
+            return { path: '/tmp/placements.csv', placement_count: 12 };
+        }
+    });
+
+    const params = { target_image_path: '/tmp/target.png', tile_directory: '/tmp/tiles' };
+    const output = { path: '/tmp/placements.csv', format: 'csv' };
+    const saved = await api.exportManifest(params, output);
+
+    assert.deepEqual(calls, [{ command: 'export_manifest', payload: { params, output } }]);
+    assert.equal(saved.placement_count, 12);
+});
+
+test('watchLibrary sends the folders, or none to stop watching', async () => {
+    const calls = [];
+    const api = createGenerateApi({
+        async invokeCommand(command, payload) {
+            calls.push({ command, payload });
+        }
+    });
+
+    await api.watchLibrary(['/tmp/tiles', '/tmp/phone']);
+    await api.watchLibrary([]);
+
+    assert.deepEqual(calls, [
+        { command: 'watch_library', payload: { directories: ['/tmp/tiles', '/tmp/phone'] } },
+        { command: 'watch_library', payload: { directories: [] } }
+    ]);
+});
+
+test('getLoadReport returns the skipped files of the loaded library', async () => {
+    const calls = [];
+    const api = createGenerateApi({
+        async invokeCommand(command, payload) {
+            calls.push({ command, payload });
+            return { loaded: 4, skipped: [{ path: '/tmp/tiles/bad.png', reason: 'decode_error', detail: '' }] };
+        }
+    });
+
+    const report = await api.getLoadReport();
+
+    assert.deepEqual(calls, [{ command: 'get_load_report', payload: {} }]);
+    assert.equal(report.skipped.length, 1);
+});
+
+test('analyzeCoverage sends the mosaic params', async () => {
+    const calls = [];
+    const api = createGenerateApi({
+        async invokeCommand(command, payload) {
+            calls.push({ command, payload });
+            return { coverage_score: 0.8, bins: [], under_covered: [] };
+        }
+    });
+
+    const params = { target_image_path: '/tmp/target.png', tile_directory: '/tmp/tiles' };
+    const report = await api.analyzeCoverage(params);
+
+    assert.deepEqual(calls, [{ command: 'analyze_coverage', payload: { params } }]);
+    assert.equal(report.coverage_score, 0.8);
+});
@@ -0,0 +1,21 @@
+/**
+ * Formats the constraints the backend could not satisfy as a short sentence.
+ * Returns an empty string when every constraint was met.
+ */
+export function describeUnmetConstraints(constraints = []) {
+    return constraints
+        .map((constraint) => {
+            switch (constraint.kind) {
+                case 'usage_cap_exceeded':
+                    return `${constraint.cells} cells exceeded the ${constraint.cap}-use limit`;
+                case 'tiles_unused':
+                    return `${constraint.unused} tiles could not be placed in ${constraint.cells} cells`;
+                case 'repeat_distance_relaxed':
+                    return `${constraint.cells} cells repeat a tile within the repeat distance`;
+                default:
+                    return null;
+            }
+        })
+        .filter(Boolean)
+        .join('; ');
+}
@@ -0,0 +1,20 @@
+import assert from 'node:assert/strict';
+import test from 'node:test';
+import { describeUnmetConstraints } from './unmet-constraints.js';
+
+test('describeUnmetConstraints is empty when all constraints are met', () => {
+    assert.equal(describeUnmetConstraints([]), '');
+    assert.equal(describeUnmetConstraints(undefined), '');
+});
+
+test('describeUnmetConstraints joins each unmet constraint', () => {
+    const text = describeUnmetConstraints([
+        { kind: 'usage_cap_exceeded', cap: 2, cells: 5 },
+        { kind: 'tiles_unused', unused: 3, cells: 10 }
+    ]);
+
+    assert.equal(
+        text,
+        '5 cells exceeded the 2-use limit; 3 tiles could not be placed in 10 cells'
+    );
+});
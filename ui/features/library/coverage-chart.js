@@ -0,0 +1,33 @@
+const toHex = (color) => `#${color.map((c) => c.toString(16).padStart(2, '0')).join('')}`;
+
+/**
+ * Rows for the coverage chart: the target's most common color regions with
+ * the library's share of each, scaled so the largest share fills the bar.
+ */
+export function coverageRows(report, limit = 12) {
+    const gaps = new Set(report.under_covered.map((bin) => toHex(bin.color)));
+    const rows = report.bins
+        .filter((bin) => bin.target_share > 0)
+        .sort((a, b) => b.target_share - a.target_share)
+        .slice(0, limit);
+    const largest = Math.max(...rows.flatMap((bin) => [bin.target_share, bin.library_share]), 0);
+    const percent = (share) => (largest > 0 ? Math.round((share / largest) * 100) : 0);
+
+    return rows.map((bin) => ({
+        color: toHex(bin.color),
+        targetWidth: percent(bin.target_share),
+        libraryWidth: percent(bin.library_share),
+        tileCount: bin.tile_count,
+        underCovered: gaps.has(toHex(bin.color))
+    }));
+}
+
+/**
+ * One-line summary of a coverage report.
+ */
+export function describeCoverage(report) {
+    const score = `Color coverage ${Math.round(report.coverage_score * 100)}%`;
+    const gaps = report.under_covered.length;
+    if (gaps === 0) return score;
+    return `${score}, ${gaps} under-covered color${gaps === 1 ? '' : 's'}`;
+}
@@ -0,0 +1,25 @@
+import assert from 'node:assert/strict';
+import test from 'node:test';
+import { coverageRows, describeCoverage } from './coverage-chart.js';
+
+const report = {
+    coverage_score: 0.6,
+    bins: [
+        { color: [16, 16, 80], target_share: 0.5, library_share: 0.1, tile_count: 1 },
+        { color: [240, 144, 16], target_share: 0.5, library_share: 0.9, tile_count: 9 },
+        { color: [240, 240, 240], target_share: 0, library_share: 0, tile_count: 0 }
+    ],
+    under_covered: [{ color: [16, 16, 80], target_share: 0.5, library_share: 0.1, tile_count: 1 }]
+};
+
+test('coverageRows lists target colors scaled to the largest share', () => {
+    assert.deepEqual(coverageRows(report), [
+        { color: '#101050', targetWidth: 56, libraryWidth: 11, tileCount: 1, underCovered: true },
+        { color: '#f09010', targetWidth: 56, libraryWidth: 100, tileCount: 9, underCovered: false }
+    ]);
+});
+
+test('describeCoverage reports the score and the number of gaps', () => {
+    assert.equal(describeCoverage(report), 'Color coverage 60%, 1 under-covered color');
+    assert.equal(describeCoverage({ ...report, under_covered: [] }), 'Color coverage 60%');
+});
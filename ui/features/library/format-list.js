@@ -0,0 +1,10 @@
+/**
+ * Parses a user-entered list such as "jpg, .PNG webp" into format names.
+ * An empty result means every format the backend can decode.
+ */
+export function parseFormatList(text = '') {
+    return text
+        .split(/[\s,;]+/)
+        .map((name) => name.trim().replace(/^\./, '').toLowerCase())
+        .filter(Boolean);
+}
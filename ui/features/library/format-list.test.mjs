@@ -0,0 +1,12 @@
+import assert from 'node:assert/strict';
+import test from 'node:test';
+import { parseFormatList } from './format-list.js';
+
+test('parseFormatList accepts commas, spaces and leading dots', () => {
+    assert.deepEqual(parseFormatList('jpg, .PNG  webp;tiff'), ['jpg', 'png', 'webp', 'tiff']);
+});
+
+test('parseFormatList is empty for blank input', () => {
+    assert.deepEqual(parseFormatList('  '), []);
+    assert.deepEqual(parseFormatList(undefined), []);
+});
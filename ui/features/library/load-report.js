@@ -0,0 +1,51 @@
+const REASON_LABELS = {
+    permission_denied: 'permission denied',
+    unreadable: 'unreadable',
+    decode_error: 'decode error',
+    too_small: 'too small',
+    unsupported: 'unsupported'
+};
+
+const plural = (count, noun) => `${count} ${noun}${count === 1 ? '' : 's'}`;
+
+/**
+ * Summarizes the files skipped or collapsed as near-duplicates while loading
+ * a tile library, and a failed cache write, or returns null when every file
+ * became a tile and the cache was saved.
+ */
+export function describeLoadReport(report) {
+    const skipped = report?.skipped ?? [];
+    const groups = report?.duplicate_groups ?? [];
+    const cacheError = report?.cache_error ?? null;
+    if (skipped.length === 0 && groups.length === 0 && !cacheError) return null;
+
+    const parts = [];
+    if (skipped.length > 0) {
+        const counts = new Map();
+        for (const file of skipped) {
+            counts.set(file.reason, (counts.get(file.reason) ?? 0) + 1);
+        }
+        const breakdown = [...counts]
+            .map(([reason, count]) => `${count} ${REASON_LABELS[reason] ?? reason}`)
+            .join(', ');
+        parts.push(`${plural(skipped.length, 'file')} skipped (${breakdown})`);
+    }
+    if (groups.length > 0) {
+        const collapsed = groups.reduce((sum, group) => sum + group.duplicates.length, 0);
+        parts.push(`${plural(collapsed, 'near-duplicate')} collapsed`);
+    }
+    if (cacheError) parts.push('tile cache not saved');
+
+    return {
+        summary: parts.join('; '),
+        details: [
+            ...skipped.map(
+                (file) => `${file.path}: ${REASON_LABELS[file.reason] ?? file.reason} (${file.detail})`
+            ),
+            ...groups.map(
+                (group) => `${group.representative}: kept over ${group.duplicates.join(', ')}`
+            ),
+            ...(cacheError ? [`Tile cache: ${cacheError}`] : [])
+        ]
+    };
+}
@@ -0,0 +1,50 @@
+import assert from 'node:assert/strict';
+import test from 'node:test';
+import { describeLoadReport } from './load-report.js';
+
+test('describeLoadReport counts skipped files by reason', () => {
+    const described = describeLoadReport({
+        loaded: 40,
+        skipped: [
+            { path: '/tiles/a.png', reason: 'decode_error', detail: 'Failed to load image: unexpected EOF' },
+            { path: '/tiles/b.gif', reason: 'too_small', detail: '1x1 is below the 8px minimum' },
+            { path: '/tiles/c.png', reason: 'decode_error', detail: 'Failed to load image: bad CRC' }
+        ]
+    });
+
+    assert.equal(described.summary, '3 files skipped (2 decode error, 1 too small)');
+    assert.deepEqual(described.details[1], '/tiles/b.gif: too small (1x1 is below the 8px minimum)');
+});
+
+test('describeLoadReport lists collapsed near-duplicates after skipped files', () => {
+    const described = describeLoadReport({
+        loaded: 40,
+        skipped: [{ path: '/tiles/a.png', reason: 'unsupported', detail: 'Avif images cannot be decoded by this build' }],
+        duplicate_groups: [
+            { representative: '/tiles/burst1.jpg', duplicates: ['/tiles/burst2.jpg', '/tiles/burst3.jpg'] },
+            { representative: '/tiles/beach.jpg', duplicates: ['/tiles/beach-edit.jpg'] }
+        ]
+    });
+
+    assert.equal(described.summary, '1 file skipped (1 unsupported); 3 near-duplicates collapsed');
+    assert.equal(described.details.length, 3);
+    assert.equal(described.details[1], '/tiles/burst1.jpg: kept over /tiles/burst2.jpg, /tiles/burst3.jpg');
+});
+
+test('describeLoadReport reports a cache that could not be saved', () => {
+    const described = describeLoadReport({
+        loaded: 40,
+        skipped: [],
+        duplicate_groups: [],
+        cache_error: 'IO Error: Failed to write /cache/tiles.bin: Read-only file system'
+    });
+
+    assert.equal(described.summary, 'tile cache not saved');
+    assert.deepEqual(described.details, [
+        'Tile cache: IO Error: Failed to write /cache/tiles.bin: Read-only file system'
+    ]);
+});
+
+test('describeLoadReport returns null when every file became a tile', () => {
+    assert.equal(describeLoadReport({ loaded: 40, skipped: [], duplicate_groups: [], cache_error: null }), null);
+});
@@ -0,0 +1,15 @@
+/**
+ * Formats a library rescan result as a short status line.
+ */
+export function describeRescan(summary) {
+    const changes = [
+        summary.added && `${summary.added} added`,
+        summary.removed && `${summary.removed} removed`,
+        summary.modified && `${summary.modified} modified`
+    ].filter(Boolean);
+
+    if (changes.length === 0) {
+        return `Tile library unchanged (${summary.tile_count} tiles)`;
+    }
+    return `Tile library updated: ${changes.join(', ')} (${summary.tile_count} tiles)`;
+}
@@ -0,0 +1,17 @@
+import assert from 'node:assert/strict';
+import test from 'node:test';
+import { describeRescan } from './rescan-summary.js';
+
+test('describeRescan lists only the kinds of change that happened', () => {
+    assert.equal(
+        describeRescan({ added: 3, removed: 0, modified: 1, tile_count: 42 }),
+        'Tile library updated: 3 added, 1 modified (42 tiles)'
+    );
+});
+
+test('describeRescan reports an unchanged library', () => {
+    assert.equal(
+        describeRescan({ added: 0, removed: 0, modified: 0, tile_count: 42 }),
+        'Tile library unchanged (42 tiles)'
+    );
+});
@@ -0,0 +1,28 @@
+/**
+ * Parses a user-entered list such as "*.jpg, 2023/**" into glob patterns.
+ * Unlike format names, patterns keep their case and may contain spaces
+ * inside a path, so only commas and new lines separate them.
+ */
+export function parsePatternList(text = '') {
+    return text
+        .split(/[,\n]+/)
+        .map((pattern) => pattern.trim())
+        .filter(Boolean);
+}
+
+/**
+ * Builds the `tile_sources` sent to the backend: every folder shares the
+ * same patterns, depth limit and symlink setting.
+ */
+export function buildTileSources(dirs, { include = [], exclude = [], maxDepth = null, followSymlinks = false } = {}) {
+    return dirs
+        .filter(Boolean)
+        .filter((dir, index, all) => all.indexOf(dir) === index)
+        .map((dir) => ({
+            dir,
+            include,
+            exclude,
+            max_depth: maxDepth > 0 ? maxDepth : null,
+            follow_symlinks: followSymlinks
+        }));
+}
@@ -0,0 +1,22 @@
+import assert from 'node:assert/strict';
+import test from 'node:test';
+import { buildTileSources, parsePatternList } from './tile-sources.js';
+
+test('parsePatternList splits on commas and new lines and keeps case', () => {
+    assert.deepEqual(parsePatternList('**/*.JPG, My Photos/**\n**/.*'), ['**/*.JPG', 'My Photos/**', '**/.*']);
+    assert.deepEqual(parsePatternList(undefined), []);
+});
+
+test('buildTileSources applies shared options to each distinct folder', () => {
+    const sources = buildTileSources(['/photos', null, '/phone', '/photos'], {
+        exclude: ['**/.*'],
+        maxDepth: 2,
+        followSymlinks: true
+    });
+
+    assert.deepEqual(sources, [
+        { dir: '/photos', include: [], exclude: ['**/.*'], max_depth: 2, follow_symlinks: true },
+        { dir: '/phone', include: [], exclude: ['**/.*'], max_depth: 2, follow_symlinks: true }
+    ]);
+    assert.equal(buildTileSources(['/photos'], { maxDepth: 0 })[0].max_depth, null);
+});
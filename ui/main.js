@@ -1,17 +1,31 @@
 import { convertFileSrc } from '@tauri-apps/api/core';
-import { open } from '@tauri-apps/plugin-dialog';
+import { listen } from '@tauri-apps/api/event';
+import { open, save } from '@tauri-apps/plugin-dialog';
 import { UIManager } from './ui.js';
 import { deriveGenerateUiFlags, transitionGenerateState } from './generate-state.mjs';
 import { generateApi } from './features/generate/generate-api.js';
+import { describeUnmetConstraints } from './features/generate/unmet-constraints.js';
+import { describeRescan } from './features/library/rescan-summary.js';
+import { describeCoverage } from './features/library/coverage-chart.js';
+import { buildTileSources } from './features/library/tile-sources.js';
+
+function formatFileSize(bytes) {
+    if (bytes < 1024) return `${bytes} B`;
+    if (bytes < 1024 * 1024) return `${(bytes / 1024).toFixed(1)} KB`;
+    return `${(bytes / (1024 * 1024)).toFixed(1)} MB`;
+}
 
 function init() {
     const ui = new UIManager();
     const state = {
         targetPath: null,
         tileDir: null,
+        extraTileDirs: [],
         lastMosaicUrl: null,
         targetImageSrc: null,
         overlayEnabled: false,
+        saving: false,
+        imageExtensions: ['png', 'jpg', 'jpeg'],
         generateState: {
             inFlight: false,
             hasPreview: false
@@ -39,7 +53,7 @@ function init() {
         try {
             const selected = await open({
                 multiple: false,
-                filters: [{ name: 'Image', extensions: ['png', 'jpg', 'jpeg'] }]
+                filters: [{ name: 'Image', extensions: state.imageExtensions }]
             });
 
             if (selected) {
@@ -79,6 +93,7 @@ function init() {
                 if (container) {
                     container.classList.add('has-selection');
                 }
+                await updateFolderWatch();
                 validateState();
             }
         } catch (err) {
@@ -87,13 +102,99 @@ function init() {
         }
     }
 
+    async function addTileFolder() {
+        try {
+            const selected = await open({ directory: true });
+            if (selected && selected !== state.tileDir && !state.extraTileDirs.includes(selected)) {
+                state.extraTileDirs = [...state.extraTileDirs, selected];
+                showExtraTileDirs();
+                await updateFolderWatch();
+                validateState();
+            }
+        } catch (err) {
+            console.error('Add folder error:', err);
+            ui.setStatus(`Folder failed: ${err}`, 'error');
+        }
+    }
+
+    async function removeTileFolder(dir) {
+        state.extraTileDirs = state.extraTileDirs.filter((d) => d !== dir);
+        showExtraTileDirs();
+        await updateFolderWatch();
+        validateState();
+    }
+
+    function showExtraTileDirs() {
+        ui.showExtraTileDirs(state.extraTileDirs, removeTileFolder);
+    }
+
+    function tileSources(settings = ui.getSettings()) {
+        return buildTileSources([state.tileDir, ...state.extraTileDirs], {
+            include: settings.tile_include,
+            exclude: settings.tile_exclude,
+            maxDepth: settings.tile_max_depth,
+            followSymlinks: settings.follow_symlinks
+        });
+    }
+
+    // The backend only rescans when the loaded library comes from these folders
+    async function updateFolderWatch() {
+        const watching = document.getElementById('watch-folder-toggle')?.checked;
+        try {
+            const dirs = tileSources().map((source) => source.dir);
+            await generateApi.watchLibrary(watching && state.tileDir ? dirs : []);
+        } catch (err) {
+            console.error('Watch folder error:', err);
+            ui.setStatus(typeof err === 'string' ? err : 'Could not watch folder', 'error');
+        }
+    }
+
+    // Skipped files change whenever the library is loaded or rescanned
+    async function refreshLoadReport() {
+        try {
+            ui.showLoadReport(await generateApi.getLoadReport());
+        } catch (err) {
+            console.error('Load report error:', err);
+        }
+    }
+
+    async function rescanLibrary() {
+        try {
+            const summary = await generateApi.rescanLibrary();
+            ui.setStatus(describeRescan(summary), 'success');
+            refreshLoadReport();
+        } catch (err) {
+            console.error('Rescan error:', err);
+            ui.setStatus(typeof err === 'string' ? err : 'Rescan failed', 'error');
+        }
+    }
+
+    // The backend knows which formats this build decodes
+    generateApi
+        .getSupportedFormats()
+        .then((extensions) => {
+            state.imageExtensions = extensions;
+        })
+        .catch((err) => console.error('Supported formats error:', err));
+
+    document.getElementById('watch-folder-toggle')?.addEventListener('change', updateFolderWatch);
+    document.getElementById('rescan-btn')?.addEventListener('click', rescanLibrary);
+    listen('library-changed', (event) => {
+        ui.setStatus(describeRescan(event.payload), 'info');
+        refreshLoadReport();
+    });
+    listen('library-watch-error', (event) => ui.setStatus(event.payload, 'error'));
+
     async function calculateAdaptiveSettings() {
         if (!state.targetPath || !state.tileDir) return;
         
         try {
+            const settings = ui.getSettings();
             const adaptive = await generateApi.getAdaptiveSettings({
                 targetPath: state.targetPath,
-                tileDir: state.tileDir
+                tileDir: state.tileDir,
+                tileFormats: settings.tile_formats,
+                tileSources: tileSources(settings)
             });
             
             // Update UI with adaptive suggestions
@@ -113,6 +214,42 @@ function init() {
         }
     }
 
+    function buildParams() {
+        const settings = ui.getSettings();
+        return {
+            target_image_path: state.targetPath,
+            tile_directory: state.tileDir,
+            tile_sources: tileSources(settings),
+            tile_size: settings.tile_size,
+            penalty_factor: settings.penalty_factor,
+            sigma_divisor: settings.sigma_divisor,
+            tile_formats: settings.tile_formats,
+            collapse_duplicates: settings.collapse_duplicates,
+            duplicate_hash: settings.duplicate_hash,
+            duplicate_threshold: settings.duplicate_threshold,
+            color_space: settings.color_space,
+            ciede2000_rerank: settings.ciede2000_rerank,
+            descriptor_grid: settings.descriptor_grid,
+            structure_weight: settings.structure_weight,
+            assignment_mode: settings.assignment_mode,
+            min_repeat_distance: settings.min_repeat_distance,
+            max_uses_per_tile: settings.max_uses_per_tile,
+            use_every_tile: settings.use_every_tile,
+            color_correction: settings.color_correction,
+            overlay_opacity: settings.overlay_opacity,
+            overlay_blend: settings.overlay_blend,
+            render_tile_size: settings.render_tile_size,
+            cell_shape: settings.cell_shape,
+            tile_aspect: settings.tile_aspect,
+            orientation_aware: settings.orientation_aware,
+            crop_strategy: settings.crop_strategy,
+            tile_transforms: settings.tile_transforms,
+            quadtree_layout: settings.quadtree_layout,
+            max_cell_span: settings.max_cell_span,
+            detail_threshold: settings.detail_threshold
+        };
+    }
+
     async function generate() {
         if (!canGenerate()) return;
         const started = transitionAndApply('start');
@@ -123,22 +260,21 @@ function init() {
 
         ui.clearStatus();
 
-        const settings = ui.getSettings();
-        const params = {
-            target_image_path: state.targetPath,
-            tile_directory: state.tileDir,
-            tile_size: settings.tile_size,
-            penalty_factor: settings.penalty_factor,
-            sigma_divisor: settings.sigma_divisor
-        };
+        const params = buildParams();
 
         try {
             const output = await generateApi.generateMosaic(params);
-            // output is already a base64 data URL from backend
-            ui.updatePreview(output, state.targetImageSrc, state.overlayEnabled);
-            state.lastMosaicUrl = output; // Store for download
+            // output.image is already a base64 data URL from backend
+            ui.updatePreview(output.image, state.targetImageSrc, state.overlayEnabled);
+            state.lastMosaicUrl = output.image; // Store for download
             transitionAndApply('success');
-            ui.setStatus('Mosaic generated!', 'success');
+            refreshLoadReport();
+            const unmet = describeUnmetConstraints(output.unmet_constraints);
+            if (unmet) {
+                ui.setStatus(`Mosaic generated, but ${unmet}`, 'info');
+            } else {
+                ui.setStatus('Mosaic generated!', 'success');
+            }
         } catch (err) {
             console.error('Generate error:', err);
             transitionAndApply('error');
@@ -149,6 +285,7 @@ function init() {
     // Bind events
     document.getElementById('select-target-btn')?.addEventListener('click', selectTarget);
     document.getElementById('select-tiles-btn')?.addEventListener('click', selectTileFolder);
+    document.getElementById('add-tiles-btn')?.addEventListener('click', addTileFolder);
     document.getElementById('generate-btn')?.addEventListener('click', generate);
 
     // Make target preview clickable to change image
@@ -219,6 +356,112 @@ function init() {
 
     document.getElementById('download-btn')?.addEventListener('click', downloadMosaic);
 
+    const SAVE_FILTERS = {
+        png: { name: 'PNG Image', extensions: ['png'] },
+        jpeg: { name: 'JPEG Image', extensions: ['jpg', 'jpeg'] },
+        webp: { name: 'WebP Image', extensions: ['webp'] },
+        tiff: { name: 'TIFF Image', extensions: ['tif', 'tiff'] }
+    };
+
+    // Renders at full resolution in the backend and writes straight to disk
+    async function saveToDisk() {
+        if (!canGenerate() || state.saving) return;
+
+        const { format, jpeg_quality } = ui.getSaveSettings();
+        const filter = SAVE_FILTERS[format];
+        const path = await save({
+            defaultPath: `mosaic.${filter.extensions[0]}`,
+            filters: [filter]
+        });
+        if (!path) return;
+
+        state.saving = true;
+        ui.setStatus('Saving mosaic...', 'info');
+        try {
+            const saved = await generateApi.saveMosaic(buildParams(), { path, format, jpeg_quality });
+            ui.setStatus(`Saved ${saved.path} (${formatFileSize(saved.file_size)})`, 'success');
+        } catch (err) {
+            console.error('Save error:', err);
+            ui.setStatus(typeof err === 'string' ? err : 'Save failed', 'error');
+        } finally {
+            state.saving = false;
+        }
+    }
+
+    document.getElementById('save-btn')?.addEventListener('click', saveToDisk);
+
+    // Writes a .dzi descriptor plus a <name>_files folder of JPEG tiles
+    async function exportDeepZoom() {
+        if (!canGenerate() || state.saving) return;
+
+        const { jpeg_quality } = ui.getSaveSettings();
+        const path = await save({
+            defaultPath: 'mosaic.dzi',
+            filters: [{ name: 'Deep Zoom Image', extensions: ['dzi'] }]
+        });
+        if (!path) return;
+
+        state.saving = true;
+        ui.setStatus('Exporting Deep Zoom pyramid...', 'info');
+        try {
+            const exported = await generateApi.exportDeepZoom(buildParams(), { path, jpeg_quality });
+            ui.setStatus(`Exported ${exported.path} (${exported.levels} levels, ${exported.tile_count} tiles)`, 'success');
+        } catch (err) {
+            console.error('Deep Zoom export error:', err);
+            ui.setStatus(typeof err === 'string' ? err : 'Deep Zoom export failed', 'error');
+        } finally {
+            state.saving = false;
+        }
+    }
+
+    document.getElementById('deep-zoom-btn')?.addEventListener('click', exportDeepZoom);
+
+    // The chosen file extension picks JSON or CSV
+    async function exportPlacements() {
+        if (!canGenerate() || state.saving) return;
+
+        const path = await save({
+            defaultPath: 'placements.json',
+            filters: [
+                { name: 'JSON', extensions: ['json'] },
+                { name: 'CSV', extensions: ['csv'] }
+            ]
+        });
+        if (!path) return;
+
+        const format = path.toLowerCase().endsWith('.csv') ? 'csv' : 'json';
+        state.saving = true;
+        ui.setStatus('Exporting placements...', 'info');
+        try {
+            const saved = await generateApi.exportManifest(buildParams(), { path, format });
+            ui.setStatus(`Exported ${saved.placement_count} placements to ${saved.path}`, 'success');
+        } catch (err) {
+            console.error('Placement export error:', err);
+            ui.setStatus(typeof err === 'string' ? err : 'Placement export failed', 'error');
+        } finally {
+            state.saving = false;
+        }
+    }
+
+    document.getElementById('manifest-btn')?.addEventListener('click', exportPlacements);
+
+    async function analyzeCoverage() {
+        if (!canGenerate()) return;
+
+        ui.setStatus('Analyzing color coverage...', 'info');
+        try {
+            const report = await generateApi.analyzeCoverage(buildParams());
+            ui.showCoverage(report);
+            ui.setStatus(describeCoverage(report), report.under_covered.length ? 'info' : 'success');
+            refreshLoadReport();
+        } catch (err) {
+            console.error('Coverage error:', err);
+            ui.setStatus(typeof err === 'string' ? err : 'Coverage analysis failed', 'error');
+        }
+    }
+
+    document.getElementById('coverage-btn')?.addEventListener('click', analyzeCoverage);
+
     validateState();
 }
 
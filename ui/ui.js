@@ -1,3 +1,8 @@
+import { parseFormatList } from './features/library/format-list.js';
+import { parsePatternList } from './features/library/tile-sources.js';
+import { describeLoadReport } from './features/library/load-report.js';
+import { coverageRows, describeCoverage } from './features/library/coverage-chart.js';
+
 export class UIManager {
     constructor() {
         this.els = {
@@ -5,16 +10,62 @@ export class UIManager {
             generateBtn: document.getElementById('generate-btn'),
             placeholder: document.getElementById('placeholder'),
             loading: document.getElementById('loading'),
+            colorSpace: document.getElementById('color-space'),
+            ciede2000: document.getElementById('ciede2000-toggle'),
+            descriptorGrid: document.getElementById('descriptor-grid'),
+            assignmentMode: document.getElementById('assignment-mode'),
+            useEveryTile: document.getElementById('use-every-tile-toggle'),
+            blendMode: document.getElementById('blend-mode'),
+            saveFormat: document.getElementById('save-format'),
+            jpegQuality: document.getElementById('jpeg-quality'),
+            tileFormats: document.getElementById('tile-formats'),
+            tileInclude: document.getElementById('tile-include'),
+            tileExclude: document.getElementById('tile-exclude'),
+            tileMaxDepth: document.getElementById('tile-max-depth'),
+            followSymlinks: document.getElementById('follow-symlinks-toggle'),
+            extraTileDirs: document.getElementById('extra-tile-dirs'),
+            collapseDuplicates: document.getElementById('collapse-duplicates-toggle'),
+            duplicateHash: document.getElementById('duplicate-hash'),
+            cellShape: document.getElementById('cell-shape'),
+            tileAspectWidth: document.getElementById('tile-aspect-width'),
+            tileAspectHeight: document.getElementById('tile-aspect-height'),
+            orientationAware: document.getElementById('orientation-aware-toggle'),
+            cropStrategy: document.getElementById('crop-strategy'),
+            tileTransforms: document.getElementById('tile-transforms'),
+            quadtree: document.getElementById('quadtree-toggle'),
+            maxCellSpan: document.getElementById('max-cell-span'),
+            loadReport: document.getElementById('load-report'),
+            loadReportSummary: document.getElementById('load-report-summary'),
+            loadReportList: document.getElementById('load-report-list'),
+            coveragePanel: document.getElementById('coverage-panel'),
+            coverageSummary: document.getElementById('coverage-summary'),
+            coverageChart: document.getElementById('coverage-chart'),
             sliders: {
                 tileSize: document.getElementById('tile-size'),
+                renderTileSize: document.getElementById('render-tile-size'),
                 penalty: document.getElementById('penalty-factor'),
                 sigma: document.getElementById('sigma-divisor'),
+                structure: document.getElementById('structure-weight'),
+                repeatDistance: document.getElementById('repeat-distance'),
+                maxUses: document.getElementById('max-uses'),
+                duplicateThreshold: document.getElementById('duplicate-threshold'),
+                detailThreshold: document.getElementById('detail-threshold'),
+                colorCorrection: document.getElementById('color-correction'),
+                blendOpacity: document.getElementById('blend-opacity'),
                 opacity: document.getElementById('opacity-slider')
             },
             values: {
                 tileSize: document.getElementById('tile-size-value'),
+                renderTileSize: document.getElementById('render-tile-size-value'),
                 penalty: document.getElementById('penalty-factor-value'),
                 sigma: document.getElementById('sigma-divisor-value'),
+                structure: document.getElementById('structure-weight-value'),
+                repeatDistance: document.getElementById('repeat-distance-value'),
+                maxUses: document.getElementById('max-uses-value'),
+                duplicateThreshold: document.getElementById('duplicate-threshold-value'),
+                detailThreshold: document.getElementById('detail-threshold-value'),
+                colorCorrection: document.getElementById('color-correction-value'),
+                blendOpacity: document.getElementById('blend-opacity-value'),
                 opacity: document.getElementById('opacity-value')
             }
         };
@@ -37,7 +88,45 @@ export class UIManager {
         return {
             tile_size: parseInt(this.els.sliders.tileSize?.value || 32),
             penalty_factor: parseFloat(this.els.sliders.penalty?.value || 50),
-            sigma_divisor: parseFloat(this.els.sliders.sigma?.value || 4)
+            sigma_divisor: parseFloat(this.els.sliders.sigma?.value || 4),
+            tile_formats: parseFormatList(this.els.tileFormats?.value),
+            tile_include: parsePatternList(this.els.tileInclude?.value),
+            tile_exclude: parsePatternList(this.els.tileExclude?.value),
+            tile_max_depth: parseInt(this.els.tileMaxDepth?.value || 0),
+            follow_symlinks: Boolean(this.els.followSymlinks?.checked),
+            collapse_duplicates: Boolean(this.els.collapseDuplicates?.checked),
+            duplicate_hash: this.els.duplicateHash?.value || 'difference',
+            duplicate_threshold: parseInt(this.els.sliders.duplicateThreshold?.value || 6),
+            cell_shape: this.els.cellShape?.value || 'square',
+            tile_aspect: {
+                width: parseInt(this.els.tileAspectWidth?.value || 1),
+                height: parseInt(this.els.tileAspectHeight?.value || 1)
+            },
+            orientation_aware: Boolean(this.els.orientationAware?.checked),
+            crop_strategy: this.els.cropStrategy?.value || 'center',
+            tile_transforms: this.els.tileTransforms?.value || 'original',
+            quadtree_layout: Boolean(this.els.quadtree?.checked),
+            max_cell_span: parseInt(this.els.maxCellSpan?.value || 4),
+            detail_threshold: parseFloat(this.els.sliders.detailThreshold?.value || 12),
+            color_space: this.els.colorSpace?.value || 'srgb',
+            ciede2000_rerank: Boolean(this.els.ciede2000?.checked),
+            descriptor_grid: this.els.descriptorGrid?.value || '1x1',
+            structure_weight: parseFloat(this.els.sliders.structure?.value || 0),
+            assignment_mode: this.els.assignmentMode?.value || 'greedy',
+            min_repeat_distance: parseInt(this.els.sliders.repeatDistance?.value || 0),
+            max_uses_per_tile: parseInt(this.els.sliders.maxUses?.value || 0),
+            use_every_tile: Boolean(this.els.useEveryTile?.checked),
+            color_correction: parseFloat(this.els.sliders.colorCorrection?.value || 0),
+            overlay_opacity: parseFloat(this.els.sliders.blendOpacity?.value || 0),
+            overlay_blend: this.els.blendMode?.value || 'normal',
+            render_tile_size: parseInt(this.els.sliders.renderTileSize?.value || 0)
+        };
+    }
+
+    getSaveSettings() {
+        return {
+            format: this.els.saveFormat?.value || 'png',
+            jpeg_quality: parseInt(this.els.jpegQuality?.value || 90)
         };
     }
 
@@ -105,6 +194,77 @@ export class UIManager {
         setTimeout(() => el.remove(), 5000);
     }
     
+    showLoadReport(report) {
+        const { loadReport, loadReportSummary, loadReportList } = this.els;
+        if (!loadReport) return;
+
+        const described = describeLoadReport(report);
+        loadReport.classList.toggle('hidden', !described);
+        if (!described) return;
+
+        loadReportSummary.textContent = described.summary;
+        loadReportList.replaceChildren(
+            ...described.details.map((line) => {
+                const item = document.createElement('li');
+                item.textContent = line;
+                return item;
+            })
+        );
+    }
+
+    showExtraTileDirs(dirs, onRemove) {
+        const list = this.els.extraTileDirs;
+        if (!list) return;
+
+        list.replaceChildren(
+            ...dirs.map((dir) => {
+                const item = document.createElement('li');
+                const name = document.createElement('span');
+                name.className = 'file-path selected';
+                name.textContent = dir.split(/[/\\]/).pop();
+                name.title = dir;
+                const remove = document.createElement('button');
+                remove.className = 'file-btn';
+                remove.textContent = '×';
+                remove.title = 'Stop reading tiles from this folder';
+                remove.addEventListener('click', () => onRemove(dir));
+                item.append(name, remove);
+                return item;
+            })
+        );
+    }
+
+    showCoverage(report) {
+        const { coveragePanel, coverageSummary, coverageChart } = this.els;
+        if (!coveragePanel) return;
+
+        coveragePanel.classList.remove('hidden');
+        coverageSummary.textContent = describeCoverage(report);
+        coverageChart.replaceChildren(
+            ...coverageRows(report).map((row) => {
+                const el = document.createElement('div');
+                el.className = row.underCovered ? 'coverage-row under-covered' : 'coverage-row';
+                el.title = `${row.color}: ${row.tileCount} tiles`;
+
+                const swatch = document.createElement('span');
+                swatch.className = 'coverage-swatch';
+                swatch.style.background = row.color;
+
+                const bars = document.createElement('div');
+                bars.className = 'coverage-bars';
+                for (const [kind, width] of [['target', row.targetWidth], ['library', row.libraryWidth]]) {
+                    const bar = document.createElement('div');
+                    bar.className = `coverage-bar ${kind}`;
+                    bar.style.width = `${width}%`;
+                    bars.appendChild(bar);
+                }
+
+                el.append(swatch, bars);
+                return el;
+            })
+        );
+    }
+
     clearStatus() {
         if (this.els.statusContainer) {
             this.els.statusContainer.innerHTML = '';